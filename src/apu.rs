@@ -1,130 +1,974 @@
-use std::ptr;
+use std::{cell::Cell, collections::VecDeque, io::{self, ErrorKind}, ptr::NonNull};
 
-use crate::nsf::{CNSFCore, CNSFFile};
+use crate::region::Region;
 
+// stereo i16 samples are put out at this rate, both channels are the same
+pub const SAMPLE_RATE: usize = 48000;
+
+// lengths loaded from the top 5 bits of $4003/$4007/$400b/$400f
+const LENGTHS: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14,
+    12, 16, 24, 18, 48, 20, 96, 22, 192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+const DUTIES: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+const TRIANGLE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0,
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15,
+];
+
+// noise and dmc periods in cpu cycles, dendy has the ntsc tables
+const NOISE_NTSC: [u16; 16] = [4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068];
+const NOISE_PAL: [u16; 16] = [4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778];
+const DMC_NTSC: [u16; 16] = [428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54];
+const DMC_PAL: [u16; 16] = [398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50];
+
+// cpu cycles into the frame counter's sequence of each step, the last
+// step wraps back to 0
+const STEPS_NTSC: [[usize; 6]; 2] = [
+    [7457, 14913, 22371, 29828, 29829, 29830],
+    [7457, 14913, 22371, 29829, 37281, 37282],
+];
+const STEPS_PAL: [[usize; 6]; 2] = [
+    [8313, 16627, 24939, 33252, 33253, 33254],
+    [8313, 16627, 24939, 33253, 41565, 41566],
+];
+
+#[derive(Copy, Clone, Default)]
+struct Envelope {
+    start: bool,
+    looping: bool,
+    constant: bool,
+    volume: u8,
+    divider: u8,
+    decay: u8,
+}
+
+impl Envelope {
+    fn write(&mut self, value: u8) {
+        self.looping = (value & 0x20) != 0;
+        self.constant = (value & 0x10) != 0;
+        self.volume = value & 0xf;
+    }
+
+    // quarter frames
+    fn clock(&mut self) {
+        if self.start {
+            self.start = false;
+            self.decay = 15;
+            self.divider = self.volume;
+        } else if self.divider == 0 {
+            self.divider = self.volume;
+            if self.decay > 0 {
+                self.decay -= 1;
+            } else if self.looping {
+                self.decay = 15;
+            }
+        } else {
+            self.divider -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if self.constant { self.volume } else { self.decay }
+    }
+}
+
+#[derive(Copy, Clone, Default)]
+struct Length {
+    enabled: bool,
+    halt: bool,
+    counter: u8,
+}
+
+impl Length {
+    fn load(&mut self, value: u8) {
+        if self.enabled {
+            self.counter = LENGTHS[(value >> 3) as usize];
+        }
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.counter = 0;
+        }
+    }
+
+    // half frames
+    fn clock(&mut self) {
+        if !self.halt && self.counter > 0 {
+            self.counter -= 1;
+        }
+    }
+}
+
+#[derive(Copy, Clone, Default)]
+struct Pulse {
+    // the sweep of pulse 1 negates with ones' complement
+    ones_complement: bool,
+    envelope: Envelope,
+    length: Length,
+    duty: u8,
+    step: u8,
+    period: u16,
+    timer: u16,
+
+    sweep_enabled: bool,
+    sweep_period: u8,
+    sweep_negate: bool,
+    sweep_shift: u8,
+    sweep_divider: u8,
+    sweep_reload: bool,
+}
+
+impl Pulse {
+    fn write_vol(&mut self, value: u8) {
+        self.duty = value >> 6;
+        self.length.halt = (value & 0x20) != 0;
+        self.envelope.write(value);
+    }
+
+    fn write_sweep(&mut self, value: u8) {
+        self.sweep_enabled = (value & 0x80) != 0;
+        self.sweep_period = (value >> 4) & 0x7;
+        self.sweep_negate = (value & 0x08) != 0;
+        self.sweep_shift = value & 0x7;
+        self.sweep_reload = true;
+    }
+
+    fn write_lo(&mut self, value: u8) {
+        self.period = (self.period & 0x700) | value as u16;
+    }
+
+    fn write_hi(&mut self, value: u8) {
+        self.period = (self.period & 0xff) | (((value & 0x7) as u16) << 8);
+        self.length.load(value);
+        self.envelope.start = true;
+        self.step = 0;
+    }
+
+    // the period the sweep is heading for, it mutes the channel when
+    // it's out of range even with the sweep disabled
+    fn target(&self) -> u16 {
+        let change = self.period >> self.sweep_shift;
+        if self.sweep_negate {
+            self.period.saturating_sub(change + self.ones_complement as u16)
+        } else {
+            self.period + change
+        }
+    }
+
+    fn muted(&self) -> bool {
+        self.period < 8 || self.target() > 0x7ff
+    }
+
+    // every other cpu cycle
+    fn clock(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.period;
+            self.step = (self.step + 1) & 0x7;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn clock_sweep(&mut self) {
+        if self.sweep_divider == 0 && self.sweep_enabled && self.sweep_shift > 0 && !self.muted() {
+            self.period = self.target();
+        }
+        if self.sweep_divider == 0 || self.sweep_reload {
+            self.sweep_divider = self.sweep_period;
+            self.sweep_reload = false;
+        } else {
+            self.sweep_divider -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if self.muted() || self.length.counter == 0 || DUTIES[self.duty as usize][self.step as usize] == 0 {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+}
+
+#[derive(Copy, Clone, Default)]
+struct Triangle {
+    length: Length,
+    period: u16,
+    timer: u16,
+    step: u8,
+
+    linear_reload_value: u8,
+    linear_counter: u8,
+    linear_reload: bool,
+}
+
+impl Triangle {
+    fn write_linear(&mut self, value: u8) {
+        // the control flag halts both counters
+        self.length.halt = (value & 0x80) != 0;
+        self.linear_reload_value = value & 0x7f;
+    }
+
+    fn write_lo(&mut self, value: u8) {
+        self.period = (self.period & 0x700) | value as u16;
+    }
+
+    fn write_hi(&mut self, value: u8) {
+        self.period = (self.period & 0xff) | (((value & 0x7) as u16) << 8);
+        self.length.load(value);
+        self.linear_reload = true;
+    }
+
+    // every cpu cycle
+    fn clock(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.period;
+            if self.length.counter > 0 && self.linear_counter > 0 {
+                self.step = (self.step + 1) & 0x1f;
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn clock_linear(&mut self) {
+        if self.linear_reload {
+            self.linear_counter = self.linear_reload_value;
+        } else if self.linear_counter > 0 {
+            self.linear_counter -= 1;
+        }
+        if !self.length.halt {
+            self.linear_reload = false;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        TRIANGLE[self.step as usize]
+    }
+}
+
+#[derive(Copy, Clone)]
+struct Noise {
+    envelope: Envelope,
+    length: Length,
+    // short mode taps bit 6 instead of bit 1
+    short: bool,
+    period: u8,
+    timer: u16,
+    shift: u16,
+}
+
+impl Noise {
+    fn write_vol(&mut self, value: u8) {
+        self.length.halt = (value & 0x20) != 0;
+        self.envelope.write(value);
+    }
+
+    fn write_lo(&mut self, value: u8) {
+        self.short = (value & 0x80) != 0;
+        self.period = value & 0xf;
+    }
+
+    fn write_hi(&mut self, value: u8) {
+        self.length.load(value);
+        self.envelope.start = true;
+    }
+
+    // every cpu cycle
+    fn clock(&mut self, region: Region) {
+        if self.timer == 0 {
+            let periods = if region == Region::Pal { &NOISE_PAL } else { &NOISE_NTSC };
+            self.timer = periods[self.period as usize] - 1;
+            let tap = if self.short { 6 } else { 1 };
+            let feedback = (self.shift ^ (self.shift >> tap)) & 1;
+            self.shift = (self.shift >> 1) | (feedback << 14);
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if (self.shift & 1) != 0 || self.length.counter == 0 {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+}
+
+impl Default for Noise {
+    fn default() -> Self {
+        Self {
+            envelope: Envelope::default(),
+            length: Length::default(),
+            short: false,
+            period: 0,
+            timer: 0,
+            shift: 1,
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+struct Dmc {
+    irq_enabled: bool,
+    looping: bool,
+    rate: u8,
+    timer: u16,
+    level: u8,
+
+    // from $4012 and $4013
+    sample_addr: u16,
+    sample_len: u16,
+    // the sample being played
+    addr: u16,
+    remaining: u16,
+
+    // the byte fetched by the last dma, and the one being played
+    buffer: Option<u8>,
+    shift: u8,
+    bits: u8,
+    silence: bool,
+
+    irq: bool,
+}
+
+impl Default for Dmc {
+    fn default() -> Self {
+        Self {
+            irq_enabled: false,
+            looping: false,
+            rate: 0,
+            timer: DMC_NTSC[0] - 1,
+            level: 0,
+            sample_addr: 0xc000,
+            sample_len: 1,
+            addr: 0xc000,
+            remaining: 0,
+            buffer: None,
+            shift: 0,
+            bits: 8,
+            silence: true,
+            irq: false,
+        }
+    }
+}
+
+impl Dmc {
+    fn write_freq(&mut self, value: u8) {
+        self.irq_enabled = (value & 0x80) != 0;
+        if !self.irq_enabled {
+            self.irq = false;
+        }
+        self.looping = (value & 0x40) != 0;
+        self.rate = value & 0xf;
+    }
+
+    fn restart(&mut self) {
+        self.addr = self.sample_addr;
+        self.remaining = self.sample_len;
+    }
+
+    // the address of the next byte when the buffer needs filling
+    fn dma(&self) -> Option<u16> {
+        (self.buffer.is_none() && self.remaining > 0).then_some(self.addr)
+    }
+
+    fn fill(&mut self, value: u8) {
+        // nothing was asked for
+        if self.remaining == 0 {
+            return;
+        }
+        self.buffer = Some(value);
+        // addresses wrap around to $8000
+        self.addr = self.addr.wrapping_add(1) | 0x8000;
+        self.remaining -= 1;
+        if self.remaining == 0 {
+            if self.looping {
+                self.restart();
+            } else if self.irq_enabled {
+                self.irq = true;
+            }
+        }
+    }
+
+    // every cpu cycle
+    fn clock(&mut self, region: Region) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        let rates = if region == Region::Pal { &DMC_PAL } else { &DMC_NTSC };
+        self.timer = rates[self.rate as usize] - 1;
+
+        if !self.silence {
+            if (self.shift & 1) != 0 {
+                if self.level <= 125 {
+                    self.level += 2;
+                }
+            } else if self.level >= 2 {
+                self.level -= 2;
+            }
+        }
+        self.shift >>= 1;
+        self.bits -= 1;
+        if self.bits == 0 {
+            self.bits = 8;
+            match self.buffer.take() {
+                Some(value) => {
+                    self.silence = false;
+                    self.shift = value;
+                },
+                None => self.silence = true,
+            }
+        }
+    }
+}
+
+// the 2a03's sound, run a cpu cycle at a time to catch up with the cpu
+// whenever it's touched
 pub struct Apu {
-    // nsf core
-    nsf: Box<CNSFCore>,
+    pulse1: Pulse,
+    pulse2: Pulse,
+    triangle: Triangle,
+    noise: Noise,
+    dmc: Dmc,
+
+    // frame counter
+    five_step: bool,
+    irq_inhibit: bool,
+    frame_irq: bool,
+    frame_cycle: usize,
+    // cpu cycles until a write to $4017 restarts the sequence
+    frame_reset: u8,
+    // the value last written to $4017, kept through resets
+    frame_value: u8,
+    // pulses and the frame reset follow apu cycles, every other cpu cycle
+    odd: bool,
+
+    region: Region,
+    cycles: NonNull<Cell<usize>>,
+    // the cycle the apu has been run up to
+    last: usize,
+
+    // nonlinear mixer, indexed by the sum of the pulses and by
+    // 3 * triangle + 2 * noise + dmc
+    pulse_table: [f32; 31],
+    tnd_table: [f32; 203],
+
+    // the mixer's output averaged over each sample, through a high pass
+    // filter to take out the dc offset
+    sum: f32,
+    count: u32,
+    sample_clock: f64,
+    filter_in: f32,
+    filter_out: f32,
+    samples: VecDeque<i16>,
 }
 
 impl Apu {
-    pub fn new() -> Self {
-        const NSF_DUMMY_CODE: [u8; 3] = [0x4c,0x00,0x80];
+    pub fn new(cycles: NonNull<Cell<usize>>) -> Self {
+        let pulse_table = std::array::from_fn(|n| if n == 0 { 0.0 } else { 95.52 / (8128.0 / n as f32 + 100.0) });
+        let tnd_table = std::array::from_fn(|n| if n == 0 { 0.0 } else { 163.67 / (24329.0 / n as f32 + 100.0) });
+        Self {
+            pulse1: Pulse { ones_complement: true, ..Pulse::default() },
+            pulse2: Pulse::default(),
+            triangle: Triangle::default(),
+            noise: Noise::default(),
+            dmc: Dmc::default(),
+            five_step: false,
+            irq_inhibit: false,
+            frame_irq: false,
+            frame_cycle: 0,
+            frame_reset: 0,
+            frame_value: 0,
+            odd: false,
+            region: Region::Ntsc,
+            cycles,
+            last: unsafe { cycles.as_ref() }.get(),
+            pulse_table,
+            tnd_table,
+            sum: 0.0,
+            count: 0,
+            sample_clock: 0.0,
+            filter_in: 0.0,
+            filter_out: 0.0,
+            samples: VecDeque::new(),
+        }
+    }
 
-        // create dummy file
-        let mut nsf_dummy_file = CNSFFile::new();
-        nsf_dummy_file.nLoadAddress = 0x8000;
-        nsf_dummy_file.nInitAddress = 0x8000;
-        nsf_dummy_file.nPlayAddress = 0x8000;
+    // the frame counter, clock and noise/dmc periods all follow the region
+    pub fn set_region(&mut self, region: Region) {
+        self.catch_up();
+        self.region = region;
+    }
 
-        nsf_dummy_file.nNTSC_PlaySpeed = 16666;
-        nsf_dummy_file.nTrackCount = 1;
+    // silences everything and restarts the frame counter in the mode it was in
+    pub fn reset(&mut self) {
+        self.catch_up();
+        self.write_snd_chn(0);
+        self.dmc.irq = false;
+        self.write_joy2(self.frame_value);
+    }
 
-        nsf_dummy_file.pDataBuffer = NSF_DUMMY_CODE.as_ptr() as _;
-        nsf_dummy_file.nDataBufferSize = NSF_DUMMY_CODE.len() as _;
+    fn now(&self) -> usize {
+        unsafe { self.cycles.as_ref() }.get()
+    }
 
-        let mut nsf = Box::new(CNSFCore::new());
-        nsf.Initialize();
-        nsf.SetPlaybackOptions(48000, 2);
-        nsf.LoadNSF(&nsf_dummy_file);
-        nsf.SetTrack(0);
-        nsf.SetChannelOptions(0,1, 255, -45, 1);
-        nsf.SetChannelOptions(1, 1, 255, 45, 1);
-        nsf.SetChannelOptions(2, 1, 255, 0, 0);
-        nsf.SetChannelOptions(3, 1, 255, 0, 0);
+    pub fn catch_up(&mut self) {
+        let now = self.now();
+        while self.last < now {
+            self.clock();
+            self.last += 1;
+        }
+    }
 
-        nsf_dummy_file.pDataBuffer = ptr::null_mut();
+    fn clock(&mut self) {
+        self.clock_frame_counter();
 
-        Self {
-            nsf
+        self.triangle.clock();
+        self.noise.clock(self.region);
+        self.dmc.clock(self.region);
+        if self.odd {
+            self.pulse1.clock();
+            self.pulse2.clock();
         }
+        self.odd = !self.odd;
+
+        self.mix();
     }
 
+    fn clock_frame_counter(&mut self) {
+        if self.frame_reset > 0 {
+            self.frame_reset -= 1;
+            if self.frame_reset == 0 {
+                self.frame_cycle = 0;
+                if self.five_step {
+                    self.quarter_frame();
+                    self.half_frame();
+                }
+            }
+        }
+
+        self.frame_cycle += 1;
+        let Some(step) = self.steps().iter().position(|&cycle| cycle == self.frame_cycle) else {
+            return;
+        };
+        match (self.five_step, step) {
+            (_, 0) | (_, 2) => self.quarter_frame(),
+            (_, 1) => {
+                self.quarter_frame();
+                self.half_frame();
+            },
+            (false, 3) | (false, 5) => self.set_frame_irq(),
+            (false, 4) => {
+                self.quarter_frame();
+                self.half_frame();
+                self.set_frame_irq();
+            },
+            (true, 4) => {
+                self.quarter_frame();
+                self.half_frame();
+            },
+            _ => {},
+        }
+        if step == 5 {
+            self.frame_cycle = 0;
+        }
+    }
+
+    // the frame counter's sequence for the region and mode
+    fn steps(&self) -> &'static [usize; 6] {
+        let steps = if self.region == Region::Pal { &STEPS_PAL } else { &STEPS_NTSC };
+        &steps[self.five_step as usize]
+    }
+
+    fn set_frame_irq(&mut self) {
+        if !self.irq_inhibit {
+            self.frame_irq = true;
+        }
+    }
+
+    fn quarter_frame(&mut self) {
+        self.pulse1.envelope.clock();
+        self.pulse2.envelope.clock();
+        self.triangle.clock_linear();
+        self.noise.envelope.clock();
+    }
+
+    fn half_frame(&mut self) {
+        self.pulse1.length.clock();
+        self.pulse2.length.clock();
+        self.triangle.length.clock();
+        self.noise.length.clock();
+        self.pulse1.clock_sweep();
+        self.pulse2.clock_sweep();
+    }
+
+    fn mix(&mut self) {
+        let pulse = self.pulse1.output() + self.pulse2.output();
+        let tnd = 3 * self.triangle.output() as usize + 2 * self.noise.output() as usize + self.dmc.level as usize;
+        self.sum += self.pulse_table[pulse as usize] + self.tnd_table[tnd];
+        self.count += 1;
+
+        self.sample_clock += SAMPLE_RATE as f64;
+        let cpu_clock = self.region.cpu_clock();
+        if self.sample_clock < cpu_clock {
+            return;
+        }
+        self.sample_clock -= cpu_clock;
+
+        let input = self.sum / self.count as f32;
+        self.sum = 0.0;
+        self.count = 0;
+        // about 37 hz
+        const R: f32 = 0.9952;
+        self.filter_out = R * (self.filter_out + input - self.filter_in);
+        self.filter_in = input;
+        self.samples.push_back((self.filter_out * 30000.0).clamp(-32768.0, 32767.0) as i16);
+    }
+
+    // fills buf with interleaved stereo samples, one frame's worth
     pub fn tick(&mut self, buf: &mut [i16]) {
-        let result = (self.nsf.GetSamples(buf.as_mut_ptr() as _, (buf.len()<<1) as _) as usize)>>1;
-        // notsofatso may not fill the entire buffer...
-        // in which case just repeat the last sample
-        let l = buf[result-2];
-        let r = buf[result-1];
-        for i in (result>>1)..(buf.len()>>1) {
-            buf[i<<1] = l;
-            buf[(i<<1)|1] = r;
+        self.catch_up();
+        let count = buf.len() >> 1;
+        let mut last = 0;
+        for i in 0..count {
+            // if there aren't enough, just repeat the last sample
+            last = self.samples.pop_front().unwrap_or(last);
+            buf[i << 1] = last;
+            buf[(i << 1) | 1] = last;
         }
+        // don't let the latency build up
+        if self.samples.len() > count {
+            self.samples.drain(..self.samples.len() - count);
+        }
+    }
+
+    // an irq from the frame counter or the dmc
+    pub fn irq(&mut self) -> bool {
+        self.catch_up();
+        self.frame_irq || self.dmc.irq
+    }
+
+    // the address the dmc wants to read its next byte from, the cpu
+    // does the read and hands it over with fill_dmc
+    pub fn take_dmc_dma(&mut self) -> Option<u16> {
+        self.catch_up();
+        self.dmc.dma()
+    }
+
+    pub(crate) fn fill_dmc(&mut self, value: u8) {
+        self.dmc.fill(value);
     }
 
     pub fn write_sq1_vol(&mut self, value: u8) {
-        self.nsf.WriteMemory_pAPU(0x4000, value);
+        self.catch_up();
+        self.pulse1.write_vol(value);
     }
 
     pub fn write_sq1_sweep(&mut self, value: u8) {
-        self.nsf.WriteMemory_pAPU(0x4001, value);
+        self.catch_up();
+        self.pulse1.write_sweep(value);
     }
 
     pub fn write_sq1_lo(&mut self, value: u8) {
-        self.nsf.WriteMemory_pAPU(0x4002, value);
+        self.catch_up();
+        self.pulse1.write_lo(value);
     }
 
     pub fn write_sq1_hi(&mut self, value: u8) {
-        self.nsf.WriteMemory_pAPU(0x4003, value);
+        self.catch_up();
+        self.pulse1.write_hi(value);
     }
 
     pub fn write_sq2_vol(&mut self, value: u8) {
-        self.nsf.WriteMemory_pAPU(0x4004, value);
+        self.catch_up();
+        self.pulse2.write_vol(value);
     }
 
     pub fn write_sq2_sweep(&mut self, value: u8) {
-        self.nsf.WriteMemory_pAPU(0x4005, value);
+        self.catch_up();
+        self.pulse2.write_sweep(value);
     }
 
     pub fn write_sq2_lo(&mut self, value: u8) {
-        self.nsf.WriteMemory_pAPU(0x4006, value);
+        self.catch_up();
+        self.pulse2.write_lo(value);
     }
 
     pub fn write_sq2_hi(&mut self, value: u8) {
-        self.nsf.WriteMemory_pAPU(0x4007, value);
+        self.catch_up();
+        self.pulse2.write_hi(value);
     }
 
     pub fn write_tri_linear(&mut self, value: u8) {
-        self.nsf.WriteMemory_pAPU(0x4008, value);
+        self.catch_up();
+        self.triangle.write_linear(value);
     }
 
     pub fn write_tri_lo(&mut self, value: u8) {
-        self.nsf.WriteMemory_pAPU(0x400a, value);
+        self.catch_up();
+        self.triangle.write_lo(value);
     }
 
     pub fn write_tri_hi(&mut self, value: u8) {
-        self.nsf.WriteMemory_pAPU(0x400b, value);
+        self.catch_up();
+        self.triangle.write_hi(value);
     }
 
     pub fn write_noise_vol(&mut self, value: u8) {
-        self.nsf.WriteMemory_pAPU(0x400c, value);
+        self.catch_up();
+        self.noise.write_vol(value);
     }
 
     pub fn write_noise_lo(&mut self, value: u8) {
-        self.nsf.WriteMemory_pAPU(0x400e, value);
+        self.catch_up();
+        self.noise.write_lo(value);
     }
 
     pub fn write_noise_hi(&mut self, value: u8) {
-        self.nsf.WriteMemory_pAPU(0x400f, value);
+        self.catch_up();
+        self.noise.write_hi(value);
     }
 
-    pub fn write_dmc_freq(&mut self, value: u8) {}
+    pub fn write_dmc_freq(&mut self, value: u8) {
+        self.catch_up();
+        self.dmc.write_freq(value);
+    }
 
-    pub fn write_dmc_raw(&mut self, value: u8) {}
+    pub fn write_dmc_raw(&mut self, value: u8) {
+        self.catch_up();
+        self.dmc.level = value & 0x7f;
+    }
 
-    pub fn write_dmc_start(&mut self, value: u8) {}
+    // samples start at $c000 + value*64
+    pub fn write_dmc_start(&mut self, value: u8) {
+        self.catch_up();
+        self.dmc.sample_addr = 0xc000 | ((value as u16) << 6);
+    }
 
-    pub fn write_dmc_len(&mut self, value: u8) {}
+    // and are value*16 + 1 bytes long
+    pub fn write_dmc_len(&mut self, value: u8) {
+        self.catch_up();
+        self.dmc.sample_len = ((value as u16) << 4) | 1;
+    }
 
     pub fn read_snd_chn(&mut self) -> u8 {
-        self.nsf.ReadMemory_pAPU(0x4015)
+        self.catch_up();
+        let value = ((self.pulse1.length.counter > 0) as u8)
+            | (((self.pulse2.length.counter > 0) as u8) << 1)
+            | (((self.triangle.length.counter > 0) as u8) << 2)
+            | (((self.noise.length.counter > 0) as u8) << 3)
+            | (((self.dmc.remaining > 0) as u8) << 4)
+            | ((self.frame_irq as u8) << 6)
+            | ((self.dmc.irq as u8) << 7);
+        self.frame_irq = false;
+        value
     }
 
     pub fn write_snd_chn(&mut self, value: u8) {
-        self.nsf.WriteMemory_pAPU(0x4015, value);
+        self.catch_up();
+        self.pulse1.length.set_enabled((value & 0x01) != 0);
+        self.pulse2.length.set_enabled((value & 0x02) != 0);
+        self.triangle.length.set_enabled((value & 0x04) != 0);
+        self.noise.length.set_enabled((value & 0x08) != 0);
+        if (value & 0x10) == 0 {
+            self.dmc.remaining = 0;
+        } else if self.dmc.remaining == 0 {
+            self.dmc.restart();
+        }
+        self.dmc.irq = false;
     }
 
+    // the frame counter
     pub fn write_joy2(&mut self, value: u8) {
-        self.nsf.WriteMemory_pAPU(0x4017, value);
+        self.catch_up();
+        self.frame_value = value;
+        self.five_step = (value & 0x80) != 0;
+        self.irq_inhibit = (value & 0x40) != 0;
+        if self.irq_inhibit {
+            self.frame_irq = false;
+        }
+        // the sequence restarts 3 or 4 cpu cycles later, depending on
+        // where the write lands in the apu cycle
+        self.frame_reset = if self.odd { 4 } else { 3 };
+    }
+
+    // everything but the audio that's been put out, in a format that's
+    // only meant to be loaded back by the same version
+    pub fn save_state(&mut self) -> Vec<u8> {
+        self.catch_up();
+        let mut state = State::Save(Vec::new());
+        self.state(&mut state);
+        match state {
+            State::Save(data) => data,
+            State::Load(..) => unreachable!(),
+        }
+    }
+
+    pub fn load_state(&mut self, data: &[u8]) -> io::Result<()> {
+        // a bad state leaves the apu as it was
+        let backup = self.save_state();
+        let mut state = State::Load(data, true);
+        self.state(&mut state);
+        if !matches!(state, State::Load(rest, true) if rest.is_empty()) {
+            self.state(&mut State::Load(&backup, true));
+            return Err(io::Error::new(ErrorKind::InvalidData, "apu state is the wrong size"));
+        }
+
+        // keep anything used as an index or a shift in range, periods
+        // to 11 bits, samples in prg and the frame counter in its sequence
+        for pulse in [&mut self.pulse1, &mut self.pulse2] {
+            pulse.period &= 0x7ff;
+            pulse.duty &= 0x3;
+            pulse.step &= 0x7;
+            pulse.sweep_period &= 0x7;
+            pulse.sweep_shift &= 0x7;
+        }
+        for envelope in [&mut self.pulse1.envelope, &mut self.pulse2.envelope, &mut self.noise.envelope] {
+            envelope.volume &= 0xf;
+            envelope.decay &= 0xf;
+        }
+        self.triangle.period &= 0x7ff;
+        self.triangle.step &= 0x1f;
+        self.noise.period &= 0xf;
+        self.dmc.rate &= 0xf;
+        self.dmc.level &= 0x7f;
+        self.dmc.sample_addr |= 0x8000;
+        self.dmc.addr |= 0x8000;
+        self.dmc.bits = self.dmc.bits.clamp(1, 8);
+        self.frame_cycle = self.frame_cycle.min(self.steps()[5] - 1);
+        Ok(())
     }
-}
\ No newline at end of file
+
+    // saves or loads every field, in the same order both ways
+    fn state(&mut self, state: &mut State) {
+        for pulse in [&mut self.pulse1, &mut self.pulse2] {
+            state.envelope(&mut pulse.envelope);
+            state.length(&mut pulse.length);
+            state.u8(&mut pulse.duty);
+            state.u8(&mut pulse.step);
+            state.u16(&mut pulse.period);
+            state.u16(&mut pulse.timer);
+            state.bool(&mut pulse.sweep_enabled);
+            state.u8(&mut pulse.sweep_period);
+            state.bool(&mut pulse.sweep_negate);
+            state.u8(&mut pulse.sweep_shift);
+            state.u8(&mut pulse.sweep_divider);
+            state.bool(&mut pulse.sweep_reload);
+        }
+
+        let triangle = &mut self.triangle;
+        state.length(&mut triangle.length);
+        state.u16(&mut triangle.period);
+        state.u16(&mut triangle.timer);
+        state.u8(&mut triangle.step);
+        state.u8(&mut triangle.linear_reload_value);
+        state.u8(&mut triangle.linear_counter);
+        state.bool(&mut triangle.linear_reload);
+
+        let noise = &mut self.noise;
+        state.envelope(&mut noise.envelope);
+        state.length(&mut noise.length);
+        state.bool(&mut noise.short);
+        state.u8(&mut noise.period);
+        state.u16(&mut noise.timer);
+        state.u16(&mut noise.shift);
+
+        let dmc = &mut self.dmc;
+        state.bool(&mut dmc.irq_enabled);
+        state.bool(&mut dmc.looping);
+        state.u8(&mut dmc.rate);
+        state.u16(&mut dmc.timer);
+        state.u8(&mut dmc.level);
+        state.u16(&mut dmc.sample_addr);
+        state.u16(&mut dmc.sample_len);
+        state.u16(&mut dmc.addr);
+        state.u16(&mut dmc.remaining);
+        let mut has_buffer = dmc.buffer.is_some();
+        let mut buffer = dmc.buffer.unwrap_or(0);
+        state.bool(&mut has_buffer);
+        state.u8(&mut buffer);
+        dmc.buffer = has_buffer.then_some(buffer);
+        state.u8(&mut dmc.shift);
+        state.u8(&mut dmc.bits);
+        state.bool(&mut dmc.silence);
+        state.bool(&mut dmc.irq);
+
+        state.bool(&mut self.five_step);
+        state.bool(&mut self.irq_inhibit);
+        state.bool(&mut self.frame_irq);
+        let mut frame_cycle = self.frame_cycle as u16;
+        state.u16(&mut frame_cycle);
+        self.frame_cycle = frame_cycle as usize;
+        state.u8(&mut self.frame_reset);
+        state.u8(&mut self.frame_value);
+        state.bool(&mut self.odd);
+    }
+}
+
+// the bytes saved so far, or the bytes left to load and whether
+// they've all been there
+enum State<'a> {
+    Save(Vec<u8>),
+    Load(&'a [u8], bool),
+}
+
+impl State<'_> {
+    fn bytes<const N: usize>(&mut self, value: [u8; N]) -> [u8; N] {
+        match self {
+            State::Save(data) => {
+                data.extend_from_slice(&value);
+                value
+            },
+            State::Load(data, ok) => match data.split_first_chunk::<N>() {
+                Some((bytes, rest)) => {
+                    *data = rest;
+                    *bytes
+                },
+                None => {
+                    *ok = false;
+                    value
+                },
+            },
+        }
+    }
+
+    fn u8(&mut self, value: &mut u8) {
+        *value = self.bytes([*value])[0];
+    }
+
+    fn u16(&mut self, value: &mut u16) {
+        *value = u16::from_le_bytes(self.bytes(value.to_le_bytes()));
+    }
+
+    fn bool(&mut self, value: &mut bool) {
+        *value = self.bytes([*value as u8])[0] != 0;
+    }
+
+    fn envelope(&mut self, envelope: &mut Envelope) {
+        self.bool(&mut envelope.start);
+        self.bool(&mut envelope.looping);
+        self.bool(&mut envelope.constant);
+        self.u8(&mut envelope.volume);
+        self.u8(&mut envelope.divider);
+        self.u8(&mut envelope.decay);
+    }
+
+    fn length(&mut self, length: &mut Length) {
+        self.bool(&mut length.enabled);
+        self.bool(&mut length.halt);
+        self.u8(&mut length.counter);
+    }
+}
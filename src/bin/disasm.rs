@@ -0,0 +1,93 @@
+use std::{env, fs, process::ExitCode};
+
+use nes::{disasm, mem::Mem};
+
+// prg rom mapped at $8000-$ffff (16 kb roms are mirrored)
+struct Prg {
+    prg: Vec<u8>,
+}
+
+impl Mem for Prg {
+    fn read(&mut self, addr: u16) -> u8 {
+        self.peek(addr)
+    }
+    fn write(&mut self, _addr: u16, _value: u8) {}
+    fn peek(&self, addr: u16) -> u8 {
+        if addr < 0x8000 {
+            0
+        } else {
+            self.prg[(addr as usize - 0x8000) % self.prg.len()]
+        }
+    }
+    fn poke(&mut self, _addr: u16, _value: u8) {}
+}
+
+fn parse_addr(s: &str) -> Option<u16> {
+    let s = s.trim_start_matches('$').trim_start_matches("0x");
+    u16::from_str_radix(s, 16).ok()
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 || args.len() > 4 {
+        eprintln!("usage: {} <rom.nes> [start] [end]", args[0]);
+        return ExitCode::FAILURE;
+    }
+
+    let game = match fs::read(&args[1]) {
+        Ok(game) => game,
+        Err(e) => {
+            eprintln!("{}: {}", args[1], e);
+            return ExitCode::FAILURE;
+        },
+    };
+    if game.len() < 0x10 || &game[..4] != b"NES\x1a" {
+        eprintln!("{}: not an ines file", args[1]);
+        return ExitCode::FAILURE;
+    }
+    let prg_len = (game[4] as usize) << 14;
+    let trainer = if (game[6] & 0x04) != 0 { 0x200 } else { 0 };
+    let prg_start = 0x10 + trainer;
+    if prg_len == 0 || game.len() < prg_start + prg_len {
+        eprintln!("{}: truncated prg rom", args[1]);
+        return ExitCode::FAILURE;
+    }
+    // only the last 32 kb is visible without a mapper
+    let prg_start = prg_start + prg_len.saturating_sub(0x8000);
+    let prg_len = prg_len.min(0x8000);
+    let prg = Prg {
+        prg: game[prg_start..prg_start + prg_len].to_vec(),
+    };
+
+    // default to the reset vector through the end of the address space
+    let start = match args.get(2) {
+        Some(s) => match parse_addr(s) {
+            Some(addr) => addr,
+            None => {
+                eprintln!("invalid start address: {}", s);
+                return ExitCode::FAILURE;
+            },
+        },
+        None => prg.peek16(0xfffc),
+    };
+    let end = match args.get(3) {
+        Some(s) => match parse_addr(s) {
+            Some(addr) => addr,
+            None => {
+                eprintln!("invalid end address: {}", s);
+                return ExitCode::FAILURE;
+            },
+        },
+        None => 0xffff,
+    };
+
+    let mut addr = start as u32;
+    while addr <= end as u32 {
+        let instr = disasm::disassemble(&prg, addr as u16);
+        let bytes: Vec<String> = instr.bytes().iter().map(|b| format!("{:02X}", b)).collect();
+        println!("{:04X}  {:<8}  {}{}", instr.addr, bytes.join(" "), if instr.official { ' ' } else { '*' }, instr);
+        addr += instr.size() as u32;
+    }
+
+    ExitCode::SUCCESS
+}
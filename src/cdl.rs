@@ -0,0 +1,122 @@
+use std::io::{self, ErrorKind};
+
+use crate::disasm::{Mode, OPCODES};
+
+// fceux compatible code/data log
+// the file is one byte per prg rom byte followed by one byte per chr rom byte
+
+// prg flags
+pub const CODE: u8 = 0x01;
+pub const DATA: u8 = 0x02;
+// which 8 kb window of $8000-$ffff the byte was last accessed through
+pub const BANK_MASK: u8 = 0x0c;
+pub const INDIRECT_CODE: u8 = 0x10;
+pub const INDIRECT_DATA: u8 = 0x20;
+pub const PCM: u8 = 0x40;
+
+// chr flags
+pub const RENDERED: u8 = 0x01;
+pub const READ: u8 = 0x02;
+
+pub struct Cdl {
+    prg: Box<[u8]>,
+    chr: Box<[u8]>,
+
+    // bytes of the instruction being executed, reads from here are operands
+    instr_start: u16,
+    instr_len: u16,
+    indirect_data: bool,
+    indirect_jump: bool,
+}
+
+impl Cdl {
+    // chr_len is 0 for chr ram, which isn't logged
+    pub fn new(prg_len: usize, chr_len: usize) -> Self {
+        Self {
+            prg: vec![0; prg_len].into_boxed_slice(),
+            chr: vec![0; chr_len].into_boxed_slice(),
+            instr_start: 0,
+            instr_len: 0,
+            indirect_data: false,
+            indirect_jump: false,
+        }
+    }
+
+    pub fn load(data: &[u8], prg_len: usize, chr_len: usize) -> io::Result<Self> {
+        if data.len() != prg_len + chr_len {
+            return Err(io::Error::new(ErrorKind::InvalidData, "cdl file doesn't match the rom size"));
+        }
+        let mut cdl = Self::new(prg_len, chr_len);
+        cdl.prg.copy_from_slice(&data[..prg_len]);
+        cdl.chr.copy_from_slice(&data[prg_len..]);
+        Ok(cdl)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(self.prg.len() + self.chr.len());
+        data.extend_from_slice(&self.prg);
+        data.extend_from_slice(&self.chr);
+        data
+    }
+
+    pub fn prg(&self) -> &[u8] {
+        &self.prg
+    }
+
+    pub fn chr(&self) -> &[u8] {
+        &self.chr
+    }
+
+    #[inline]
+    fn mark_prg(&mut self, addr: u16, flags: u8) {
+        if addr < 0x8000 || self.prg.is_empty() {
+            return;
+        }
+        // 16 kb roms are mirrored
+        let i = (addr as usize - 0x8000) % self.prg.len();
+        let bank = (((addr >> 13) & 0x3) as u8) << 2;
+        self.prg[i] = (self.prg[i] & !BANK_MASK) | flags | bank;
+    }
+
+    // called by the cpu once it has fetched an opcode
+    pub(crate) fn log_instruction(&mut self, pc: u16, opcode: u8) {
+        let op = &OPCODES[opcode as usize];
+        let len = 1 + op.mode.operand_len();
+        let indirect = if self.indirect_jump { INDIRECT_CODE } else { 0 };
+        for i in 0..len {
+            self.mark_prg(pc.wrapping_add(i), CODE | indirect);
+        }
+
+        self.instr_start = pc;
+        self.instr_len = len;
+        self.indirect_data = matches!(op.mode, Mode::IndirectX | Mode::IndirectY);
+        // the next instruction is the target of a jmp ($xxxx)
+        self.indirect_jump = opcode == 0x6c;
+    }
+
+    // called for every cpu read made by the current instruction
+    pub(crate) fn log_read(&mut self, addr: u16) {
+        if addr.wrapping_sub(self.instr_start) < self.instr_len {
+            // operand fetch, already logged as code
+            return;
+        }
+        // pointers are in the zeropage, so any rom read is through them
+        let indirect = if self.indirect_data { INDIRECT_DATA } else { 0 };
+        self.mark_prg(addr, DATA | indirect);
+    }
+
+    // called for every byte of a dmc sample the apu reads
+    pub(crate) fn log_pcm(&mut self, addr: u16) {
+        self.mark_prg(addr, PCM);
+    }
+
+    pub(crate) fn log_chr(&mut self, addr: u16, len: u16, flags: u8) {
+        if self.chr.is_empty() {
+            return;
+        }
+        for i in 0..len {
+            let i = (addr.wrapping_add(i) as usize) % self.chr.len();
+            self.chr[i] |= flags;
+        }
+    }
+}
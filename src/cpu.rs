@@ -1,6 +1,16 @@
 use std::{cell::Cell, ptr::NonNull};
 
-use crate::{mem::Mem, Controller, Memory};
+use crate::{cdl::Cdl, disasm::{Mnemonic, Mode, Opcode, OPCODES}, mem::Mem, profile::{Kind, Profiler}, trace::Tracer, Controller, Memory};
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum CpuVariant {
+    // the nes cpu, decimal mode is disconnected so the d flag
+    // can be set and cleared but adc and sbc ignore it
+    #[default]
+    Ricoh2A03,
+    // a stock nmos 6502 with bcd arithmetic
+    Nmos6502,
+}
 
 pub struct Cpu<C: Controller> {
     pub pc: u16,
@@ -10,9 +20,19 @@ pub struct Cpu<C: Controller> {
     pub sr: u8,
     pub sp: u8,
 
+    pub variant: CpuVariant,
+
     pub mem: NonNull<Memory<C>>,
 
     pub cycles: NonNull<Cell<usize>>,
+
+    pub tracer: Option<Tracer>,
+    pub profiler: Option<Profiler>,
+
+    cdl: Option<NonNull<Cdl>>,
+
+    // set by a jam opcode, only a reset gets the cpu going again
+    jammed: bool,
 }
 
 impl<C: Controller> Cpu<C> {
@@ -24,15 +44,33 @@ impl<C: Controller> Cpu<C> {
             y: 0,
             sr: 0,
             sp: 0,
+            variant: CpuVariant::default(),
             mem,
             cycles,
+            tracer: None,
+            profiler: None,
+            cdl: None,
+            jammed: false,
         };
 
-        cpu.pc = cpu.read16(0xfffc);
-        unsafe { cpu.cycles.as_ref().set(0) };
+        cpu.reset();
         cpu
     }
 
+    pub fn reset(&mut self) {
+        // the reset sequence takes 7 cycles and decrements sp by 3
+        // without writing to the stack
+        self.sp = self.sp.wrapping_sub(3);
+        self.sr |= 0x24;
+        self.pc = self.read16(0xfffc);
+        self.jammed = false;
+        self.add_cycles(5);
+    }
+
+    pub fn jammed(&self) -> bool {
+        self.jammed
+    }
+
     #[inline]
     const fn n(&self) -> bool {
         ((self.sr >> 7) & 1) != 0
@@ -87,13 +125,22 @@ impl<C: Controller> Cpu<C> {
 
     // a: 0, c: 0
     pub fn brk(&mut self) {
-        self.add_cycles(6);
-        panic!("unimplemented: brk")
+        let sp = self.sp;
+
+        // push pc, skipping the padding byte after the opcode
+        self.write16(0x100 | self.sp.wrapping_sub(1) as u16, self.pc.wrapping_add(1));
+        self.sp = self.sp.wrapping_sub(2);
+        // push sr, with b set
+        self.write(0x100 | self.sp as u16, (self.sr & 0xcf) | 0x30);
+        self.sp = self.sp.wrapping_sub(1);
+        self.set_i(true);
+        // shares the irq vector
+        self.pc = self.read16(0xfffe);
+        self.profile_call(Kind::Irq, sp);
     }
     pub fn php(&mut self) {
         self.write(0x100 | self.sp as u16, (self.sr & 0xcf) | 0x30);
         self.sp = self.sp.wrapping_sub(1);
-        self.add_cycles(1);
     }
     pub fn bpl(&mut self, value: u8) {
         if !self.n() {
@@ -105,15 +152,16 @@ impl<C: Controller> Cpu<C> {
     }
     pub fn clc(&mut self) {
         self.set_c(false);
-        self.add_cycles(1);
     }
 
     // a: 1, c: 0
     pub fn jsr(&mut self, value: u16) {
+        let sp = self.sp;
         self.write16(0x100 | self.sp.wrapping_sub(1) as u16, self.pc.wrapping_sub(1));
         self.sp = self.sp.wrapping_sub(2);
         self.pc = value;
         self.add_cycles(1);
+        self.profile_call(Kind::Subroutine, sp);
     }
     pub fn bit(&mut self, value: u8) {
         self.set_n((value as i8) < 0);
@@ -123,7 +171,6 @@ impl<C: Controller> Cpu<C> {
     pub fn plp(&mut self) {
         self.sp = self.sp.wrapping_add(1);
         self.sr = (self.sr & !0xcf) | (self.read(0x100 | self.sp as u16) & 0xcf);
-        self.add_cycles(2);
     }
     pub fn bmi(&mut self, value: u8) {
         if self.n() {
@@ -135,7 +182,6 @@ impl<C: Controller> Cpu<C> {
     }
     pub fn sec(&mut self) {
         self.set_c(true);
-        self.add_cycles(1);
     }
 
     // a: 2, c: 0
@@ -146,11 +192,11 @@ impl<C: Controller> Cpu<C> {
         self.sp = self.sp.wrapping_add(2);
         self.pc = self.read16(0x100 | self.sp.wrapping_sub(1) as u16);
         self.add_cycles(2);
+        self.profile_ret();
     }
     pub fn pha(&mut self) {
         self.write(0x100 | self.sp as u16, self.a);
         self.sp = self.sp.wrapping_sub(1);
-        self.add_cycles(1);
     }
     pub fn jmp(&mut self, value: u16) {
         self.pc = value;
@@ -165,7 +211,6 @@ impl<C: Controller> Cpu<C> {
     }
     pub fn cli(&mut self) {
         self.set_i(false);
-        self.add_cycles(1);
     }
 
     // a: 3, c: 0
@@ -174,6 +219,7 @@ impl<C: Controller> Cpu<C> {
         self.sp = self.sp.wrapping_add(2);
         self.pc = self.read16(0x100 | self.sp.wrapping_sub(1) as u16).wrapping_add(1);
         self.add_cycles(3);
+        self.profile_ret();
     }
     pub fn pla(&mut self) {
         self.sp = self.sp.wrapping_add(1);
@@ -181,7 +227,6 @@ impl<C: Controller> Cpu<C> {
         self.set_n((result as i8) < 0);
         self.set_z(result == 0);
         self.a = result;
-        self.add_cycles(2);
     }
     pub fn bvs(&mut self, value: u8) {
         if self.v() {
@@ -193,7 +238,6 @@ impl<C: Controller> Cpu<C> {
     }
     pub fn sei(&mut self) {
         self.set_i(true);
-        self.add_cycles(1);
     }
 
     // a: 4, c: 0
@@ -205,7 +249,6 @@ impl<C: Controller> Cpu<C> {
         self.set_n((result as i8) < 0);
         self.set_z(result == 0);
         self.y = result;
-        self.add_cycles(1);
     }
     pub fn bcc(&mut self, value: u8) {
         if !self.c() {
@@ -220,7 +263,6 @@ impl<C: Controller> Cpu<C> {
         self.set_n((result as i8) < 0);
         self.set_z(result == 0);
         self.a = result;
-        self.add_cycles(1);
     }
 
     // a: 5, c: 0
@@ -235,7 +277,6 @@ impl<C: Controller> Cpu<C> {
         self.set_n((result as i8) < 0);
         self.set_z(result == 0);
         self.y = result;
-        self.add_cycles(1);
     }
     pub fn bcs(&mut self, value: u8) {
         if self.c() {
@@ -247,7 +288,6 @@ impl<C: Controller> Cpu<C> {
     }
     pub fn clv(&mut self) {
         self.set_v(false);
-        self.add_cycles(1);
     }
 
     // a: 6, c: 0
@@ -262,7 +302,6 @@ impl<C: Controller> Cpu<C> {
         self.set_n((result as i8) < 0);
         self.set_z(result == 0);
         self.y = result;
-        self.add_cycles(1);
     }
     pub fn bne(&mut self, value: u8) {
         if !self.z() {
@@ -274,7 +313,6 @@ impl<C: Controller> Cpu<C> {
     }
     pub fn cld(&mut self) {
         self.set_d(false);
-        self.add_cycles(1);
     }
 
     // a: 7, c: 0
@@ -289,7 +327,6 @@ impl<C: Controller> Cpu<C> {
         self.set_n((result as i8) < 0);
         self.set_z(result == 0);
         self.x = result;
-        self.add_cycles(1);
     }
     pub fn beq(&mut self, value: u8) {
         if self.z() {
@@ -301,7 +338,6 @@ impl<C: Controller> Cpu<C> {
     }
     pub fn sed(&mut self) {
         self.set_d(true);
-        self.add_cycles(1);
     }
 
 
@@ -332,6 +368,10 @@ impl<C: Controller> Cpu<C> {
 
     // a: 3, c: 1
     pub fn adc(&mut self, value: u8) {
+        if self.variant == CpuVariant::Nmos6502 && self.d() {
+            return self.adc_decimal(value);
+        }
+
         // overflow flag is checked less often
         // so use result from unsigned add for total result
         // (so second add can be optimized out if inlined)
@@ -341,9 +381,8 @@ impl<C: Controller> Cpu<C> {
 
         // eprintln!("adc: 0x{:x} + 0x{:x} + {} = 0x{:x}", self.a, value, self.c(), result);
 
-        let (v_result, v) = (self.a as i8).overflowing_add(value as i8);
-        let (_, v2) = v_result.overflowing_add(self.c() as i8);
-        let v = v || v2;
+        // signed overflow if both inputs have a different sign from the result
+        let v = ((self.a ^ result) & (value ^ result) & 0x80) != 0;
 
         self.set_n((result as i8) < 0);
         self.set_z(result == 0);
@@ -379,22 +418,66 @@ impl<C: Controller> Cpu<C> {
         // overflow flag is checked less often
         // so use result from unsigned sub for total result
         // (so second sub can be optimized out if inlined)
-        //
-        // todo: check if carry flag math is right
+        let borrow = !self.c();
         let (result, c) = self.a.overflowing_sub(value);
-        let (result, c2) = result.overflowing_sub((!self.c()) as u8);
+        let (result, c2) = result.overflowing_sub(borrow as u8);
         let c = c || c2;
 
-        let (v_result, v) = (self.a as i8).overflowing_sub(value as i8);
-        let (_, v2) = v_result.overflowing_sub((!self.c()) as i8);
-        let v = v || v2;
+        // signed overflow if the inputs differ in sign and the result
+        // has a different sign from a
+        let v = ((self.a ^ result) & (self.a ^ value) & 0x80) != 0;
 
         self.set_n((result as i8) < 0);
         self.set_z(result == 0);
         self.set_v(v);
         self.set_c(!c);
 
-        self.a = result;
+        if self.variant == CpuVariant::Nmos6502 && self.d() {
+            self.a = Self::sbc_decimal(self.a, value, borrow);
+        } else {
+            self.a = result;
+        }
+    }
+
+    // bcd addition a digit at a time, invalid bcd digits give
+    // the same results as a real 6502
+    fn adc_decimal(&mut self, value: u8) {
+        let carry = self.c() as u8;
+
+        // z comes from the binary sum
+        self.set_z(self.a.wrapping_add(value).wrapping_add(carry) == 0);
+
+        let mut lo = (self.a & 0x0f) + (value & 0x0f) + carry;
+        let mut hi = (self.a >> 4) + (value >> 4);
+        if lo > 0x09 {
+            lo += 0x06;
+            hi += 1;
+        }
+
+        // n and v are taken before the high digit is adjusted
+        let result = (hi << 4) | (lo & 0x0f);
+        self.set_n((result as i8) < 0);
+        self.set_v(((self.a ^ result) & (value ^ result) & 0x80) != 0);
+
+        if hi > 0x09 {
+            hi += 0x06;
+        }
+        self.set_c(hi > 0x0f);
+        self.a = (hi << 4) | (lo & 0x0f);
+    }
+    // the flags are the same as for a binary subtraction, so this only
+    // works out the result
+    const fn sbc_decimal(a: u8, value: u8, borrow: bool) -> u8 {
+        let mut lo = (a & 0x0f) as i8 - (value & 0x0f) as i8 - borrow as i8;
+        let mut hi = (a >> 4) as i8 - (value >> 4) as i8;
+        if lo < 0 {
+            lo -= 0x06;
+            hi -= 1;
+        }
+        if hi < 0 {
+            hi -= 0x06;
+        }
+        ((hi << 4) | (lo & 0x0f)) as u8
     }
 
 
@@ -448,11 +531,9 @@ impl<C: Controller> Cpu<C> {
         self.set_n((result as i8) < 0);
         self.set_z(result == 0);
         self.a = result;
-        self.add_cycles(1);
     }
     pub fn txs(&mut self) {
         self.sp = self.x;
-        self.add_cycles(1);
     }
 
     // a: 5, c: 2
@@ -467,14 +548,12 @@ impl<C: Controller> Cpu<C> {
         self.set_n((result as i8) < 0);
         self.set_z(result == 0);
         self.x = result;
-        self.add_cycles(1);
     }
     pub fn tsx(&mut self) {
         let result = self.sp;
         self.set_n((result as i8) < 0);
         self.set_z(result == 0);
         self.x = result;
-        self.add_cycles(1);
     }
 
     // a: 6, c: 2
@@ -490,7 +569,6 @@ impl<C: Controller> Cpu<C> {
         self.set_n((result as i8) < 0);
         self.set_z(result == 0);
         self.x = result;
-        self.add_cycles(1);
     }
 
     // a: 7, c: 2
@@ -501,91 +579,264 @@ impl<C: Controller> Cpu<C> {
         self.add_cycles(1);
         result
     }
-    pub fn nop(&self) {
-        self.add_cycles(1);
+    pub fn nop(&self) {}
+
+
+
+    // unofficial
+    pub fn slo(&mut self, value: u8) -> u8 {
+        let result = self.asl(value);
+        self.ora(result);
+        result
+    }
+    pub fn rla(&mut self, value: u8) -> u8 {
+        let result = self.rol(value);
+        self.and(result);
+        result
+    }
+    pub fn sre(&mut self, value: u8) -> u8 {
+        let result = self.lsr(value);
+        self.eor(result);
+        result
+    }
+    pub fn rra(&mut self, value: u8) -> u8 {
+        let result = self.ror(value);
+        self.adc(result);
+        result
+    }
+    pub fn sax(&mut self) -> u8 {
+        self.a & self.x
+    }
+    pub fn lax(&mut self, value: u8) {
+        self.lda(value);
+        self.x = value;
+    }
+    pub fn dcp(&mut self, value: u8) -> u8 {
+        let result = self.dec(value);
+        self.cmp(result);
+        result
+    }
+    pub fn isb(&mut self, value: u8) -> u8 {
+        let result = self.inc(value);
+        self.sbc(result);
+        result
+    }
+    pub fn anc(&mut self, value: u8) {
+        self.and(value);
+        self.set_c(self.n());
+    }
+    pub fn alr(&mut self, value: u8) {
+        let value = self.a & value;
+        let result = value >> 1;
+        self.set_n(false);
+        self.set_z(result == 0);
+        self.set_c((value & 1) != 0);
+        self.a = result;
+    }
+    pub fn arr(&mut self, value: u8) {
+        let result = ((self.c() as u8) << 7) | ((self.a & value) >> 1);
+        self.set_n((result as i8) < 0);
+        self.set_z(result == 0);
+        // c and v come from bits 6 and 5 of the result
+        self.set_c((result & 0x40) != 0);
+        self.set_v((((result >> 6) ^ (result >> 5)) & 1) != 0);
+        self.a = result;
+    }
+    pub fn axs(&mut self, value: u8) {
+        let (result, c) = (self.a & self.x).overflowing_sub(value);
+        self.set_n((result as i8) < 0);
+        self.set_z(result == 0);
+        self.set_c(!c);
+        self.x = result;
+    }
+    // unstable, the bits that survive from a depend on the chip,
+    // $ee is what most consoles show
+    pub fn xaa(&mut self, value: u8) {
+        self.lda((self.a | 0xee) & self.x & value);
+    }
+    pub fn las(&mut self, value: u8) {
+        self.lda(value & self.sp);
+        self.x = self.a;
+        self.sp = self.a;
+    }
+    pub fn ahx(&self) -> u8 {
+        self.a & self.x
+    }
+    pub fn shx(&self) -> u8 {
+        self.x
+    }
+    pub fn shy(&self) -> u8 {
+        self.y
+    }
+    pub fn tas(&mut self) -> u8 {
+        self.sp = self.a & self.x;
+        self.sp
+    }
+    // stops the cpu with pc left on the opcode
+    pub fn jam(&mut self) {
+        self.pc = self.pc.wrapping_sub(1);
+        self.jammed = true;
     }
 
 
 
     // INTERRUPTS
     pub fn nmi(&mut self) {
-        // trigger an nmi interrupt
-        // todo: does this take cpu cycles to execute?
+        // a jammed cpu ignores interrupts
+        if self.jammed {
+            return;
+        }
+        let sp = self.sp;
 
         // push pc
         self.write16(0x100 | self.sp.wrapping_sub(1) as u16, self.pc);
         self.sp = self.sp.wrapping_sub(2);
-        // push sr
-        self.write(0x100 | self.sp as u16, self.sr);
+        // push sr, with b clear
+        self.write(0x100 | self.sp as u16, (self.sr & 0xcf) | 0x20);
         self.sp = self.sp.wrapping_sub(1);
-        // goto interrupt routine
+        self.set_i(true);
+        // goto interrupt routine, 7 cycles in all like an irq
         self.pc = self.read16(0xfffa);
+        self.add_cycles(2);
+        self.profile_call(Kind::Nmi, sp);
+    }
+
+    // an irq is ignored while the i flag is set
+    pub fn irq(&mut self) {
+        if self.i() || self.jammed {
+            return;
+        }
+        let sp = self.sp;
+
+        // push pc
+        self.write16(0x100 | self.sp.wrapping_sub(1) as u16, self.pc);
+        self.sp = self.sp.wrapping_sub(2);
+        // push sr, with b clear
+        self.write(0x100 | self.sp as u16, (self.sr & 0xcf) | 0x20);
+        self.sp = self.sp.wrapping_sub(1);
+        self.set_i(true);
+        // goto interrupt routine
+        self.pc = self.read16(0xfffe);
+        self.add_cycles(2);
+        self.profile_call(Kind::Irq, sp);
+    }
+
+    // profiler hooks, sp is the stack pointer before the call
+    fn profile_call(&mut self, kind: Kind, sp: u8) {
+        let now = self.cycles();
+        if let Some(profiler) = &mut self.profiler {
+            profiler.call(kind, self.pc, sp, now);
+        }
+    }
+    fn profile_ret(&mut self) {
+        let now = self.cycles();
+        if let Some(profiler) = &mut self.profiler {
+            profiler.ret(self.sp, now);
+        }
     }
 
     // ADDRESSING MODES
-    fn immediate(&mut self) -> u16 {
-        let addr = self.pc;
-        self.pc = self.pc.wrapping_add(1);
-        addr
-    }
-    fn zeropage(&mut self) -> u16 {
-        let addr = self.read(self.pc) as u16;
-        self.pc = self.pc.wrapping_add(1);
-        addr
-    }
-    fn zeropage_x(&mut self) -> u16 {
-        let addr = self.read(self.pc).wrapping_add(self.x) as u16;
-        self.pc = self.pc.wrapping_add(1);
-        self.add_cycles(1);
-        addr
+    // fetches the opcode and operand, instructions in prg rom come decoded
+    // from a cache instead of going through the bus a byte at a time
+    #[inline]
+    fn fetch(&mut self) -> (u8, u16) {
+        let pc = self.pc;
+        let mem = unsafe { self.mem.as_mut() };
+        if let Some((opcode, operand)) = mem.decoded(pc) {
+            let len = OPCODES[opcode as usize].mode.operand_len();
+            self.pc = pc.wrapping_add(1 + len);
+            self.add_cycles(1 + len as usize);
+            return (opcode, operand);
+        }
+
+        let opcode = self.read(pc);
+        let len = OPCODES[opcode as usize].mode.operand_len();
+        let operand = match len {
+            0 => 0,
+            1 => self.read(pc.wrapping_add(1)) as u16,
+            _ => (self.read(pc.wrapping_add(1)) as u16) | ((self.read(pc.wrapping_add(2)) as u16) << 8),
+        };
+        self.pc = pc.wrapping_add(1 + len);
+        (opcode, operand)
     }
-    fn zeropage_y(&mut self) -> u16 {
-        let addr = self.read(self.pc).wrapping_add(self.y) as u16;
-        self.pc = self.pc.wrapping_add(1);
-        self.add_cycles(1);
-        addr
-    }
-    fn absolute(&mut self) -> u16 {
-        let addr = self.read16(self.pc);
-        self.pc = self.pc.wrapping_add(2);
-        addr
-    }
-    fn absolute_x(&mut self, store: bool) -> u16 {
-        let addr = self.read16(self.pc);
-        let addr2 = addr.wrapping_add(self.x as u16);
-        self.pc = self.pc.wrapping_add(2);
-        self.add_cycles((((addr >> 8) != (addr2 >> 8)) || store) as usize);
-        addr2
+
+    // effective address of the operand, write is set for instructions that
+    // always take the extra cycle for indexing
+    #[inline]
+    fn address(&mut self, mode: Mode, operand: u16, write: bool) -> u16 {
+        match mode {
+            Mode::Implied | Mode::Accumulator | Mode::Immediate | Mode::Relative => 0,
+            Mode::Zeropage | Mode::Absolute => operand,
+            Mode::ZeropageX => {
+                self.add_cycles(1);
+                (operand as u8).wrapping_add(self.x) as u16
+            },
+            Mode::ZeropageY => {
+                self.add_cycles(1);
+                (operand as u8).wrapping_add(self.y) as u16
+            },
+            Mode::AbsoluteX => self.index(operand, self.x, write),
+            Mode::AbsoluteY => self.index(operand, self.y, write),
+            Mode::Indirect => self.read16_wrap(operand),
+            Mode::IndirectX => {
+                let addr = self.read16_wrap((operand as u8).wrapping_add(self.x) as u16);
+                self.add_cycles(1);
+                addr
+            },
+            Mode::IndirectY => {
+                let addr = self.read16_wrap(operand);
+                self.index(addr, self.y, write)
+            },
+        }
     }
-    fn absolute_y(&mut self, store: bool) -> u16 {
-        let addr = self.read16(self.pc);
-        let addr2 = addr.wrapping_add(self.y as u16);
-        self.pc = self.pc.wrapping_add(2);
-        self.add_cycles((((addr >> 8) != (addr2 >> 8)) || store) as usize);
+    #[inline]
+    fn index(&mut self, addr: u16, index: u8, write: bool) -> u16 {
+        let addr2 = addr.wrapping_add(index as u16);
+        self.add_cycles((((addr >> 8) != (addr2 >> 8)) || write) as usize);
         addr2
     }
-    fn indirect(&mut self) -> u16 {
-        let addr = self.read16(self.pc);
-        self.pc = self.pc.wrapping_add(2);
-        self.read16(addr)
+
+    // the value an instruction operates on
+    #[inline]
+    fn load(&mut self, mode: Mode, addr: u16, operand: u16) -> u8 {
+        if mode == Mode::Immediate {
+            operand as u8
+        } else {
+            self.read(addr)
+        }
     }
-    fn indirect_x(&mut self) -> u16 {
-        let addr = self.read(self.pc).wrapping_add(self.x) as u16;
-        self.pc = self.pc.wrapping_add(1);
-        let addr = self.read16(addr);
-        self.add_cycles(1);
-        addr
-    }
-    fn indirect_y(&mut self, store: bool) -> u16 {
-        let addr = self.read(self.pc) as u16;
-        self.pc = self.pc.wrapping_add(1);
-        let addr = self.read16(addr);
-        let addr2 = addr.wrapping_add(self.y as u16);
-        self.add_cycles((((addr >> 8) != (addr2 >> 8)) || store) as usize);
-        addr2
+
+    // the unstable stores mask the value with the high byte of the base
+    // address plus one, and if indexing crossed a page the result also
+    // replaces the high byte of the address
+    #[inline]
+    fn write_high(&mut self, addr: u16, index: u8, value: u8) {
+        let base = addr.wrapping_sub(index as u16);
+        let value = value & ((base >> 8) as u8).wrapping_add(1);
+        let addr = if (base >> 8) != (addr >> 8) {
+            ((value as u16) << 8) | (addr & 0xff)
+        } else {
+            addr
+        };
+        self.write(addr, value);
+    }
+
+    // read-modify-write instructions
+    #[inline]
+    fn modify(&mut self, mode: Mode, addr: u16, f: fn(&mut Self, u8) -> u8) {
+        if mode == Mode::Accumulator {
+            self.a = f(self, self.a);
+        } else {
+            let value = self.read(addr);
+            let result = f(self, value);
+            self.write(addr, result);
+        }
     }
 
     fn read(&mut self, addr: u16) -> u8 {
+        if let Some(mut cdl) = self.cdl {
+            unsafe { cdl.as_mut() }.log_read(addr);
+        }
         let mem = unsafe { self.mem.as_mut() };
         let result = mem.read(addr);
         self.add_cycles(1);
@@ -605,917 +856,200 @@ impl<C: Controller> Cpu<C> {
         result
     }
 
+    // the high byte is fetched without carrying into the page,
+    // so pointers wrap within the zeropage and jmp ($xxff) reads $xx00
+    fn read16_wrap(&mut self, addr: u16) -> u16 {
+        let lo = self.read(addr) as u16;
+        let hi = self.read((addr & 0xff00) | (addr.wrapping_add(1) & 0x00ff)) as u16;
+        (hi << 8) | lo
+    }
+
     fn write16(&mut self, addr: u16, value: u16) {
         let mem = unsafe { self.mem.as_mut() };
         mem.write16(addr, value);
         self.add_cycles(2);
     }
 
+    pub fn attach_cdl(&mut self, cdl: Option<NonNull<Cdl>>) {
+        self.cdl = cdl;
+    }
+
+    pub fn cycles(&self) -> usize {
+        unsafe { self.cycles.as_ref().get() }
+    }
+
     fn add_cycles(&self, value: usize) {
         let cycles = unsafe { self.cycles.as_ref() };
         cycles.set(cycles.get() + value);
     }
 
     pub fn execute(&mut self) {
-        // self.print_next();
-        // eprintln!();
+        // a jammed cpu keeps the bus busy without doing anything
+        if self.jammed {
+            self.add_cycles(1);
+            return;
+        }
+
+        if let Some(mut tracer) = self.tracer.take() {
+            tracer.trace(self, unsafe { self.mem.as_ref() });
+            self.tracer = Some(tracer);
+        }
+
+        if let Some(mut cdl) = self.cdl {
+            let opcode = unsafe { self.mem.as_ref() }.peek(self.pc);
+            unsafe { cdl.as_mut() }.log_instruction(self.pc, opcode);
+        }
+
+        let start = self.cycles();
+
         // get instruction
-        let opcode = self.read(self.pc);
-        self.pc = self.pc.wrapping_add(1);
+        let (opcode, operand) = self.fetch();
+        let Opcode { mnemonic, mode, cycles, .. } = OPCODES[opcode as usize];
+        let addr = self.address(mode, operand, writes(mnemonic));
 
         // determine instruction:
-        match opcode {
-            // a: 0, c: 0
-            0x00 => self.brk(),
-            0x08 => self.php(),
-            0x10 => {
-                let addr = self.immediate();
-                let value = self.read(addr);
-                self.bpl(value);
-            },
-            0x18 => self.clc(),
-            // a: 1, c: 0
-            0x20 => {
-                let addr = self.absolute();
-                self.jsr(addr);
-            },
-            0x24 => {
-                let addr = self.zeropage();
-                let value = self.read(addr);
-                self.bit(value);
-            },
-            0x28 => self.plp(),
-            0x2c => {
-                let addr = self.absolute();
-                let value = self.read(addr);
-                self.bit(value);
-            },
-            0x30 => {
-                let addr = self.immediate();
-                let value = self.read(addr);
-                self.bmi(value);
-            },
-            0x38 => self.sec(),
-            // a: 2, c: 0
-            0x40 => self.rti(),
-            0x48 => self.pha(),
-            0x4c => {
-                let addr = self.absolute();
-                self.jmp(addr);
-            },
-            0x50 => {
-                let addr = self.immediate();
-                let value = self.read(addr);
-                self.bvc(value);
-            },
-            0x58 => self.cli(),
-            // a: 3, c: 0
-            0x60 => self.rts(),
-            0x68 => self.pla(),
-            0x6c => {
-                let addr = self.indirect();
-                self.jmp(addr);
-            },
-            0x70 => {
-                let addr = self.immediate();
-                let value = self.read(addr);
-                self.bvs(value);
-            },
-            0x78 => self.sei(),
-            // a: 4, c: 0
-            0x84 => {
-                let addr = self.zeropage();
-                let result = self.sty();
-                self.write(addr, result);
-            },
-            0x88 => self.dey(),
-            0x8c => {
-                let addr = self.absolute();
-                let result = self.sty();
-                self.write(addr, result);
-            },
-            0x90 => {
-                let addr = self.immediate();
-                let value = self.read(addr);
-                self.bcc(value);
-            },
-            0x94 => {
-                let addr = self.zeropage_x();
-                let result = self.sty();
-                self.write(addr, result);
-            },
-            0x98 => self.tya(),
-            // a: 5, c: 0
-            0xa0 => {
-                let addr = self.immediate();
-                let value = self.read(addr);
-                self.ldy(value);
-            },
-            0xa4 => {
-                let addr = self.zeropage();
-                let value = self.read(addr);
-                self.ldy(value);
-            },
-            0xa8 => self.tay(),
-            0xac => {
-                let addr = self.absolute();
-                let value = self.read(addr);
-                self.ldy(value);
-            },
-            0xb0 => {
-                let addr = self.immediate();
-                let value = self.read(addr);
-                self.bcs(value);
-            },
-            0xb4 => {
-                let addr = self.zeropage_x();
-                let value = self.read(addr);
-                self.ldy(value);
-            },
-            0xb8 => self.clv(),
-            0xbc => {
-                let addr = self.absolute_x(false);
-                let value = self.read(addr);
-                self.ldy(value);
-            },
-            // a: 6, c: 0
-            0xc0 => {
-                let addr = self.immediate();
-                let value = self.read(addr);
-                self.cpy(value);
-            },
-            0xc4 => {
-                let addr = self.zeropage();
-                let value = self.read(addr);
-                self.cpy(value);
-            },
-            0xc8 => self.iny(),
-            0xcc => {
-                let addr = self.absolute();
-                let value = self.read(addr);
-                self.cpy(value);
-            },
-            0xd0 => {
-                let addr = self.immediate();
-                let value = self.read(addr);
-                self.bne(value);
-            },
-            0xd8 => self.cld(),
-            // a: 7, c: 0
-            0xe0 => {
-                let addr = self.immediate();
-                let value = self.read(addr);
-                self.cpx(value);
-            },
-            0xe4 => {
-                let addr = self.zeropage();
-                let value = self.read(addr);
-                self.cpx(value);
-            },
-            0xe8 => self.inx(),
-            0xec => {
-                let addr = self.absolute();
-                let value = self.read(addr);
-                self.cpx(value);
-            },
-            0xf0 => {
-                let addr = self.immediate();
-                let value = self.read(addr);
-                self.beq(value);
-            },
-            0xf8 => self.sed(),
-
-            // a: 0, c: 1
-            0x01 => {
-                let addr = self.indirect_x();
-                let value = self.read(addr);
-                self.ora(value);
-            },
-            0x05 => {
-                let addr = self.zeropage();
-                let value = self.read(addr);
-                self.ora(value);
-            },
-            0x09 => {
-                let addr = self.immediate();
-                let value = self.read(addr);
-                self.ora(value);
-            },
-            0x0d => {
-                let addr = self.absolute();
-                let value = self.read(addr);
-                self.ora(value);
-            },
-            0x11 => {
-                let addr = self.indirect_y(false);
-                let value = self.read(addr);
-                self.ora(value);
-            },
-            0x15 => {
-                let addr = self.zeropage_x();
-                let value = self.read(addr);
-                self.ora(value);
-            },
-            0x19 => {
-                let addr = self.absolute_y(false);
-                let value = self.read(addr);
-                self.ora(value);
-            },
-            0x1d => {
-                let addr = self.absolute_x(false);
-                let value = self.read(addr);
-                self.ora(value);
-            },
-            // a: 1, c: 1
-            0x21 => {
-                let addr = self.indirect_x();
-                let value = self.read(addr);
-                self.and(value);
-            },
-            0x25 => {
-                let addr = self.zeropage();
-                let value = self.read(addr);
-                self.and(value);
-            },
-            0x29 => {
-                let addr = self.immediate();
-                let value = self.read(addr);
-                self.and(value);
-            },
-            0x2d => {
-                let addr = self.absolute();
-                let value = self.read(addr);
-                self.and(value);
-            },
-            0x31 => {
-                let addr = self.indirect_y(false);
-                let value = self.read(addr);
-                self.and(value);
-            },
-            0x35 => {
-                let addr = self.zeropage_x();
-                let value = self.read(addr);
-                self.and(value);
-            },
-            0x39 => {
-                let addr = self.absolute_y(false);
-                let value = self.read(addr);
-                self.and(value);
-            },
-            0x3d => {
-                let addr = self.absolute_x(false);
-                let value = self.read(addr);
-                self.and(value);
-            },
-            // a: 2, c: 1
-            0x41 => {
-                let addr = self.indirect_x();
-                let value = self.read(addr);
-                self.eor(value);
-            },
-            0x45 => {
-                let addr = self.zeropage();
-                let value = self.read(addr);
-                self.eor(value);
-            },
-            0x49 => {
-                let addr = self.immediate();
-                let value = self.read(addr);
-                self.eor(value);
-            },
-            0x4d => {
-                let addr = self.absolute();
-                let value = self.read(addr);
-                self.eor(value);
-            },
-            0x51 => {
-                let addr = self.indirect_y(false);
-                let value = self.read(addr);
-                self.eor(value);
-            },
-            0x55 => {
-                let addr = self.zeropage_x();
-                let value = self.read(addr);
-                self.eor(value);
-            },
-            0x59 => {
-                let addr = self.absolute_y(false);
-                let value = self.read(addr);
-                self.eor(value);
-            },
-            0x5d => {
-                let addr = self.absolute_x(false);
-                let value = self.read(addr);
-                self.eor(value);
-            },
-            // a: 3, c: 1
-            0x61 => {
-                let addr = self.indirect_x();
-                let value = self.read(addr);
-                self.adc(value);
-            },
-            0x65 => {
-                let addr = self.zeropage();
-                let value = self.read(addr);
-                self.adc(value);
-            },
-            0x69 => {
-                let addr = self.immediate();
-                let value = self.read(addr);
-                self.adc(value);
-            },
-            0x6d => {
-                let addr = self.absolute();
-                let value = self.read(addr);
-                self.adc(value);
-            },
-            0x71 => {
-                let addr = self.indirect_y(false);
-                let value = self.read(addr);
-                self.adc(value);
-            },
-            0x75 => {
-                let addr = self.zeropage_x();
-                let value = self.read(addr);
-                self.adc(value);
-            },
-            0x79 => {
-                let addr = self.absolute_y(false);
-                let value = self.read(addr);
-                self.adc(value);
-            },
-            0x7d => {
-                let addr = self.absolute_x(false);
-                let value = self.read(addr);
-                self.adc(value);
-            },
-            // a: 4, c: 1
-            0x81 => {
-                let addr = self.indirect_x();
-                let result = self.sta();
-                self.write(addr, result);
-            },
-            0x85 => {
-                let addr = self.zeropage();
-                let result = self.sta();
-                self.write(addr, result);
-            },
-            0x8d => {
-                let addr = self.absolute();
-                let result = self.sta();
-                self.write(addr, result);
-            },
-            0x91 => {
-                let addr = self.indirect_y(true);
-                let result = self.sta();
-                self.write(addr, result);
-            },
-            0x95 => {
-                let addr = self.zeropage_x();
-                let result = self.sta();
-                self.write(addr, result);
-            },
-            0x99 => {
-                let addr = self.absolute_y(true);
-                let result = self.sta();
-                self.write(addr, result);
-            },
-            0x9d => {
-                let addr = self.absolute_x(true);
-                let result = self.sta();
-                self.write(addr, result);
-            },
-            // a: 5, c: 1
-            0xa1 => {
-                let addr = self.indirect_x();
-                let value = self.read(addr);
-                self.lda(value);
-            },
-            0xa5 => {
-                let addr = self.zeropage();
-                let value = self.read(addr);
-                self.lda(value);
-            },
-            0xa9 => {
-                let addr = self.immediate();
-                let value = self.read(addr);
-                self.lda(value);
-            },
-            0xad => {
-                let addr = self.absolute();
-                let value = self.read(addr);
-                self.lda(value);
-            },
-            0xb1 => {
-                let addr = self.indirect_y(false);
-                let value = self.read(addr);
-                self.lda(value);
-            },
-            0xb5 => {
-                let addr = self.zeropage_x();
-                let value = self.read(addr);
-                self.lda(value);
-            },
-            0xb9 => {
-                let addr = self.absolute_y(false);
-                let value = self.read(addr);
-                self.lda(value);
-            },
-            0xbd => {
-                let addr = self.absolute_x(false);
-                let value = self.read(addr);
-                self.lda(value);
-            },
-            // a: 6, c: 1
-            0xc1 => {
-                let addr = self.indirect_x();
-                let value = self.read(addr);
-                self.cmp(value);
-            },
-            0xc5 => {
-                let addr = self.zeropage();
-                let value = self.read(addr);
-                self.cmp(value);
-            },
-            0xc9 => {
-                let addr = self.immediate();
-                let value = self.read(addr);
-                self.cmp(value);
-            },
-            0xcd => {
-                let addr = self.absolute();
-                let value = self.read(addr);
-                self.cmp(value);
-            },
-            0xd1 => {
-                let addr = self.indirect_y(false);
-                let value = self.read(addr);
-                self.cmp(value);
-            },
-            0xd5 => {
-                let addr = self.zeropage_x();
-                let value = self.read(addr);
-                self.cmp(value);
-            },
-            0xd9 => {
-                let addr = self.absolute_y(false);
-                let value = self.read(addr);
-                self.cmp(value);
-            },
-            0xdd => {
-                let addr = self.absolute_x(false);
-                let value = self.read(addr);
-                self.cmp(value);
-            },
-            // a: 7, c: 1
-            0xe1 => {
-                let addr = self.indirect_x();
-                let value = self.read(addr);
-                self.sbc(value);
-            },
-            0xe5 => {
-                let addr = self.zeropage();
-                let value = self.read(addr);
-                self.sbc(value);
-            },
-            0xe9 => {
-                let addr = self.immediate();
-                let value = self.read(addr);
-                self.sbc(value);
-            },
-            0xed => {
-                let addr = self.absolute();
-                let value = self.read(addr);
-                self.sbc(value);
-            },
-            0xf1 => {
-                let addr = self.indirect_y(false);
-                let value = self.read(addr);
-                self.sbc(value);
-            },
-            0xf5 => {
-                let addr = self.zeropage_x();
-                let value = self.read(addr);
-                self.sbc(value);
-            },
-            0xf9 => {
-                let addr = self.absolute_y(false);
-                let value = self.read(addr);
-                self.sbc(value);
-            },
-            0xfd => {
-                let addr = self.absolute_x(false);
-                let value = self.read(addr);
-                self.sbc(value);
-            },
+        match mnemonic {
+            // loads, arithmetic and comparisons
+            Mnemonic::Adc => { let value = self.load(mode, addr, operand); self.adc(value) },
+            Mnemonic::And => { let value = self.load(mode, addr, operand); self.and(value) },
+            Mnemonic::Bit => { let value = self.load(mode, addr, operand); self.bit(value) },
+            Mnemonic::Cmp => { let value = self.load(mode, addr, operand); self.cmp(value) },
+            Mnemonic::Cpx => { let value = self.load(mode, addr, operand); self.cpx(value) },
+            Mnemonic::Cpy => { let value = self.load(mode, addr, operand); self.cpy(value) },
+            Mnemonic::Eor => { let value = self.load(mode, addr, operand); self.eor(value) },
+            Mnemonic::Lda => { let value = self.load(mode, addr, operand); self.lda(value) },
+            Mnemonic::Ldx => { let value = self.load(mode, addr, operand); self.ldx(value) },
+            Mnemonic::Ldy => { let value = self.load(mode, addr, operand); self.ldy(value) },
+            Mnemonic::Ora => { let value = self.load(mode, addr, operand); self.ora(value) },
+            Mnemonic::Sbc => { let value = self.load(mode, addr, operand); self.sbc(value) },
+            Mnemonic::Lax => { let value = self.load(mode, addr, operand); self.lax(value) },
+            Mnemonic::Anc => { let value = self.load(mode, addr, operand); self.anc(value) },
+            Mnemonic::Alr => { let value = self.load(mode, addr, operand); self.alr(value) },
+            Mnemonic::Arr => { let value = self.load(mode, addr, operand); self.arr(value) },
+            Mnemonic::Axs => { let value = self.load(mode, addr, operand); self.axs(value) },
+            Mnemonic::Xaa => { let value = self.load(mode, addr, operand); self.xaa(value) },
+            Mnemonic::Las => { let value = self.load(mode, addr, operand); self.las(value) },
+            Mnemonic::Nop => {
+                // unofficial nops still read their operand
+                if !matches!(mode, Mode::Implied | Mode::Immediate) {
+                    self.read(addr);
+                }
+            },
+
+            // stores
+            Mnemonic::Sta => { let result = self.sta(); self.write(addr, result) },
+            Mnemonic::Stx => { let result = self.stx(); self.write(addr, result) },
+            Mnemonic::Sty => { let result = self.sty(); self.write(addr, result) },
+            Mnemonic::Sax => { let result = self.sax(); self.write(addr, result) },
+            Mnemonic::Ahx => { let result = self.ahx(); self.write_high(addr, self.y, result) },
+            Mnemonic::Shx => { let result = self.shx(); self.write_high(addr, self.y, result) },
+            Mnemonic::Shy => { let result = self.shy(); self.write_high(addr, self.x, result) },
+            Mnemonic::Tas => { let result = self.tas(); self.write_high(addr, self.y, result) },
+
+            // read-modify-write
+            Mnemonic::Asl => self.modify(mode, addr, Self::asl),
+            Mnemonic::Lsr => self.modify(mode, addr, Self::lsr),
+            Mnemonic::Rol => self.modify(mode, addr, Self::rol),
+            Mnemonic::Ror => self.modify(mode, addr, Self::ror),
+            Mnemonic::Inc => self.modify(mode, addr, Self::inc),
+            Mnemonic::Dec => self.modify(mode, addr, Self::dec),
+            Mnemonic::Slo => self.modify(mode, addr, Self::slo),
+            Mnemonic::Rla => self.modify(mode, addr, Self::rla),
+            Mnemonic::Sre => self.modify(mode, addr, Self::sre),
+            Mnemonic::Rra => self.modify(mode, addr, Self::rra),
+            Mnemonic::Dcp => self.modify(mode, addr, Self::dcp),
+            Mnemonic::Isb => self.modify(mode, addr, Self::isb),
+
+            // branches
+            Mnemonic::Bcc => self.bcc(operand as u8),
+            Mnemonic::Bcs => self.bcs(operand as u8),
+            Mnemonic::Beq => self.beq(operand as u8),
+            Mnemonic::Bmi => self.bmi(operand as u8),
+            Mnemonic::Bne => self.bne(operand as u8),
+            Mnemonic::Bpl => self.bpl(operand as u8),
+            Mnemonic::Bvc => self.bvc(operand as u8),
+            Mnemonic::Bvs => self.bvs(operand as u8),
+
+            // jumps and the stack
+            Mnemonic::Jmp => self.jmp(addr),
+            Mnemonic::Jsr => self.jsr(addr),
+            Mnemonic::Rts => self.rts(),
+            Mnemonic::Rti => self.rti(),
+            Mnemonic::Brk => self.brk(),
+            Mnemonic::Pha => self.pha(),
+            Mnemonic::Php => self.php(),
+            Mnemonic::Pla => self.pla(),
+            Mnemonic::Plp => self.plp(),
+
+            // registers and flags
+            Mnemonic::Clc => self.clc(),
+            Mnemonic::Cld => self.cld(),
+            Mnemonic::Cli => self.cli(),
+            Mnemonic::Clv => self.clv(),
+            Mnemonic::Sec => self.sec(),
+            Mnemonic::Sed => self.sed(),
+            Mnemonic::Sei => self.sei(),
+            Mnemonic::Dex => self.dex(),
+            Mnemonic::Dey => self.dey(),
+            Mnemonic::Inx => self.inx(),
+            Mnemonic::Iny => self.iny(),
+            Mnemonic::Tax => self.tax(),
+            Mnemonic::Tay => self.tay(),
+            Mnemonic::Tsx => self.tsx(),
+            Mnemonic::Txa => self.txa(),
+            Mnemonic::Txs => self.txs(),
+            Mnemonic::Tya => self.tya(),
+
+            Mnemonic::Jam => self.jam(),
+        }
 
-            // a: 0, c: 2
-            0x06 => {
-                let addr = self.zeropage();
-                let value = self.read(addr);
-                let result = self.asl(value);
-                self.write(addr, result);
-            },
-            0x0a => self.a = self.asl(self.a),
-            0x0e => {
-                let addr = self.absolute();
-                let value = self.read(addr);
-                let result = self.asl(value);
-                self.write(addr, result);
-            },
-            0x16 => {
-                let addr = self.zeropage_x();
-                let value = self.read(addr);
-                let result = self.asl(value);
-                self.write(addr, result);
-            },
-            0x1e => {
-                let addr = self.absolute_x(true);
-                let value = self.read(addr);
-                let result = self.asl(value);
-                self.write(addr, result);
-            },
-            // a: 1, c: 2
-            0x26 => {
-                let addr = self.zeropage();
-                let value = self.read(addr);
-                let result = self.rol(value);
-                self.write(addr, result);
-            },
-            0x2a => self.a = self.rol(self.a),
-            0x2e => {
-                let addr = self.absolute();
-                let value = self.read(addr);
-                let result = self.rol(value);
-                self.write(addr, result);
-            },
-            0x36 => {
-                let addr = self.zeropage_x();
-                let value = self.read(addr);
-                let result = self.rol(value);
-                self.write(addr, result);
-            },
-            0x3e => {
-                let addr = self.absolute_x(true);
-                let value = self.read(addr);
-                let result = self.rol(value);
-                self.write(addr, result);
-            },
-            // a: 2, c: 2
-            0x46 => {
-                let addr = self.zeropage();
-                let value = self.read(addr);
-                let result = self.lsr(value);
-                self.write(addr, result);
-            },
-            0x4a => self.a = self.lsr(self.a),
-            0x4e => {
-                let addr = self.absolute();
-                let value = self.read(addr);
-                let result = self.lsr(value);
-                self.write(addr, result);
-            },
-            0x56 => {
-                let addr = self.zeropage_x();
-                let value = self.read(addr);
-                let result = self.lsr(value);
-                self.write(addr, result);
-            },
-            0x5e => {
-                let addr = self.absolute_x(true);
-                let value = self.read(addr);
-                let result = self.lsr(value);
-                self.write(addr, result);
-            },
-            // a: 3, c: 2
-            0x66 => {
-                let addr = self.zeropage();
-                let value = self.read(addr);
-                let result = self.ror(value);
-                self.write(addr, result);
-            },
-            0x6a => self.a = self.ror(self.a),
-            0x6e => {
-                let addr = self.absolute();
-                let value = self.read(addr);
-                let result = self.ror(value);
-                self.write(addr, result);
-            },
-            0x76 => {
-                let addr = self.zeropage_x();
-                let value = self.read(addr);
-                let result = self.ror(value);
-                self.write(addr, result);
-            },
-            0x7e => {
-                let addr = self.absolute_x(true);
-                let value = self.read(addr);
-                let result = self.ror(value);
-                self.write(addr, result);
-            },
-            // a: 4, c: 2
-            0x86 => {
-                let addr = self.zeropage();
-                let result = self.stx();
-                self.write(addr, result);
-            },
-            0x8a => self.txa(),
-            0x8e => {
-                let addr = self.absolute();
-                let result = self.stx();
-                self.write(addr, result);
-            },
-            0x96 => {
-                let addr = self.zeropage_y();
-                let result = self.stx();
-                self.write(addr, result);
-            },
-            0x9a => self.txs(),
-            // a: 5, c: 2
-            0xa2 => {
-                let addr = self.immediate();
-                let value = self.read(addr);
-                self.ldx(value);
-            }
-            0xa6 => {
-                let addr = self.zeropage();
-                let value = self.read(addr);
-                self.ldx(value);
-            },
-            0xaa => self.tax(),
-            0xae => {
-                let addr = self.absolute();
-                let value = self.read(addr);
-                self.ldx(value);
-            },
-            0xb6 => {
-                let addr = self.zeropage_y();
-                let value = self.read(addr);
-                self.ldx(value);
-            },
-            0xba => self.tsx(),
-            0xbe => {
-                let addr = self.absolute_y(false);
-                let value = self.read(addr);
-                self.ldx(value);
-            },
-            // a: 6, c: 2
-            0xc6 => {
-                let addr = self.zeropage();
-                let value = self.read(addr);
-                let result = self.dec(value);
-                self.write(addr, result);
-            },
-            0xca => self.dex(),
-            0xce => {
-                let addr = self.absolute();
-                let value = self.read(addr);
-                let result = self.dec(value);
-                self.write(addr, result);
-            },
-            0xd6 => {
-                let addr = self.zeropage_x();
-                let value = self.read(addr);
-                let result = self.dec(value);
-                self.write(addr, result);
-            },
-            0xde => {
-                let addr = self.absolute_x(true);
-                let value = self.read(addr);
-                let result = self.dec(value);
-                self.write(addr, result);
-            },
-            // a: 7, c: 2
-            0xe6 => {
-                let addr = self.zeropage();
-                let value = self.read(addr);
-                let result = self.inc(value);
-                self.write(addr, result);
-            },
-            0xea => self.nop(),
-            0xee => {
-                let addr = self.absolute();
-                let value = self.read(addr);
-                let result = self.inc(value);
-                self.write(addr, result);
-            },
-            0xf6 => {
-                let addr = self.zeropage_x();
-                let value = self.read(addr);
-                let result = self.inc(value);
-                self.write(addr, result);
-            },
-            0xfe => {
-                let addr = self.absolute_x(true);
-                let value = self.read(addr);
-                let result = self.inc(value);
-                self.write(addr, result);
-            },
+        // internal cycles that don't access the bus
+        let spent = self.cycles() - start;
+        if spent < cycles as usize {
+            self.add_cycles(cycles as usize - spent);
+        }
 
-            // fallback
-            _ => panic!("unimplemented: opcode {opcode:x}")
+        if let Some(page) = unsafe { self.mem.as_mut() }.take_oam_dma() {
+            self.oam_dma(page);
+        }
+        if let Some(addr) = unsafe { self.mem.as_mut() }.take_dmc_dma() {
+            self.dmc_dma(addr);
         }
     }
 
-    // for debugging
-    #[allow(unused)]
-    fn print_next(&mut self) {
-        let mem = unsafe { self.mem.as_mut() };
-        
-        // get instruction
-        let opcode = mem.read(self.pc);
-
-        eprintln!("next instruction:");
-        eprint!("  ");
-
-        match opcode {
-            0x00 => eprintln!("brk"),
-            0x08 => eprintln!("php"),
-            0x10 => eprintln!("bpl ${:x}", (self.pc.wrapping_add(2) as i16).wrapping_add(mem.read(self.pc.wrapping_add(1)) as i8 as i16) as u16),
-            0x18 => eprintln!("clc"),
-
-            0x20 => eprintln!("jsr ${:x}", mem.read16(self.pc.wrapping_add(1))),
-            0x24 => eprintln!("bit ${:x}", mem.read(self.pc.wrapping_add(1))),
-            0x28 => eprintln!("plp"),
-            0x2c => eprintln!("bit ${:x}", mem.read16(self.pc.wrapping_add(1))),
-            0x30 => eprintln!("bmi ${:x}", (self.pc.wrapping_add(2) as i16).wrapping_add(mem.read(self.pc.wrapping_add(1)) as i8 as i16) as u16),
-            0x38 => eprintln!("sec"),
-
-            0x40 => eprintln!("rti"),
-            0x48 => eprintln!("pha"),
-            0x4c => eprintln!("jmp ${:x}", mem.read16(self.pc.wrapping_add(1))),
-            0x50 => eprintln!("bvc ${:x}", (self.pc.wrapping_add(2) as i16).wrapping_add(mem.read(self.pc.wrapping_add(1)) as i8 as i16) as u16),
-            0x58 => eprintln!("cli"),
-
-            0x60 => eprintln!("rts"),
-            0x68 => eprintln!("pla"),
-            0x6c => eprintln!("jmp (${:x})", mem.read16(self.pc.wrapping_add(1))),
-            0x70 => eprintln!("bvs ${:x}", (self.pc.wrapping_add(2) as i16).wrapping_add(mem.read(self.pc.wrapping_add(1)) as i8 as i16) as u16),
-            0x78 => eprintln!("sei"),
-
-            0x84 => eprintln!("sty ${:x}", mem.read(self.pc.wrapping_add(1))),
-            0x88 => eprintln!("dey"),
-            0x8c => eprintln!("sty ${:x}", mem.read16(self.pc.wrapping_add(1))),
-            0x90 => eprintln!("bcc ${:x}", (self.pc.wrapping_add(2) as i16).wrapping_add(mem.read(self.pc.wrapping_add(1)) as i8 as i16) as u16),
-            0x94 => eprintln!("sty ${:x},x", mem.read(self.pc.wrapping_add(1))),
-            0x98 => eprintln!("tya"),
-
-            0xa0 => eprintln!("ldy #${:x}", mem.read(self.pc.wrapping_add(1))),
-            0xa4 => eprintln!("ldy ${:x}", mem.read(self.pc.wrapping_add(1))),
-            0xa8 => eprintln!("tay"),
-            0xac => eprintln!("ldy ${:x}", mem.read16(self.pc.wrapping_add(1))),
-            0xb0 => eprintln!("bcs ${:x}", (self.pc.wrapping_add(2) as i16).wrapping_add(mem.read(self.pc.wrapping_add(1)) as i8 as i16) as u16),
-            0xb4 => eprintln!("ldy ${:x},x", mem.read(self.pc.wrapping_add(1))),
-            0xb8 => eprintln!("clv"),
-            0xbc => eprintln!("ldy ${:x},x", mem.read16(self.pc.wrapping_add(1))),
-
-            0xc0 => eprintln!("cpy #${:x}", mem.read(self.pc.wrapping_add(1))),
-            0xc4 => eprintln!("cpy ${:x}", mem.read(self.pc.wrapping_add(1))),
-            0xc8 => eprintln!("iny"),
-            0xcc => eprintln!("cpy ${:x}", mem.read16(self.pc.wrapping_add(1))),
-            0xd0 => eprintln!("bne ${:x}", (self.pc.wrapping_add(2) as i16).wrapping_add(mem.read(self.pc.wrapping_add(1)) as i8 as i16) as u16),
-            0xd8 => eprintln!("cld"),
-            
-            0xe0 => eprintln!("cpx #${:x}", mem.read(self.pc.wrapping_add(1))),
-            0xe4 => eprintln!("cpx ${:x}", mem.read(self.pc.wrapping_add(1))),
-            0xe8 => eprintln!("inx"),
-            0xec => eprintln!("cpx ${:x}", mem.read16(self.pc.wrapping_add(1))),
-            0xf0 => eprintln!("beq ${:x}", (self.pc.wrapping_add(2) as i16).wrapping_add(mem.read(self.pc.wrapping_add(1)) as i8 as i16) as u16),
-            0xf8 => eprintln!("sed"),
-
-
-
-            0x01 => eprintln!("ora (${:x},x)", mem.read(self.pc.wrapping_add(1))),
-            0x05 => eprintln!("ora ${:x}", mem.read(self.pc.wrapping_add(1))),
-            0x09 => eprintln!("ora #${:x}", mem.read(self.pc.wrapping_add(1))),
-            0x0d => eprintln!("ora ${:x}", mem.read16(self.pc.wrapping_add(1))),
-            0x11 => eprintln!("ora (${:x}),y", mem.read(self.pc.wrapping_add(1))),
-            0x15 => eprintln!("ora ${:x},x", mem.read(self.pc.wrapping_add(1))),
-            0x19 => eprintln!("ora ${:x},y", mem.read16(self.pc.wrapping_add(1))),
-            0x1d => eprintln!("ora ${:x},x", mem.read16(self.pc.wrapping_add(1))),
-
-            0x21 => eprintln!("and (${:x},x)", mem.read(self.pc.wrapping_add(1))),
-            0x25 => eprintln!("and ${:x}", mem.read(self.pc.wrapping_add(1))),
-            0x29 => eprintln!("and #${:x}", mem.read(self.pc.wrapping_add(1))),
-            0x2d => eprintln!("and ${:x}", mem.read16(self.pc.wrapping_add(1))),
-            0x31 => eprintln!("and (${:x}),y", mem.read(self.pc.wrapping_add(1))),
-            0x35 => eprintln!("and ${:x},x", mem.read(self.pc.wrapping_add(1))),
-            0x39 => eprintln!("and ${:x},y", mem.read16(self.pc.wrapping_add(1))),
-            0x3d => eprintln!("and ${:x},x", mem.read16(self.pc.wrapping_add(1))),
-
-            0x41 => eprintln!("eor (${:x},x)", mem.read(self.pc.wrapping_add(1))),
-            0x45 => eprintln!("eor ${:x}", mem.read(self.pc.wrapping_add(1))),
-            0x49 => eprintln!("eor #${:x}", mem.read(self.pc.wrapping_add(1))),
-            0x4d => eprintln!("eor ${:x}", mem.read16(self.pc.wrapping_add(1))),
-            0x51 => eprintln!("eor (${:x}),y", mem.read(self.pc.wrapping_add(1))),
-            0x55 => eprintln!("eor ${:x},x", mem.read(self.pc.wrapping_add(1))),
-            0x59 => eprintln!("eor ${:x},y", mem.read16(self.pc.wrapping_add(1))),
-            0x5d => eprintln!("eor ${:x},x", mem.read16(self.pc.wrapping_add(1))),
-
-            0x61 => eprintln!("adc (${:x},x)", mem.read(self.pc.wrapping_add(1))),
-            0x65 => eprintln!("adc ${:x}", mem.read(self.pc.wrapping_add(1))),
-            0x69 => eprintln!("adc #${:x}", mem.read(self.pc.wrapping_add(1))),
-            0x6d => eprintln!("adc ${:x}", mem.read16(self.pc.wrapping_add(1))),
-            0x71 => eprintln!("adc (${:x}),y", mem.read(self.pc.wrapping_add(1))),
-            0x75 => eprintln!("adc ${:x},x", mem.read(self.pc.wrapping_add(1))),
-            0x79 => eprintln!("adc ${:x},y", mem.read16(self.pc.wrapping_add(1))),
-            0x7d => eprintln!("adc ${:x},x", mem.read16(self.pc.wrapping_add(1))),
-
-            0x81 => eprintln!("sta (${:x},x)", mem.read(self.pc.wrapping_add(1))),
-            0x85 => eprintln!("sta ${:x}", mem.read(self.pc.wrapping_add(1))),
-            0x8d => eprintln!("sta ${:x}", mem.read16(self.pc.wrapping_add(1))),
-            0x91 => eprintln!("sta (${:x}),y", mem.read(self.pc.wrapping_add(1))),
-            0x95 => eprintln!("sta ${:x},x", mem.read(self.pc.wrapping_add(1))),
-            0x99 => eprintln!("sta ${:x},y", mem.read16(self.pc.wrapping_add(1))),
-            0x9d => eprintln!("sta ${:x},x", mem.read16(self.pc.wrapping_add(1))),
-
-            0xa1 => eprintln!("lda (${:x},x)", mem.read(self.pc.wrapping_add(1))),
-            0xa5 => eprintln!("lda ${:x}", mem.read(self.pc.wrapping_add(1))),
-            0xa9 => eprintln!("lda #${:x}", mem.read(self.pc.wrapping_add(1))),
-            0xad => eprintln!("lda ${:x}", mem.read16(self.pc.wrapping_add(1))),
-            0xb1 => eprintln!("lda (${:x}),y", mem.read(self.pc.wrapping_add(1))),
-            0xb5 => eprintln!("lda ${:x},x", mem.read(self.pc.wrapping_add(1))),
-            0xb9 => eprintln!("lda ${:x},y", mem.read16(self.pc.wrapping_add(1))),
-            0xbd => eprintln!("lda ${:x},x", mem.read16(self.pc.wrapping_add(1))),
-
-            0xc1 => eprintln!("cmp (${:x},x)", mem.read(self.pc.wrapping_add(1))),
-            0xc5 => eprintln!("cmp ${:x}", mem.read(self.pc.wrapping_add(1))),
-            0xc9 => eprintln!("cmp #${:x}", mem.read(self.pc.wrapping_add(1))),
-            0xcd => eprintln!("cmp ${:x}", mem.read16(self.pc.wrapping_add(1))),
-            0xd1 => eprintln!("cmp (${:x}),y", mem.read(self.pc.wrapping_add(1))),
-            0xd5 => eprintln!("cmp ${:x},x", mem.read(self.pc.wrapping_add(1))),
-            0xd9 => eprintln!("cmp ${:x},y", mem.read16(self.pc.wrapping_add(1))),
-            0xdd => eprintln!("cmp ${:x},x", mem.read16(self.pc.wrapping_add(1))),
-
-            0xe1 => eprintln!("sbc (${:x},x)", mem.read(self.pc.wrapping_add(1))),
-            0xe5 => eprintln!("sbc ${:x}", mem.read(self.pc.wrapping_add(1))),
-            0xe9 => eprintln!("sbc #${:x}", mem.read(self.pc.wrapping_add(1))),
-            0xed => eprintln!("sbc ${:x}", mem.read16(self.pc.wrapping_add(1))),
-            0xf1 => eprintln!("sbc (${:x}),y", mem.read(self.pc.wrapping_add(1))),
-            0xf5 => eprintln!("sbc ${:x},x", mem.read(self.pc.wrapping_add(1))),
-            0xf9 => eprintln!("sbc ${:x},y", mem.read16(self.pc.wrapping_add(1))),
-            0xfd => eprintln!("sbc ${:x},x", mem.read16(self.pc.wrapping_add(1))),
-
-
-
-            0x06 => eprintln!("asl ${:x}", mem.read(self.pc.wrapping_add(1))),
-            0x0a => eprintln!("asl a"),
-            0x0e => eprintln!("asl ${:x}", mem.read16(self.pc.wrapping_add(1))),
-            0x16 => eprintln!("asl ${:x},x", mem.read(self.pc.wrapping_add(1))),
-            0x1e => eprintln!("asl ${:x},x", mem.read16(self.pc.wrapping_add(1))),
-
-            0x26 => eprintln!("rol ${:x}", mem.read(self.pc.wrapping_add(1))),
-            0x2a => eprintln!("rol a"),
-            0x2e => eprintln!("rol ${:x}", mem.read16(self.pc.wrapping_add(1))),
-            0x36 => eprintln!("rol ${:x},x", mem.read(self.pc.wrapping_add(1))),
-            0x3e => eprintln!("rol ${:x},x", mem.read16(self.pc.wrapping_add(1))),
-
-            0x46 => eprintln!("lsr ${:x}", mem.read(self.pc.wrapping_add(1))),
-            0x4a => eprintln!("lsr a"),
-            0x4e => eprintln!("lsr ${:x}", mem.read16(self.pc.wrapping_add(1))),
-            0x56 => eprintln!("lsr ${:x},x", mem.read(self.pc.wrapping_add(1))),
-            0x5e => eprintln!("lsr ${:x},x", mem.read16(self.pc.wrapping_add(1))),
-
-            0x66 => eprintln!("ror ${:x}", mem.read(self.pc.wrapping_add(1))),
-            0x6a => eprintln!("ror a"),
-            0x6e => eprintln!("ror ${:x}", mem.read16(self.pc.wrapping_add(1))),
-            0x76 => eprintln!("ror ${:x},x", mem.read(self.pc.wrapping_add(1))),
-            0x7e => eprintln!("ror ${:x},x", mem.read16(self.pc.wrapping_add(1))),
-
-            0x86 => eprintln!("stx ${:x}", mem.read(self.pc.wrapping_add(1))),
-            0x8a => eprintln!("txa"),
-            0x8e => eprintln!("stx ${:x}", mem.read16(self.pc.wrapping_add(1))),
-            0x96 => eprintln!("stx ${:x},y", mem.read(self.pc.wrapping_add(1))),
-            0x9a => eprintln!("txs"),
-
-            0xa2 => eprintln!("ldx #${:x}", mem.read(self.pc.wrapping_add(1))),
-            0xa6 => eprintln!("ldx ${:x}", mem.read(self.pc.wrapping_add(1))),
-            0xaa => eprintln!("tax"),
-            0xae => eprintln!("ldx ${:x}", mem.read16(self.pc.wrapping_add(1))),
-            0xb6 => eprintln!("ldx ${:x},y", mem.read(self.pc.wrapping_add(1))),
-            0xba => eprintln!("tsx"),
-            0xbe => eprintln!("ldx ${:x},y", mem.read16(self.pc.wrapping_add(1))),
-
-            0xc6 => eprintln!("dec ${:x}", mem.read(self.pc.wrapping_add(1))),
-            0xca => eprintln!("dex"),
-            0xce => eprintln!("dec ${:x}", mem.read16(self.pc.wrapping_add(1))),
-            0xd6 => eprintln!("dec ${:x},x", mem.read(self.pc.wrapping_add(1))),
-            0xde => eprintln!("dec ${:x},x", mem.read16(self.pc.wrapping_add(1))),
-
-            0xe6 => eprintln!("inc ${:x}", mem.read(self.pc.wrapping_add(1))),
-            0xea => eprintln!("nop"),
-            0xee => eprintln!("inc ${:x}", mem.read16(self.pc.wrapping_add(1))),
-            0xf6 => eprintln!("inc ${:x},x", mem.read(self.pc.wrapping_add(1))),
-            0xfe => eprintln!("inc ${:x},x", mem.read16(self.pc.wrapping_add(1))),
-
-
-
-            _ => eprintln!("unknown instruction: {opcode:x}"),
+    // the cpu is halted for 4 cycles while the dmc reads a byte of its sample
+    fn dmc_dma(&mut self, addr: u16) {
+        self.add_cycles(3);
+        if let Some(mut cdl) = self.cdl {
+            unsafe { cdl.as_mut() }.log_pcm(addr);
         }
+        let mem = unsafe { self.mem.as_mut() };
+        let value = mem.read(addr);
+        mem.fill_dmc(value);
+        self.add_cycles(1);
+    }
 
-        eprintln!("registers:");
-        eprintln!("  pc=0x{:x}, a=0x{:x}, x=0x{:x}, y=0x{:x}, sp=0x1{:02x}", self.pc, self.a, self.x, self.y, self.sp);
-        eprintln!("  n={},v={},d={},i={},z={},c={}", self.n(), self.v(), self.d(), self.i(), self.z(), self.c());
-        eprintln!("cycle: {}", unsafe { self.cycles.as_ref().get() });
+    // the cpu is halted while a page is copied to $2004, taking 513 cycles,
+    // or 514 if it has to wait for an even cycle to start reading on
+    fn oam_dma(&mut self, page: u8) {
+        self.add_cycles(1);
+        if (self.cycles() & 1) != 0 {
+            self.add_cycles(1);
+        }
+        for i in 0..0x100 {
+            let value = self.read(((page as u16) << 8) | i);
+            self.write(0x2004, value);
+        }
     }
 }
+
+// instructions that always spend a cycle on indexing, since they can't
+// read or write until the high byte of the address is fixed
+const fn writes(mnemonic: Mnemonic) -> bool {
+    matches!(mnemonic,
+        Mnemonic::Sta | Mnemonic::Stx | Mnemonic::Sty | Mnemonic::Sax
+        | Mnemonic::Asl | Mnemonic::Lsr | Mnemonic::Rol | Mnemonic::Ror
+        | Mnemonic::Inc | Mnemonic::Dec | Mnemonic::Slo | Mnemonic::Rla
+        | Mnemonic::Sre | Mnemonic::Rra | Mnemonic::Dcp | Mnemonic::Isb
+        | Mnemonic::Ahx | Mnemonic::Shx | Mnemonic::Shy | Mnemonic::Tas)
+}
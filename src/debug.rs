@@ -0,0 +1,312 @@
+use std::ops::RangeInclusive;
+
+use crate::{mem::Mem, Controller, Nes};
+
+// step over and step out give up after a second so code that never returns
+// doesn't hang the caller
+const GIVE_UP_FRAMES: usize = 60;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AddressSpace {
+    Cpu,
+    // only accesses made through $2007 are seen here
+    Ppu,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    Exec,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub space: AddressSpace,
+    pub range: RangeInclusive<u16>,
+    pub read: bool,
+    pub write: bool,
+    pub exec: bool,
+    // only trigger when this value is read or written
+    pub value: Option<u8>,
+}
+
+impl Watchpoint {
+    pub fn new(space: AddressSpace, range: RangeInclusive<u16>) -> Self {
+        Self {
+            space,
+            range,
+            read: false,
+            write: false,
+            exec: false,
+            value: None,
+        }
+    }
+
+    #[inline]
+    pub fn read(mut self, read: bool) -> Self {
+        self.read = read;
+        self
+    }
+    #[inline]
+    pub fn write(mut self, write: bool) -> Self {
+        self.write = write;
+        self
+    }
+    #[inline]
+    pub fn exec(mut self, exec: bool) -> Self {
+        self.exec = exec;
+        self
+    }
+    #[inline]
+    pub fn value(mut self, value: u8) -> Self {
+        self.value = Some(value);
+        self
+    }
+
+    fn matches(&self, space: AddressSpace, access: Access, addr: u16, value: u8) -> bool {
+        let kind = match access {
+            Access::Read => self.read,
+            Access::Write => self.write,
+            Access::Exec => self.exec,
+        };
+        kind
+            && self.space == space
+            && self.range.contains(&addr)
+            && self.value.is_none_or(|v| v == value)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    Breakpoint(u16),
+    Watchpoint {
+        index: usize,
+        space: AddressSpace,
+        access: Access,
+        addr: u16,
+        value: u8,
+    },
+    // a single step finished
+    Step,
+    // the requested scanline was reached
+    Scanline(usize),
+    // an nmi was taken, pc is at the start of the handler
+    Nmi,
+    // a frame finished without anything else stopping execution
+    Frame,
+}
+
+pub struct Debugger {
+    breakpoints: Vec<u16>,
+    watchpoints: Vec<Option<Watchpoint>>,
+
+    // first watchpoint hit during the current instruction
+    hit: Option<StopReason>,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Self {
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+            hit: None,
+        }
+    }
+
+    pub fn add_breakpoint(&mut self, addr: u16) {
+        if !self.breakpoints.contains(&addr) {
+            self.breakpoints.push(addr);
+        }
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: u16) -> bool {
+        let len = self.breakpoints.len();
+        self.breakpoints.retain(|&bp| bp != addr);
+        self.breakpoints.len() != len
+    }
+
+    pub fn breakpoints(&self) -> &[u16] {
+        &self.breakpoints
+    }
+
+    // returns an index that can be passed to remove_watchpoint
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) -> usize {
+        if let Some(index) = self.watchpoints.iter().position(|w| w.is_none()) {
+            self.watchpoints[index] = Some(watchpoint);
+            index
+        } else {
+            self.watchpoints.push(Some(watchpoint));
+            self.watchpoints.len() - 1
+        }
+    }
+
+    pub fn remove_watchpoint(&mut self, index: usize) -> Option<Watchpoint> {
+        self.watchpoints.get_mut(index)?.take()
+    }
+
+    pub fn watchpoints(&self) -> impl Iterator<Item = (usize, &Watchpoint)> {
+        self.watchpoints.iter().enumerate().filter_map(|(i, w)| Some((i, w.as_ref()?)))
+    }
+
+    pub fn clear(&mut self) {
+        self.breakpoints.clear();
+        self.watchpoints.clear();
+        self.hit = None;
+    }
+
+    // called by the memory bus and ppu for every access
+    #[inline]
+    pub fn access(&mut self, space: AddressSpace, access: Access, addr: u16, value: u8) {
+        if self.watchpoints.is_empty() || self.hit.is_some() {
+            return;
+        }
+        for (index, w) in self.watchpoints.iter().enumerate() {
+            if let Some(w) = w {
+                if w.matches(space, access, addr, value) {
+                    self.hit = Some(StopReason::Watchpoint { index, space, access, addr, value });
+                    return;
+                }
+            }
+        }
+    }
+
+    // check for a breakpoint before executing at pc
+    pub(crate) fn check_exec(&mut self, pc: u16, opcode: u8) -> Option<StopReason> {
+        if self.breakpoints.contains(&pc) {
+            return Some(StopReason::Breakpoint(pc));
+        }
+        self.access(AddressSpace::Cpu, Access::Exec, pc, opcode);
+        self.hit.take()
+    }
+
+    pub(crate) fn take_hit(&mut self) -> Option<StopReason> {
+        self.hit.take()
+    }
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+enum Until {
+    // stop at breakpoints and watchpoints only
+    Break,
+    Step,
+    // return address and stack pointer of a jsr being stepped over
+    Return { pc: u16, sp: u8 },
+    // stack pointer inside the subroutine being stepped out of
+    StepOut { sp: u8 },
+    Scanline { line: usize, left: bool },
+    Nmi,
+}
+
+impl<C: Controller> Nes<C> {
+    pub fn debugger(&mut self) -> &mut Debugger {
+        &mut self.debugger
+    }
+
+    // runs until a breakpoint or watchpoint is hit or the frame finishes
+    pub fn debug_continue(&mut self) -> StopReason {
+        self.run_until(Until::Break, 1)
+    }
+
+    pub fn step_into(&mut self) -> StopReason {
+        self.run_until(Until::Step, 1)
+    }
+
+    // runs through a jsr as a single step
+    pub fn step_over(&mut self) -> StopReason {
+        if self.mem.peek(self.cpu.pc) == 0x20 {
+            let until = Until::Return {
+                pc: self.cpu.pc.wrapping_add(3),
+                sp: self.cpu.sp,
+            };
+            self.run_until(until, GIVE_UP_FRAMES)
+        } else {
+            self.step_into()
+        }
+    }
+
+    // runs until the current subroutine or interrupt handler returns
+    pub fn step_out(&mut self) -> StopReason {
+        let until = Until::StepOut { sp: self.cpu.sp };
+        self.run_until(until, GIVE_UP_FRAMES)
+    }
+
+    pub fn run_to_scanline(&mut self, line: usize) -> StopReason {
+        let until = Until::Scanline {
+            line,
+            left: self.ppu.scanline() != line,
+        };
+        self.run_until(until, 2)
+    }
+
+    // stops before the first instruction of the nmi handler
+    pub fn run_to_nmi(&mut self) -> StopReason {
+        self.run_until(Until::Nmi, 2)
+    }
+
+    // gives up with StopReason::Frame after max_frames frames have finished
+    fn run_until(&mut self, mut until: Until, max_frames: usize) -> StopReason {
+        // forget about hits from running without the debugger
+        self.debugger.take_hit();
+
+        let mut frames = 0;
+        let mut first = true;
+        loop {
+            if self.frame_events() && matches!(until, Until::Nmi) {
+                return StopReason::Nmi;
+            }
+
+            // don't stop on the breakpoint execution is resuming from
+            let pc = self.cpu.pc;
+            let opcode = self.mem.peek(pc);
+            if !first {
+                if let Some(reason) = self.debugger.check_exec(pc, opcode) {
+                    return reason;
+                }
+            }
+            first = false;
+
+            self.cpu.execute();
+            let frame = self.end_step();
+
+            if let Some(reason) = self.debugger.take_hit() {
+                return reason;
+            }
+            match &mut until {
+                Until::Break | Until::Nmi => {},
+                Until::Step => return StopReason::Step,
+                Until::Return { pc, sp } => {
+                    if self.cpu.pc == *pc && self.cpu.sp == *sp {
+                        return StopReason::Step;
+                    }
+                },
+                Until::StepOut { sp } => {
+                    // rts or rti popped the frame we started in
+                    if (opcode == 0x60 || opcode == 0x40) && self.cpu.sp > *sp {
+                        return StopReason::Step;
+                    }
+                },
+                Until::Scanline { line, left } => {
+                    let scanline = self.ppu.scanline();
+                    if scanline != *line {
+                        *left = true;
+                    } else if *left {
+                        return StopReason::Scanline(scanline);
+                    }
+                },
+            }
+
+            if frame {
+                frames += 1;
+                if frames >= max_frames {
+                    return StopReason::Frame;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,358 @@
+use std::fmt;
+
+use crate::mem::Mem;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Mode {
+    Implied,
+    Accumulator,
+    Immediate,
+    Zeropage,
+    ZeropageX,
+    ZeropageY,
+    Absolute,
+    AbsoluteX,
+    AbsoluteY,
+    Indirect,
+    IndirectX,
+    IndirectY,
+    Relative,
+}
+
+impl Mode {
+    // size of the operand in bytes
+    pub const fn operand_len(self) -> u16 {
+        match self {
+            Mode::Implied | Mode::Accumulator => 0,
+            Mode::Immediate | Mode::Zeropage | Mode::ZeropageX | Mode::ZeropageY
+                | Mode::IndirectX | Mode::IndirectY | Mode::Relative => 1,
+            Mode::Absolute | Mode::AbsoluteX | Mode::AbsoluteY | Mode::Indirect => 2,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Mnemonic {
+    // official
+    Adc, And, Asl, Bcc, Bcs, Beq, Bit, Bmi, Bne, Bpl, Brk, Bvc, Bvs, Clc,
+    Cld, Cli, Clv, Cmp, Cpx, Cpy, Dec, Dex, Dey, Eor, Inc, Inx, Iny, Jmp,
+    Jsr, Lda, Ldx, Ldy, Lsr, Nop, Ora, Pha, Php, Pla, Plp, Rol, Ror, Rti,
+    Rts, Sbc, Sec, Sed, Sei, Sta, Stx, Sty, Tax, Tay, Tsx, Txa, Txs, Tya,
+
+    // unofficial
+    Ahx, Alr, Anc, Arr, Axs, Dcp, Isb, Jam, Las, Lax, Rla, Rra, Sax, Shx,
+    Shy, Slo, Sre, Tas, Xaa,
+}
+
+impl Mnemonic {
+    pub const fn name(self) -> &'static str {
+        match self {
+            Mnemonic::Adc => "ADC",
+            Mnemonic::And => "AND",
+            Mnemonic::Asl => "ASL",
+            Mnemonic::Bcc => "BCC",
+            Mnemonic::Bcs => "BCS",
+            Mnemonic::Beq => "BEQ",
+            Mnemonic::Bit => "BIT",
+            Mnemonic::Bmi => "BMI",
+            Mnemonic::Bne => "BNE",
+            Mnemonic::Bpl => "BPL",
+            Mnemonic::Brk => "BRK",
+            Mnemonic::Bvc => "BVC",
+            Mnemonic::Bvs => "BVS",
+            Mnemonic::Clc => "CLC",
+            Mnemonic::Cld => "CLD",
+            Mnemonic::Cli => "CLI",
+            Mnemonic::Clv => "CLV",
+            Mnemonic::Cmp => "CMP",
+            Mnemonic::Cpx => "CPX",
+            Mnemonic::Cpy => "CPY",
+            Mnemonic::Dec => "DEC",
+            Mnemonic::Dex => "DEX",
+            Mnemonic::Dey => "DEY",
+            Mnemonic::Eor => "EOR",
+            Mnemonic::Inc => "INC",
+            Mnemonic::Inx => "INX",
+            Mnemonic::Iny => "INY",
+            Mnemonic::Jmp => "JMP",
+            Mnemonic::Jsr => "JSR",
+            Mnemonic::Lda => "LDA",
+            Mnemonic::Ldx => "LDX",
+            Mnemonic::Ldy => "LDY",
+            Mnemonic::Lsr => "LSR",
+            Mnemonic::Nop => "NOP",
+            Mnemonic::Ora => "ORA",
+            Mnemonic::Pha => "PHA",
+            Mnemonic::Php => "PHP",
+            Mnemonic::Pla => "PLA",
+            Mnemonic::Plp => "PLP",
+            Mnemonic::Rol => "ROL",
+            Mnemonic::Ror => "ROR",
+            Mnemonic::Rti => "RTI",
+            Mnemonic::Rts => "RTS",
+            Mnemonic::Sbc => "SBC",
+            Mnemonic::Sec => "SEC",
+            Mnemonic::Sed => "SED",
+            Mnemonic::Sei => "SEI",
+            Mnemonic::Sta => "STA",
+            Mnemonic::Stx => "STX",
+            Mnemonic::Sty => "STY",
+            Mnemonic::Tax => "TAX",
+            Mnemonic::Tay => "TAY",
+            Mnemonic::Tsx => "TSX",
+            Mnemonic::Txa => "TXA",
+            Mnemonic::Txs => "TXS",
+            Mnemonic::Tya => "TYA",
+
+            Mnemonic::Ahx => "AHX",
+            Mnemonic::Alr => "ALR",
+            Mnemonic::Anc => "ANC",
+            Mnemonic::Arr => "ARR",
+            Mnemonic::Axs => "AXS",
+            Mnemonic::Dcp => "DCP",
+            Mnemonic::Isb => "ISB",
+            Mnemonic::Jam => "JAM",
+            Mnemonic::Las => "LAS",
+            Mnemonic::Lax => "LAX",
+            Mnemonic::Rla => "RLA",
+            Mnemonic::Rra => "RRA",
+            Mnemonic::Sax => "SAX",
+            Mnemonic::Shx => "SHX",
+            Mnemonic::Shy => "SHY",
+            Mnemonic::Slo => "SLO",
+            Mnemonic::Sre => "SRE",
+            Mnemonic::Tas => "TAS",
+            Mnemonic::Xaa => "XAA",
+        }
+    }
+}
+
+impl fmt::Display for Mnemonic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Opcode {
+    pub mnemonic: Mnemonic,
+    pub mode: Mode,
+    pub official: bool,
+    // without page crossing and branch penalties, 0 for opcodes that halt the cpu
+    pub cycles: u8,
+}
+
+const fn op(mnemonic: Mnemonic, mode: Mode, cycles: u8) -> Opcode {
+    Opcode { mnemonic, mode, official: true, cycles }
+}
+
+const fn un(mnemonic: Mnemonic, mode: Mode, cycles: u8) -> Opcode {
+    Opcode { mnemonic, mode, official: false, cycles }
+}
+
+use Mnemonic::*;
+use Mode::*;
+
+pub static OPCODES: [Opcode; 0x100] = [
+    // 0x00
+    op(Brk, Implied, 7),     op(Ora, IndirectX, 6),   un(Jam, Implied, 0),     un(Slo, IndirectX, 8),
+    un(Nop, Zeropage, 3),    op(Ora, Zeropage, 3),    op(Asl, Zeropage, 5),    un(Slo, Zeropage, 5),
+    op(Php, Implied, 3),     op(Ora, Immediate, 2),   op(Asl, Accumulator, 2), un(Anc, Immediate, 2),
+    un(Nop, Absolute, 4),    op(Ora, Absolute, 4),    op(Asl, Absolute, 6),    un(Slo, Absolute, 6),
+    // 0x10
+    op(Bpl, Relative, 2),    op(Ora, IndirectY, 5),   un(Jam, Implied, 0),     un(Slo, IndirectY, 8),
+    un(Nop, ZeropageX, 4),   op(Ora, ZeropageX, 4),   op(Asl, ZeropageX, 6),   un(Slo, ZeropageX, 6),
+    op(Clc, Implied, 2),     op(Ora, AbsoluteY, 4),   un(Nop, Implied, 2),     un(Slo, AbsoluteY, 7),
+    un(Nop, AbsoluteX, 4),   op(Ora, AbsoluteX, 4),   op(Asl, AbsoluteX, 7),   un(Slo, AbsoluteX, 7),
+    // 0x20
+    op(Jsr, Absolute, 6),    op(And, IndirectX, 6),   un(Jam, Implied, 0),     un(Rla, IndirectX, 8),
+    op(Bit, Zeropage, 3),    op(And, Zeropage, 3),    op(Rol, Zeropage, 5),    un(Rla, Zeropage, 5),
+    op(Plp, Implied, 4),     op(And, Immediate, 2),   op(Rol, Accumulator, 2), un(Anc, Immediate, 2),
+    op(Bit, Absolute, 4),    op(And, Absolute, 4),    op(Rol, Absolute, 6),    un(Rla, Absolute, 6),
+    // 0x30
+    op(Bmi, Relative, 2),    op(And, IndirectY, 5),   un(Jam, Implied, 0),     un(Rla, IndirectY, 8),
+    un(Nop, ZeropageX, 4),   op(And, ZeropageX, 4),   op(Rol, ZeropageX, 6),   un(Rla, ZeropageX, 6),
+    op(Sec, Implied, 2),     op(And, AbsoluteY, 4),   un(Nop, Implied, 2),     un(Rla, AbsoluteY, 7),
+    un(Nop, AbsoluteX, 4),   op(And, AbsoluteX, 4),   op(Rol, AbsoluteX, 7),   un(Rla, AbsoluteX, 7),
+    // 0x40
+    op(Rti, Implied, 6),     op(Eor, IndirectX, 6),   un(Jam, Implied, 0),     un(Sre, IndirectX, 8),
+    un(Nop, Zeropage, 3),    op(Eor, Zeropage, 3),    op(Lsr, Zeropage, 5),    un(Sre, Zeropage, 5),
+    op(Pha, Implied, 3),     op(Eor, Immediate, 2),   op(Lsr, Accumulator, 2), un(Alr, Immediate, 2),
+    op(Jmp, Absolute, 3),    op(Eor, Absolute, 4),    op(Lsr, Absolute, 6),    un(Sre, Absolute, 6),
+    // 0x50
+    op(Bvc, Relative, 2),    op(Eor, IndirectY, 5),   un(Jam, Implied, 0),     un(Sre, IndirectY, 8),
+    un(Nop, ZeropageX, 4),   op(Eor, ZeropageX, 4),   op(Lsr, ZeropageX, 6),   un(Sre, ZeropageX, 6),
+    op(Cli, Implied, 2),     op(Eor, AbsoluteY, 4),   un(Nop, Implied, 2),     un(Sre, AbsoluteY, 7),
+    un(Nop, AbsoluteX, 4),   op(Eor, AbsoluteX, 4),   op(Lsr, AbsoluteX, 7),   un(Sre, AbsoluteX, 7),
+    // 0x60
+    op(Rts, Implied, 6),     op(Adc, IndirectX, 6),   un(Jam, Implied, 0),     un(Rra, IndirectX, 8),
+    un(Nop, Zeropage, 3),    op(Adc, Zeropage, 3),    op(Ror, Zeropage, 5),    un(Rra, Zeropage, 5),
+    op(Pla, Implied, 4),     op(Adc, Immediate, 2),   op(Ror, Accumulator, 2), un(Arr, Immediate, 2),
+    op(Jmp, Indirect, 5),    op(Adc, Absolute, 4),    op(Ror, Absolute, 6),    un(Rra, Absolute, 6),
+    // 0x70
+    op(Bvs, Relative, 2),    op(Adc, IndirectY, 5),   un(Jam, Implied, 0),     un(Rra, IndirectY, 8),
+    un(Nop, ZeropageX, 4),   op(Adc, ZeropageX, 4),   op(Ror, ZeropageX, 6),   un(Rra, ZeropageX, 6),
+    op(Sei, Implied, 2),     op(Adc, AbsoluteY, 4),   un(Nop, Implied, 2),     un(Rra, AbsoluteY, 7),
+    un(Nop, AbsoluteX, 4),   op(Adc, AbsoluteX, 4),   op(Ror, AbsoluteX, 7),   un(Rra, AbsoluteX, 7),
+    // 0x80
+    un(Nop, Immediate, 2),   op(Sta, IndirectX, 6),   un(Nop, Immediate, 2),   un(Sax, IndirectX, 6),
+    op(Sty, Zeropage, 3),    op(Sta, Zeropage, 3),    op(Stx, Zeropage, 3),    un(Sax, Zeropage, 3),
+    op(Dey, Implied, 2),     un(Nop, Immediate, 2),   op(Txa, Implied, 2),     un(Xaa, Immediate, 2),
+    op(Sty, Absolute, 4),    op(Sta, Absolute, 4),    op(Stx, Absolute, 4),    un(Sax, Absolute, 4),
+    // 0x90
+    op(Bcc, Relative, 2),    op(Sta, IndirectY, 6),   un(Jam, Implied, 0),     un(Ahx, IndirectY, 6),
+    op(Sty, ZeropageX, 4),   op(Sta, ZeropageX, 4),   op(Stx, ZeropageY, 4),   un(Sax, ZeropageY, 4),
+    op(Tya, Implied, 2),     op(Sta, AbsoluteY, 5),   op(Txs, Implied, 2),     un(Tas, AbsoluteY, 5),
+    un(Shy, AbsoluteX, 5),   op(Sta, AbsoluteX, 5),   un(Shx, AbsoluteY, 5),   un(Ahx, AbsoluteY, 5),
+    // 0xa0
+    op(Ldy, Immediate, 2),   op(Lda, IndirectX, 6),   op(Ldx, Immediate, 2),   un(Lax, IndirectX, 6),
+    op(Ldy, Zeropage, 3),    op(Lda, Zeropage, 3),    op(Ldx, Zeropage, 3),    un(Lax, Zeropage, 3),
+    op(Tay, Implied, 2),     op(Lda, Immediate, 2),   op(Tax, Implied, 2),     un(Lax, Immediate, 2),
+    op(Ldy, Absolute, 4),    op(Lda, Absolute, 4),    op(Ldx, Absolute, 4),    un(Lax, Absolute, 4),
+    // 0xb0
+    op(Bcs, Relative, 2),    op(Lda, IndirectY, 5),   un(Jam, Implied, 0),     un(Lax, IndirectY, 5),
+    op(Ldy, ZeropageX, 4),   op(Lda, ZeropageX, 4),   op(Ldx, ZeropageY, 4),   un(Lax, ZeropageY, 4),
+    op(Clv, Implied, 2),     op(Lda, AbsoluteY, 4),   op(Tsx, Implied, 2),     un(Las, AbsoluteY, 4),
+    op(Ldy, AbsoluteX, 4),   op(Lda, AbsoluteX, 4),   op(Ldx, AbsoluteY, 4),   un(Lax, AbsoluteY, 4),
+    // 0xc0
+    op(Cpy, Immediate, 2),   op(Cmp, IndirectX, 6),   un(Nop, Immediate, 2),   un(Dcp, IndirectX, 8),
+    op(Cpy, Zeropage, 3),    op(Cmp, Zeropage, 3),    op(Dec, Zeropage, 5),    un(Dcp, Zeropage, 5),
+    op(Iny, Implied, 2),     op(Cmp, Immediate, 2),   op(Dex, Implied, 2),     un(Axs, Immediate, 2),
+    op(Cpy, Absolute, 4),    op(Cmp, Absolute, 4),    op(Dec, Absolute, 6),    un(Dcp, Absolute, 6),
+    // 0xd0
+    op(Bne, Relative, 2),    op(Cmp, IndirectY, 5),   un(Jam, Implied, 0),     un(Dcp, IndirectY, 8),
+    un(Nop, ZeropageX, 4),   op(Cmp, ZeropageX, 4),   op(Dec, ZeropageX, 6),   un(Dcp, ZeropageX, 6),
+    op(Cld, Implied, 2),     op(Cmp, AbsoluteY, 4),   un(Nop, Implied, 2),     un(Dcp, AbsoluteY, 7),
+    un(Nop, AbsoluteX, 4),   op(Cmp, AbsoluteX, 4),   op(Dec, AbsoluteX, 7),   un(Dcp, AbsoluteX, 7),
+    // 0xe0
+    op(Cpx, Immediate, 2),   op(Sbc, IndirectX, 6),   un(Nop, Immediate, 2),   un(Isb, IndirectX, 8),
+    op(Cpx, Zeropage, 3),    op(Sbc, Zeropage, 3),    op(Inc, Zeropage, 5),    un(Isb, Zeropage, 5),
+    op(Inx, Implied, 2),     op(Sbc, Immediate, 2),   op(Nop, Implied, 2),     un(Sbc, Immediate, 2),
+    op(Cpx, Absolute, 4),    op(Sbc, Absolute, 4),    op(Inc, Absolute, 6),    un(Isb, Absolute, 6),
+    // 0xf0
+    op(Beq, Relative, 2),    op(Sbc, IndirectY, 5),   un(Jam, Implied, 0),     un(Isb, IndirectY, 8),
+    un(Nop, ZeropageX, 4),   op(Sbc, ZeropageX, 4),   op(Inc, ZeropageX, 6),   un(Isb, ZeropageX, 6),
+    op(Sed, Implied, 2),     op(Sbc, AbsoluteY, 4),   un(Nop, Implied, 2),     un(Isb, AbsoluteY, 7),
+    un(Nop, AbsoluteX, 4),   op(Sbc, AbsoluteX, 4),   op(Inc, AbsoluteX, 7),   un(Isb, AbsoluteX, 7),
+];
+
+// the memory location an instruction will touch, given the current registers
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Effective {
+    // zeropage pointer for (zp,x), base address for (zp),y
+    pub pointer: Option<u16>,
+    pub addr: u16,
+    pub value: u8,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Instruction {
+    pub addr: u16,
+    pub opcode: u8,
+    pub mnemonic: Mnemonic,
+    pub mode: Mode,
+    pub official: bool,
+    pub operand: u16,
+    bytes: [u8; 3],
+}
+
+impl Instruction {
+    pub const fn size(&self) -> u16 {
+        1 + self.mode.operand_len()
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes[..self.size() as usize]
+    }
+
+    pub const fn next(&self) -> u16 {
+        self.addr.wrapping_add(self.size())
+    }
+
+    // destination of a branch, jmp or jsr
+    pub const fn target(&self) -> Option<u16> {
+        match (self.mode, self.mnemonic) {
+            (Mode::Relative, _) => Some(self.next().wrapping_add(self.operand as u8 as i8 as u16)),
+            (Mode::Absolute, Mnemonic::Jmp | Mnemonic::Jsr) => Some(self.operand),
+            _ => None,
+        }
+    }
+
+    // resolve the operand against the given index registers
+    // (all reads go through peek, so this has no side effects)
+    pub fn effective<M: Mem + ?Sized>(&self, mem: &M, x: u8, y: u8) -> Option<Effective> {
+        let (pointer, addr) = match self.mode {
+            Mode::Implied | Mode::Accumulator | Mode::Immediate | Mode::Relative => return None,
+            Mode::Absolute if matches!(self.mnemonic, Mnemonic::Jmp | Mnemonic::Jsr) => return None,
+            Mode::Zeropage => (None, self.operand),
+            Mode::ZeropageX => (None, (self.operand as u8).wrapping_add(x) as u16),
+            Mode::ZeropageY => (None, (self.operand as u8).wrapping_add(y) as u16),
+            Mode::Absolute => (None, self.operand),
+            Mode::AbsoluteX => (None, self.operand.wrapping_add(x as u16)),
+            Mode::AbsoluteY => (None, self.operand.wrapping_add(y as u16)),
+            Mode::Indirect => {
+                // jmp ($xxff) wraps within the page
+                let lo = mem.peek(self.operand);
+                let hi = mem.peek((self.operand & 0xff00) | (self.operand.wrapping_add(1) & 0x00ff));
+                (None, ((hi as u16) << 8) | (lo as u16))
+            },
+            Mode::IndirectX => {
+                let ptr = (self.operand as u8).wrapping_add(x);
+                (Some(ptr as u16), peek16_zp(mem, ptr))
+            },
+            Mode::IndirectY => {
+                let base = peek16_zp(mem, self.operand as u8);
+                (Some(base), base.wrapping_add(y as u16))
+            },
+        };
+        Some(Effective {
+            pointer,
+            addr,
+            value: mem.peek(addr),
+        })
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.mnemonic)?;
+        match self.mode {
+            Mode::Implied => Ok(()),
+            Mode::Accumulator => write!(f, " A"),
+            Mode::Immediate => write!(f, " #${:02X}", self.operand),
+            Mode::Zeropage => write!(f, " ${:02X}", self.operand),
+            Mode::ZeropageX => write!(f, " ${:02X},X", self.operand),
+            Mode::ZeropageY => write!(f, " ${:02X},Y", self.operand),
+            Mode::Absolute => write!(f, " ${:04X}", self.operand),
+            Mode::AbsoluteX => write!(f, " ${:04X},X", self.operand),
+            Mode::AbsoluteY => write!(f, " ${:04X},Y", self.operand),
+            Mode::Indirect => write!(f, " (${:04X})", self.operand),
+            Mode::IndirectX => write!(f, " (${:02X},X)", self.operand),
+            Mode::IndirectY => write!(f, " (${:02X}),Y", self.operand),
+            Mode::Relative => write!(f, " ${:04X}", self.target().unwrap()),
+        }
+    }
+}
+
+fn peek16_zp<M: Mem + ?Sized>(mem: &M, ptr: u8) -> u16 {
+    // zeropage pointers wrap around within the zeropage
+    (mem.peek(ptr as u16) as u16) | ((mem.peek(ptr.wrapping_add(1) as u16) as u16) << 8)
+}
+
+pub fn disassemble<M: Mem + ?Sized>(mem: &M, addr: u16) -> Instruction {
+    let opcode = mem.peek(addr);
+    let Opcode { mnemonic, mode, official, .. } = OPCODES[opcode as usize];
+    let mut bytes = [opcode, 0, 0];
+    for i in 0..mode.operand_len() {
+        bytes[1 + i as usize] = mem.peek(addr.wrapping_add(1 + i));
+    }
+    Instruction {
+        addr,
+        opcode,
+        mnemonic,
+        mode,
+        official,
+        operand: (bytes[1] as u16) | ((bytes[2] as u16) << 8),
+        bytes,
+    }
+}
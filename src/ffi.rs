@@ -1,10 +1,16 @@
-use std::{ffi::{c_char, c_uint, c_void}, ptr, slice};
+use std::{ffi::{c_char, c_uint, c_void, CStr}, fs, path::Path, ptr, slice};
 
-use crate::{retro, Controller, Nes};
+use crate::{ntsc::{self, Filter, Preset}, palette::{Ntsc, Palette}, region::Region, retro, video::{Aspect, Overscan, Screen}, Controller, Nes};
 
 // core
 static mut NES: Option<Nes<RetroPad>> = None;
-static mut BUF: [u8; 256*240*4] = [0; 256*240*4];
+static mut FILTER: Option<Filter> = None;
+static mut SCREEN: Screen = Screen {
+    overscan: Overscan { top: 0, bottom: 0, left: 0, right: 0 },
+    aspect: Aspect::FourThree,
+    integer_scale: false,
+};
+static mut BUF: [u32; ntsc::WIDTH*ntsc::HEIGHT] = [0; ntsc::WIDTH*ntsc::HEIGHT];
 
 static mut ENVIRON_CB: retro::environment_t = {
     unsafe extern "system" fn environ_cb(_cmd: c_uint, _data: *mut c_void) -> bool {
@@ -49,9 +55,28 @@ static mut INPUT_STATE_CB: retro::input_state_t = {
 };
 
 const SAMPLE_RATE: usize = 48000;
-const SAMPLE_COUNT: usize = (11*4*341*262*SAMPLE_RATE)/(236250000);
+// enough for a frame at 50 fps plus a sample carried over, ntsc frames
+// use less of it
+const SAMPLE_COUNT: usize = SAMPLE_RATE / 50 + 1;
 
 static mut AUDIO_BUF: [i16; SAMPLE_COUNT<<1] = [0; SAMPLE_COUNT<<1];
+// the fraction of a sample left over from the last frame
+static mut SAMPLE_CARRY: f64 = 0.0;
+
+// core options
+const REGION_KEY: &CStr = c"nes_region";
+const PALETTE_KEY: &CStr = c"nes_palette";
+const HUE_KEY: &CStr = c"nes_ntsc_hue";
+const SATURATION_KEY: &CStr = c"nes_ntsc_saturation";
+const CONTRAST_KEY: &CStr = c"nes_ntsc_contrast";
+const BRIGHTNESS_KEY: &CStr = c"nes_ntsc_brightness";
+const GAMMA_KEY: &CStr = c"nes_ntsc_gamma";
+const FILTER_KEY: &CStr = c"nes_ntsc_filter";
+const CROP_TOP_KEY: &CStr = c"nes_crop_top";
+const CROP_BOTTOM_KEY: &CStr = c"nes_crop_bottom";
+const CROP_LEFT_KEY: &CStr = c"nes_crop_left";
+const CROP_RIGHT_KEY: &CStr = c"nes_crop_right";
+const ASPECT_KEY: &CStr = c"nes_aspect";
 
 struct RetroPad {
 }
@@ -128,19 +153,128 @@ pub extern "system" fn retro_get_system_info(info: *mut retro::system_info) {
     }
 }
 
+// the value of a core option
+fn variable(key: &CStr) -> Option<&'static str> {
+    unsafe {
+        let mut var = retro::variable::default().key(key);
+        if !ENVIRON_CB(retro::ENVIRONMENT_GET_VARIABLE, &raw mut var as _) || var.value.is_null() {
+            return None;
+        }
+        CStr::from_ptr(var.value).to_str().ok()
+    }
+}
+
+// the region the frontend's core option asks for, none is auto
+fn region_option() -> Option<Region> {
+    variable(REGION_KEY).and_then(Region::from_name)
+}
+
+// used by both the generated palette and the filter
+fn ntsc_option() -> Ntsc {
+    let number = |key, default| variable(key).and_then(|v| v.parse().ok()).unwrap_or(default);
+    let ntsc = Ntsc::default();
+    ntsc
+        .hue(number(HUE_KEY, ntsc.hue))
+        .saturation(number(SATURATION_KEY, ntsc.saturation))
+        .contrast(number(CONTRAST_KEY, ntsc.contrast))
+        .brightness(number(BRIGHTNESS_KEY, ntsc.brightness))
+        .gamma(number(GAMMA_KEY, ntsc.gamma))
+}
+
+fn palette_option() -> Palette {
+    match variable(PALETTE_KEY) {
+        Some("ntsc") => Palette::ntsc(&ntsc_option()),
+        // nes.pal in the frontend's system directory
+        Some("custom") => {
+            let mut dir: *const c_char = ptr::null();
+            if !unsafe { ENVIRON_CB(retro::ENVIRONMENT_GET_SYSTEM_DIRECTORY, &raw mut dir as _) } || dir.is_null() {
+                eprintln!("No system directory to load nes.pal from.");
+                return Palette::default();
+            }
+            let path = Path::new(&*unsafe { CStr::from_ptr(dir) }.to_string_lossy()).join("nes.pal");
+            match fs::read(&path).and_then(|data| Palette::load(&data)) {
+                Ok(palette) => palette,
+                Err(e) => {
+                    eprintln!("{}: {}", path.display(), e);
+                    Palette::default()
+                },
+            }
+        },
+        _ => Palette::default(),
+    }
+}
+
+fn filter_option() -> Option<Filter> {
+    variable(FILTER_KEY)
+        .and_then(Preset::from_name)
+        .map(|preset| Filter::new(preset, ntsc_option()))
+}
+
+// stretching is left to the frontend's own settings
+fn screen_option() -> Screen {
+    let crop = |key| variable(key).and_then(|v| v.parse().ok()).unwrap_or(0);
+    Screen::default()
+        .overscan(Overscan {
+            top: crop(CROP_TOP_KEY),
+            bottom: crop(CROP_BOTTOM_KEY),
+            left: crop(CROP_LEFT_KEY),
+            right: crop(CROP_RIGHT_KEY),
+        })
+        .aspect(variable(ASPECT_KEY).and_then(Aspect::from_name).unwrap_or_default())
+}
+
+// returns true if the region or the geometry of the picture changed
+fn apply_options(nes: &mut Nes<RetroPad>) -> bool {
+    let geometry = || (picture(), unsafe { SCREEN }.ratio());
+    let before = (nes.region(), geometry());
+    nes.set_region(region_option());
+    nes.set_palette(palette_option());
+    unsafe {
+        FILTER = filter_option();
+        SCREEN = screen_option();
+    }
+    (nes.region(), geometry()) != before
+}
+
+// the filter makes the picture wider
+fn width() -> usize {
+    let filter = &raw const FILTER;
+    unsafe {
+        if (*filter).is_some() { ntsc::WIDTH } else { 256 }
+    }
+}
+
+// the part of BUF that's shown after cropping, as x, y, width and height
+fn picture() -> (usize, usize, usize, usize) {
+    let overscan = unsafe { SCREEN.overscan };
+    let width = width();
+    (overscan.left*width/256, overscan.top, overscan.width()*width/256, overscan.height())
+}
+
+fn region() -> Region {
+    let nes = &raw const NES;
+    unsafe {
+        (*nes).as_ref().map_or(Region::Ntsc, |nes| nes.region())
+    }
+}
+
+fn av_info() -> retro::system_av_info {
+    retro::system_av_info::default()
+        .timing(retro::system_timing::default()
+            .fps(region().fps())
+            .sample_rate(SAMPLE_RATE as f64))
+        .geometry(retro::game_geometry::default()
+            .base_width(picture().2 as _)
+            .base_height(picture().3 as _)
+            .max_width(ntsc::WIDTH as _)
+            .max_height(ntsc::HEIGHT as _)
+            .aspect_ratio(unsafe { SCREEN }.ratio().unwrap_or(0.0) as f32))
+}
+
 #[no_mangle]
 pub extern "system" fn retro_get_system_av_info(info: *mut retro::system_av_info) {
     unsafe {
-        *info = retro::system_av_info::default()
-            .timing(retro::system_timing::default()
-                .fps(60.0)
-                .sample_rate(SAMPLE_RATE as f64))
-            .geometry(retro::game_geometry::default()
-                .base_width(320)
-                .base_height(240)
-                .max_width(320)
-                .max_height(240)
-                .aspect_ratio(4.0 / 3.0));
+        *info = av_info();
     }
 }
 
@@ -148,6 +282,50 @@ pub extern "system" fn retro_get_system_av_info(info: *mut retro::system_av_info
 pub extern "system" fn retro_set_environment(cb: retro::environment_t) {
     unsafe {
         ENVIRON_CB = cb;
+
+        let vars = [
+            retro::variable::default()
+                .key(REGION_KEY)
+                .value(c"Region; auto|ntsc|pal|dendy"),
+            retro::variable::default()
+                .key(PALETTE_KEY)
+                .value(c"Palette; default|ntsc|custom"),
+            retro::variable::default()
+                .key(FILTER_KEY)
+                .value(c"NTSC filter; disabled|composite|svideo|rgb|monochrome"),
+            retro::variable::default()
+                .key(ASPECT_KEY)
+                .value(c"Aspect ratio; 4:3|8:7|1:1"),
+            retro::variable::default()
+                .key(CROP_TOP_KEY)
+                .value(c"Crop overscan top; 0|4|8|12|16"),
+            retro::variable::default()
+                .key(CROP_BOTTOM_KEY)
+                .value(c"Crop overscan bottom; 0|4|8|12|16"),
+            retro::variable::default()
+                .key(CROP_LEFT_KEY)
+                .value(c"Crop overscan left; 0|4|8|12|16"),
+            retro::variable::default()
+                .key(CROP_RIGHT_KEY)
+                .value(c"Crop overscan right; 0|4|8|12|16"),
+            retro::variable::default()
+                .key(HUE_KEY)
+                .value(c"NTSC palette hue; 0|5|10|15|20|25|30|-30|-25|-20|-15|-10|-5"),
+            retro::variable::default()
+                .key(SATURATION_KEY)
+                .value(c"NTSC palette saturation; 1.0|1.1|1.2|1.3|1.4|1.5|0.5|0.6|0.7|0.8|0.9"),
+            retro::variable::default()
+                .key(CONTRAST_KEY)
+                .value(c"NTSC palette contrast; 1.0|1.1|1.2|1.3|1.4|1.5|0.5|0.6|0.7|0.8|0.9"),
+            retro::variable::default()
+                .key(BRIGHTNESS_KEY)
+                .value(c"NTSC palette brightness; 1.0|1.1|1.2|1.3|1.4|1.5|0.5|0.6|0.7|0.8|0.9"),
+            retro::variable::default()
+                .key(GAMMA_KEY)
+                .value(c"NTSC palette gamma; 1.8|2.0|2.2|2.4|2.6|1.0|1.2|1.4|1.6"),
+            retro::variable::default(),
+        ];
+        ENVIRON_CB(retro::ENVIRONMENT_SET_VARIABLES, vars.as_ptr() as _);
     }
 }
 
@@ -191,12 +369,32 @@ pub extern "system" fn retro_reset() {}
 
 #[no_mangle]
 pub extern "system" fn retro_run() {
+    let (nes, audio_buf) = (&raw mut NES, &raw mut AUDIO_BUF);
     unsafe {
-        NES.as_mut().unwrap().run();
-        BUF.copy_from_slice(NES.as_mut().unwrap().framebuffer());
-        VIDEO_CB(BUF.as_ptr() as _, 256, 240 , 256*4);
-        NES.as_mut().unwrap().play_audio(&mut AUDIO_BUF);
-        AUDIO_BATCH_CB(AUDIO_BUF.as_ptr(), AUDIO_BUF.len()>>1);
+        let mut updated = false;
+        if ENVIRON_CB(retro::ENVIRONMENT_GET_VARIABLE_UPDATE, &raw mut updated as _) && updated
+            && apply_options((*nes).as_mut().unwrap()) {
+            // the fps changed with the region, or the width with the filter
+            let info = av_info();
+            ENVIRON_CB(retro::ENVIRONMENT_SET_SYSTEM_AV_INFO, &raw const info as _);
+        }
+
+        (*nes).as_mut().unwrap().run();
+        let width = width();
+        let (filter, buf) = (&raw mut FILTER, &raw mut BUF);
+        match (*filter).as_mut() {
+            Some(filter) => filter.apply((*nes).as_ref().unwrap().indexed_framebuffer(), &mut *buf),
+            None => slice::from_raw_parts_mut(buf.cast::<u8>(), 256*240*4)
+                .copy_from_slice((*nes).as_mut().unwrap().framebuffer()),
+        }
+        let (x, y, w, h) = picture();
+        VIDEO_CB(buf.cast::<u32>().add(y*width + x) as _, w as _, h as _, width*4);
+        let carry = &raw mut SAMPLE_CARRY;
+        let samples = *carry + SAMPLE_RATE as f64 / region().fps();
+        let count = samples as usize;
+        *carry = samples - count as f64;
+        (*nes).as_mut().unwrap().play_audio(&mut (&mut *audio_buf)[..count<<1]);
+        AUDIO_BATCH_CB(audio_buf.cast::<i16>(), count);
     }
 }
 
@@ -215,8 +413,11 @@ pub extern "system" fn retro_load_game(info: *const retro::game_info) -> bool {
             info.size,
         );
         NES = Nes::load_from_memory(game);
-        if let Some(nes) = NES.as_mut() {
+        SAMPLE_CARRY = 0.0;
+        let nes = &raw mut NES;
+        if let Some(nes) = (*nes).as_mut() {
             nes.connect(0, RetroPad {});
+            apply_options(nes);
             true
         } else {
             false
@@ -233,7 +434,10 @@ pub extern "system" fn retro_unload_game() {
 
 #[no_mangle]
 pub extern "system" fn retro_get_region() -> c_uint {
-    retro::REGION_NTSC
+    match region() {
+        Region::Ntsc => retro::REGION_NTSC,
+        Region::Pal | Region::Dendy => retro::REGION_PAL,
+    }
 }
 
 #[no_mangle]
@@ -0,0 +1,348 @@
+use std::{fmt::Write as _, io::{self, ErrorKind, Read, Write}, net::{SocketAddr, TcpListener, TcpStream}};
+
+use crate::{debug::{AddressSpace, StopReason, Watchpoint}, Controller, Nes};
+
+// gdb remote serial protocol over tcp
+// registers are a, x, y, p, sp (one byte each) and pc (two bytes, little endian)
+pub struct GdbStub {
+    listener: TcpListener,
+    conn: Option<TcpStream>,
+    buf: Vec<u8>,
+    running: bool,
+
+    // (type, addr, len) of each watchpoint set with a z packet,
+    // indexed the same as the debugger's watchpoints
+    watchpoints: Vec<Option<(u8, u16, u16)>>,
+}
+
+impl GdbStub {
+    // only listens on localhost
+    pub fn bind(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        listener.set_nonblocking(true)?;
+        Ok(Self {
+            listener,
+            conn: None,
+            buf: Vec::new(),
+            // wait for gdb to attach before running
+            running: false,
+            watchpoints: Vec::new(),
+        })
+    }
+
+    // the port that was picked when binding to port 0
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub fn connected(&self) -> bool {
+        self.conn.is_some()
+    }
+
+    // services gdb and runs the emulator while gdb allows it,
+    // returns true if a frame was finished
+    pub fn update<C: Controller>(&mut self, nes: &mut Nes<C>) -> bool {
+        if self.conn.is_none() {
+            if let Ok((conn, addr)) = self.listener.accept() {
+                if conn.set_nonblocking(true).is_ok() {
+                    eprintln!("gdb: connection from {}", addr);
+                    self.conn = Some(conn);
+                    self.buf.clear();
+                    self.running = false;
+                }
+            }
+        }
+
+        if self.conn.is_some() {
+            if let Err(e) = self.receive(nes) {
+                eprintln!("gdb: {}", e);
+                self.detach(nes);
+            }
+        }
+        if !self.running {
+            return false;
+        }
+
+        match nes.debug_continue() {
+            StopReason::Frame => true,
+            reason => {
+                self.running = false;
+                let reply = self.stop_reply(reason);
+                if let Err(e) = self.send_packet(&reply) {
+                    eprintln!("gdb: {}", e);
+                    self.detach(nes);
+                }
+                false
+            },
+        }
+    }
+
+    fn detach<C: Controller>(&mut self, nes: &mut Nes<C>) {
+        if self.conn.take().is_some() {
+            eprintln!("gdb: detached");
+        }
+        nes.debugger().clear();
+        self.watchpoints.clear();
+        self.running = true;
+    }
+
+    fn receive<C: Controller>(&mut self, nes: &mut Nes<C>) -> io::Result<()> {
+        let mut chunk = [0; 0x400];
+        while let Some(conn) = &mut self.conn {
+            match conn.read(&mut chunk) {
+                Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+                Ok(n) => self.buf.extend_from_slice(&chunk[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            }
+        }
+
+        while self.conn.is_some() {
+            let Some(&first) = self.buf.first() else {
+                break;
+            };
+            match first {
+                0x03 => {
+                    // ctrl-c
+                    self.buf.remove(0);
+                    if self.running {
+                        self.running = false;
+                        self.send_packet("S02")?;
+                    }
+                },
+                b'$' => {
+                    // $data#checksum
+                    let Some(end) = self.buf.iter().position(|&b| b == b'#') else {
+                        break;
+                    };
+                    if self.buf.len() < end + 3 {
+                        break;
+                    }
+                    let data = self.buf[1..end].to_vec();
+                    let checksum = std::str::from_utf8(&self.buf[end+1..end+3]).ok()
+                        .and_then(|s| u8::from_str_radix(s, 16).ok());
+                    self.buf.drain(..end + 3);
+
+                    if checksum != Some(data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b))) {
+                        self.send_raw(b"-")?;
+                        continue;
+                    }
+                    self.send_raw(b"+")?;
+                    self.handle(&String::from_utf8_lossy(&data), nes)?;
+                },
+                // acks and anything else
+                _ => {
+                    self.buf.remove(0);
+                },
+            }
+        }
+        Ok(())
+    }
+
+    fn handle<C: Controller>(&mut self, packet: &str, nes: &mut Nes<C>) -> io::Result<()> {
+        let Some(cmd) = packet.chars().next() else {
+            return self.send_packet("");
+        };
+        // the command is a single ascii character
+        let Some(args) = packet.get(1..) else {
+            return self.send_packet("E01");
+        };
+        let reply = match cmd {
+            '?' => "S05".to_string(),
+            'g' => {
+                let cpu = nes.cpu();
+                format!("{:02x}{:02x}{:02x}{:02x}{:02x}{:02x}{:02x}",
+                    cpu.a, cpu.x, cpu.y, cpu.sr, cpu.sp, cpu.pc as u8, (cpu.pc >> 8) as u8)
+            },
+            'G' => match decode(args) {
+                Some(regs) if regs.len() == 7 => {
+                    let cpu = nes.cpu_mut();
+                    cpu.a = regs[0];
+                    cpu.x = regs[1];
+                    cpu.y = regs[2];
+                    cpu.sr = regs[3];
+                    cpu.sp = regs[4];
+                    cpu.pc = u16::from_le_bytes([regs[5], regs[6]]);
+                    "OK".to_string()
+                },
+                _ => "E01".to_string(),
+            },
+            'p' => {
+                let cpu = nes.cpu();
+                match parse_hex(args) {
+                    Some(0) => format!("{:02x}", cpu.a),
+                    Some(1) => format!("{:02x}", cpu.x),
+                    Some(2) => format!("{:02x}", cpu.y),
+                    Some(3) => format!("{:02x}", cpu.sr),
+                    Some(4) => format!("{:02x}", cpu.sp),
+                    Some(5) => format!("{:02x}{:02x}", cpu.pc as u8, (cpu.pc >> 8) as u8),
+                    _ => "E01".to_string(),
+                }
+            },
+            'P' => {
+                let reg = args.split_once('=')
+                    .and_then(|(reg, value)| Some((parse_hex(reg)?, decode(value)?)));
+                let cpu = nes.cpu_mut();
+                match reg {
+                    Some((0, v)) if v.len() == 1 => { cpu.a = v[0]; "OK".to_string() },
+                    Some((1, v)) if v.len() == 1 => { cpu.x = v[0]; "OK".to_string() },
+                    Some((2, v)) if v.len() == 1 => { cpu.y = v[0]; "OK".to_string() },
+                    Some((3, v)) if v.len() == 1 => { cpu.sr = v[0]; "OK".to_string() },
+                    Some((4, v)) if v.len() == 1 => { cpu.sp = v[0]; "OK".to_string() },
+                    Some((5, v)) if v.len() == 2 => { cpu.pc = u16::from_le_bytes([v[0], v[1]]); "OK".to_string() },
+                    _ => "E01".to_string(),
+                }
+            },
+            'm' => match parse_addr_len(args) {
+                Some((addr, len)) => {
+                    let mut s = String::with_capacity(len as usize * 2);
+                    for i in 0..len {
+                        let _ = write!(s, "{:02x}", nes.peek(addr.wrapping_add(i)));
+                    }
+                    s
+                },
+                None => "E01".to_string(),
+            },
+            'M' => {
+                let write = args.split_once(':')
+                    .and_then(|(range, data)| Some((parse_addr_len(range)?, decode(data)?)));
+                match write {
+                    Some(((addr, len), data)) if data.len() == len as usize => {
+                        for (i, &value) in data.iter().enumerate() {
+                            nes.poke(addr.wrapping_add(i as u16), value);
+                        }
+                        "OK".to_string()
+                    },
+                    _ => "E01".to_string(),
+                }
+            },
+            'c' | 's' => {
+                // optionally resume at a new address
+                if let Some(addr) = parse_hex(args) {
+                    nes.cpu_mut().pc = addr;
+                }
+                if cmd == 'c' {
+                    // the stop reply is sent once execution stops
+                    self.running = true;
+                    return Ok(());
+                }
+                let reason = nes.step_into();
+                self.stop_reply(reason)
+            },
+            'Z' | 'z' => self.breakpoint(cmd == 'Z', args, nes),
+            'q' => {
+                if args.starts_with("Supported") {
+                    "PacketSize=1000".to_string()
+                } else if args == "Attached" {
+                    "1".to_string()
+                } else {
+                    String::new()
+                }
+            },
+            'H' => "OK".to_string(),
+            'k' => {
+                self.detach(nes);
+                return Ok(());
+            },
+            'D' => {
+                self.send_packet("OK")?;
+                self.detach(nes);
+                return Ok(());
+            },
+            // unsupported
+            _ => String::new(),
+        };
+        self.send_packet(&reply)
+    }
+
+    // Z/z type,addr,kind
+    fn breakpoint<C: Controller>(&mut self, insert: bool, args: &str, nes: &mut Nes<C>) -> String {
+        let mut parts = args.split(',');
+        let (Some(kind), Some(addr), Some(len)) = (
+            parts.next().and_then(parse_hex),
+            parts.next().and_then(parse_hex),
+            parts.next().and_then(parse_hex),
+        ) else {
+            return "E01".to_string();
+        };
+        let kind = kind as u8;
+
+        match kind {
+            // software and hardware breakpoints are the same thing here
+            0 | 1 => {
+                if insert {
+                    nes.debugger().add_breakpoint(addr);
+                } else {
+                    nes.debugger().remove_breakpoint(addr);
+                }
+            },
+            2..=4 => {
+                if insert {
+                    let end = addr.saturating_add(len.max(1) - 1);
+                    let watchpoint = Watchpoint::new(AddressSpace::Cpu, addr..=end)
+                        .write(kind != 3)
+                        .read(kind != 2);
+                    let index = nes.debugger().add_watchpoint(watchpoint);
+                    if self.watchpoints.len() <= index {
+                        self.watchpoints.resize(index + 1, None);
+                    }
+                    self.watchpoints[index] = Some((kind, addr, len));
+                } else if let Some(index) = self.watchpoints.iter().position(|&w| w == Some((kind, addr, len))) {
+                    nes.debugger().remove_watchpoint(index);
+                    self.watchpoints[index] = None;
+                }
+            },
+            _ => return String::new(),
+        }
+        "OK".to_string()
+    }
+
+    fn stop_reply(&self, reason: StopReason) -> String {
+        if let StopReason::Watchpoint { index, addr, .. } = reason {
+            let name = match self.watchpoints.get(index) {
+                Some(Some((2, _, _))) => "watch",
+                Some(Some((3, _, _))) => "rwatch",
+                Some(Some((4, _, _))) => "awatch",
+                _ => return "S05".to_string(),
+            };
+            format!("T05{}:{:04x};", name, addr)
+        } else {
+            // sigtrap
+            "S05".to_string()
+        }
+    }
+
+    fn send_packet(&mut self, data: &str) -> io::Result<()> {
+        let checksum = data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+        self.send_raw(format!("${}#{:02x}", data, checksum).as_bytes())
+    }
+
+    fn send_raw(&mut self, data: &[u8]) -> io::Result<()> {
+        let Some(conn) = &mut self.conn else {
+            return Ok(());
+        };
+        // replies are small, so just block until they're sent
+        conn.set_nonblocking(false)?;
+        conn.write_all(data)?;
+        conn.set_nonblocking(true)
+    }
+}
+
+fn parse_hex(s: &str) -> Option<u16> {
+    u16::from_str_radix(s, 16).ok()
+}
+
+// addr,len
+fn parse_addr_len(s: &str) -> Option<(u16, u16)> {
+    let (addr, len) = s.split_once(',')?;
+    Some((parse_hex(addr)?, parse_hex(len)?))
+}
+
+fn decode(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len()).step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i+2)?, 16).ok())
+        .collect()
+}
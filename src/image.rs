@@ -0,0 +1,118 @@
+// an rgba image, 4 bytes a pixel with no padding between rows
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![0; width * height * 4],
+        }
+    }
+
+    // takes an xrgb8888 colour like the framebuffer's
+    pub fn set(&mut self, x: usize, y: usize, colour: u32) {
+        let i = (y * self.width + x) * 4;
+        self.pixels[i..i + 4].copy_from_slice(&[(colour >> 16) as u8, (colour >> 8) as u8, colour as u8, 0xff]);
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> u32 {
+        let i = (y * self.width + x) * 4;
+        let p = &self.pixels[i..i + 4];
+        ((p[3] as u32) << 24) | ((p[0] as u32) << 16) | ((p[1] as u32) << 8) | (p[2] as u32)
+    }
+
+    // copies another image in with its top left corner at x, y,
+    // anything that doesn't fit is cut off
+    pub fn blit(&mut self, image: &Image, x: usize, y: usize) {
+        let width = image.width.min(self.width.saturating_sub(x));
+        for row in 0..image.height.min(self.height.saturating_sub(y)) {
+            let src = row * image.width * 4;
+            let dst = ((y + row) * self.width + x) * 4;
+            self.pixels[dst..dst + width * 4].copy_from_slice(&image.pixels[src..src + width * 4]);
+        }
+    }
+
+    // scales up by a whole number
+    pub fn scale(&self, n: usize) -> Image {
+        let mut image = Image::new(self.width * n, self.height * n);
+        for y in 0..image.height {
+            for x in 0..image.width {
+                image.set(x, y, self.get(x / n, y / n));
+            }
+        }
+        image
+    }
+
+    // an uncompressed png, deflate's stored blocks keep it simple
+    pub fn to_png(&self) -> Vec<u8> {
+        // each row starts with its filter type, 0 is none
+        let mut raw = Vec::with_capacity((self.width * 4 + 1) * self.height);
+        for row in self.pixels.chunks_exact(self.width * 4) {
+            raw.push(0);
+            raw.extend_from_slice(row);
+        }
+
+        // zlib header, no compression
+        let mut zlib = vec![0x78, 0x01];
+        let mut blocks = raw.chunks(0xffff).peekable();
+        if blocks.peek().is_none() {
+            zlib.extend_from_slice(&[0x01, 0x00, 0x00, 0xff, 0xff]);
+        }
+        while let Some(block) = blocks.next() {
+            let len = block.len() as u16;
+            zlib.push(blocks.peek().is_none() as u8);
+            zlib.extend_from_slice(&len.to_le_bytes());
+            zlib.extend_from_slice(&(!len).to_le_bytes());
+            zlib.extend_from_slice(block);
+        }
+        zlib.extend_from_slice(&adler32(&raw).to_be_bytes());
+
+        let mut ihdr = Vec::with_capacity(13);
+        ihdr.extend_from_slice(&(self.width as u32).to_be_bytes());
+        ihdr.extend_from_slice(&(self.height as u32).to_be_bytes());
+        // 8 bits a channel, rgba, then the default compression,
+        // filtering and no interlacing
+        ihdr.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        chunk(&mut png, b"IHDR", &ihdr);
+        chunk(&mut png, b"IDAT", &zlib);
+        chunk(&mut png, b"IEND", &[]);
+        png
+    }
+}
+
+fn chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &b in data {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = if (crc & 1) != 0 { (crc >> 1) ^ 0xedb88320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &d in data {
+        a = (a + d as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
@@ -1,18 +1,34 @@
-use std::{cell::Cell, ptr::NonNull, slice};
+use std::{cell::Cell, io, ptr::NonNull, slice};
 
 use apu::Apu;
+use cdl::Cdl;
 use cpu::Cpu;
-use mem::Memory;
-use ppu::Ppu;
+use debug::Debugger;
+use mem::{Mem, Memory};
+use palette::Palette;
+use ppu::{Mirroring, Ppu};
+use profile::Profiler;
+use region::Region;
+use trace::Tracer;
 
 mod retro;
-mod nsf;
 mod ffi;
 
 pub mod apu;
+pub mod cdl;
 pub mod cpu;
+pub mod debug;
+pub mod disasm;
+pub mod gdb;
+pub mod image;
 pub mod mem;
+pub mod ntsc;
+pub mod palette;
 pub mod ppu;
+pub mod profile;
+pub mod region;
+pub mod trace;
+pub mod video;
 
 pub trait Controller {
     fn poll(&mut self);
@@ -34,96 +50,260 @@ pub struct Nes<C: Controller> {
     ppu: Box<Ppu>,
 
     cycles: Box<Cell<usize>>,
+    debugger: Box<Debugger>,
+    cdl: Option<Box<Cdl>>,
+
+    // the ppu's output converted to xrgb8888
+    palette: Palette,
+    framebuffer: Box<[u32]>,
+
+    // rom sizes (chr_len is 0 for chr ram)
+    prg_len: usize,
+    chr_len: usize,
+    // what the header asks for, and what's being emulated
+    header_region: Region,
+    region: Region,
 }
 
 impl<C: Controller> Nes<C> {
     pub fn load_from_memory(game: &[u8]) -> Option<Self> {
-        // todo: check ines header
+        if game.len() < 0x10 {
+            return None;
+        }
         let hdr = &game[..0x10];
         if hdr[0] != b'N' || hdr[1] != b'E' || hdr[2] != b'S' || hdr[3] != b'\x1a' {
             return None;
         }
 
         // size of prg rom in 16 kb units
-        let prg_len = hdr[4];
-        if prg_len != 0x02 {
+        let prg_len = (hdr[4] as usize) << 14;
+        if prg_len != 0x4000 && prg_len != 0x8000 {
             return None;
         }
 
         // size of chr rom in 8 kb units
-        let chr_len = hdr[5];
-        if chr_len != 0x01 {
+        // (0 means the cartridge has 8 kb of chr ram instead)
+        let chr_len = (hdr[5] as usize) << 13;
+        if chr_len > 0x2000 {
             return None;
         }
 
-        if hdr[6] != 0x01 {
+        // only nrom is supported
+        let mapper = (hdr[6] >> 4) | (hdr[7] & 0xf0);
+        if mapper != 0 {
             return None;
         }
 
-        if hdr[7] != 0x00 {
+        // four-screen vram isn't supported
+        if (hdr[6] & 0x08) != 0 {
             return None;
         }
+        let mirroring = if (hdr[6] & 0x01) != 0 { Mirroring::Vertical } else { Mirroring::Horizontal };
+        let region = Region::from_header(hdr);
 
-        if hdr[8] != 0x00 {
+        // skip the trainer if present
+        let prg_start = if (hdr[6] & 0x04) != 0 { 0x10 + 0x200 } else { 0x10 };
+        let chr_start = prg_start + prg_len;
+        if game.len() < chr_start + chr_len {
             return None;
         }
 
-        if hdr[9] != 0x00 {
-            return None;
-        }
-
-        if hdr[10] != 0x00 {
-            return None;
-        }
-
-        let text = &game[0x10..0x10+0x8000];
-        let chr = &game[0x10+0x8000..0x10+0x8000+0x2000];
+        let prg = &game[prg_start..prg_start+prg_len];
+        let chr_ram = chr_len == 0;
+        let chr = if chr_ram { &[0; 0x2000][..] } else { &game[chr_start..chr_start+chr_len] };
 
         let mut cycles = Box::new(Cell::new(0));
-        let mut apu = Box::new(Apu::new());
-        let mut ppu = Box::new(Ppu::new(chr, NonNull::new(cycles.as_mut()).unwrap()));
-        let mut mem = Box::new(Memory::new(text, NonNull::new(apu.as_mut()).unwrap(), NonNull::new(ppu.as_mut()).unwrap()));
+        let mut apu = Box::new(Apu::new(NonNull::new(cycles.as_mut()).unwrap()));
+        let mut ppu = Box::new(Ppu::new(chr, chr_ram, mirroring, NonNull::new(cycles.as_mut()).unwrap()));
+        let mut mem = Box::new(Memory::new(prg, NonNull::new(apu.as_mut()).unwrap(), NonNull::new(ppu.as_mut()).unwrap()));
         let cpu = Box::new(Cpu::new(NonNull::new(mem.as_mut()).unwrap(), NonNull::new(cycles.as_mut()).unwrap()));
-        Some(Self {
+        let mut debugger = Box::new(Debugger::new());
+        mem.attach_debugger(NonNull::new(debugger.as_mut()));
+        ppu.attach_debugger(NonNull::new(debugger.as_mut()));
+        let mut nes = Self {
             mem,
             apu,
             cpu,
             ppu,
             cycles,
-        })
+            debugger,
+            cdl: None,
+            palette: Palette::default(),
+            framebuffer: vec![0; 256 * 240].into_boxed_slice(),
+            prg_len,
+            chr_len,
+            header_region: region,
+            region: Region::Ntsc,
+        };
+        nes.set_region(None);
+        Some(nes)
+    }
+
+    pub fn reset(&mut self) {
+        self.apu.reset();
+        self.cpu.reset();
     }
 
     pub fn run(&mut self) {
         // runs for one frame
-        let mut nmi_sent = false;
-        let mut screen_drawn = false;
-        while self.cycles.get() < 29781 {
-            if self.cycles.get() > 27252 && screen_drawn == false {
-                // the entire screen has been drawn
-                screen_drawn = true;
-                self.ppu.clear();
-                self.ppu.draw();
-            }
-            if self.cycles.get() >= 27280 && nmi_sent == false {
-                nmi_sent = true;
-                if (self.ppu.ppuctrl & 0x80) != 0 {
-                    eprintln!("nmi!");
-                    self.cpu.nmi();
-                }
+        while !self.step() {}
+    }
+
+    // runs a single instruction, returns true when a frame has finished
+    pub fn step(&mut self) -> bool {
+        self.frame_events();
+        self.cpu.execute();
+        self.end_step()
+    }
+
+    // sends the nmi if the ppu has raised one, or an irq from the apu,
+    // returns true if an nmi was taken
+    fn frame_events(&mut self) -> bool {
+        if self.ppu.nmi() {
+            self.cpu.nmi();
+            return true;
+        }
+        if self.apu.irq() {
+            self.cpu.irq();
+        }
+        false
+    }
+
+    // returns true when a frame has finished
+    fn end_step(&mut self) -> bool {
+        if self.ppu.frame_done() {
+            self.ppu.start_frame();
+            if let Some(profiler) = &mut self.cpu.profiler {
+                profiler.end_frame(self.cycles.get());
             }
-            self.cpu.execute();
+            true
+        } else {
+            false
         }
-        self.cycles.set(0);
     }
 
+    pub fn cpu(&self) -> &Cpu<C> {
+        &self.cpu
+    }
+
+    pub fn cpu_mut(&mut self) -> &mut Cpu<C> {
+        &mut self.cpu
+    }
+
+    pub fn ppu(&self) -> &Ppu {
+        &self.ppu
+    }
+
+    pub fn apu(&self) -> &Apu {
+        &self.apu
+    }
+
+    pub fn apu_mut(&mut self) -> &mut Apu {
+        &mut self.apu
+    }
+
+    // read memory without side effects
+    pub fn peek(&self, addr: u16) -> u8 {
+        self.mem.peek(addr)
+    }
+
+    // write memory without side effects
+    pub fn poke(&mut self, addr: u16, value: u8) {
+        self.mem.poke(addr, value)
+    }
+
+    pub fn set_sprite_limit(&mut self, limit: bool) {
+        self.ppu.set_sprite_limit(limit);
+    }
+
+    // none goes by the rom header
+    pub fn set_region(&mut self, region: Option<Region>) {
+        let region = region.unwrap_or(self.header_region);
+        if region != self.region {
+            self.region = region;
+            self.ppu.set_region(region);
+            self.apu.set_region(region);
+        }
+    }
+
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+    }
+
+    pub fn palette(&self) -> &Palette {
+        &self.palette
+    }
+
+    // xrgb8888 in native byte order, 256x240
     pub fn framebuffer(&mut self) -> &[u8] {
-        self.ppu.framebuffer().as_slice()
+        self.palette.convert(self.ppu.pixels(), &mut self.framebuffer);
+        unsafe { slice::from_raw_parts(self.framebuffer.as_ptr() as *const u8, self.framebuffer.len() * 4) }
+    }
+
+    // 256x240 palette indices, the colour in the low 6 bits and the
+    // emphasis bits of ppumask above
+    pub fn indexed_framebuffer(&self) -> &[u16] {
+        self.ppu.pixels()
     }
 
     pub fn play_audio(&mut self, buf: &mut [i16]) {
         self.apu.tick(buf)
     }
 
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) {
+        if let Some(tracer) = &mut self.cpu.tracer {
+            let _ = tracer.flush();
+        }
+        self.cpu.tracer = tracer;
+    }
+
+    pub fn set_profiler(&mut self, mut profiler: Option<Profiler>) -> Option<Profiler> {
+        if let Some(profiler) = &mut profiler {
+            profiler.start(self.cycles.get());
+        }
+        std::mem::replace(&mut self.cpu.profiler, profiler)
+    }
+
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.cpu.profiler.as_ref()
+    }
+
+    // starts logging code and data accesses, keeping any existing log
+    pub fn start_cdl(&mut self) {
+        if self.cdl.is_none() {
+            self.set_cdl(Some(Box::new(Cdl::new(self.prg_len, self.chr_len))));
+        }
+    }
+
+    // continues logging on top of a saved .cdl file
+    pub fn load_cdl(&mut self, data: &[u8]) -> io::Result<()> {
+        let cdl = Cdl::load(data, self.prg_len, self.chr_len)?;
+        self.set_cdl(Some(Box::new(cdl)));
+        Ok(())
+    }
+
+    pub fn stop_cdl(&mut self) -> Option<Box<Cdl>> {
+        let cdl = self.cdl.take();
+        self.set_cdl(None);
+        cdl
+    }
+
+    pub fn cdl(&self) -> Option<&Cdl> {
+        self.cdl.as_deref()
+    }
+
+    fn set_cdl(&mut self, cdl: Option<Box<Cdl>>) {
+        self.cdl = cdl;
+        let ptr = self.cdl.as_deref_mut().map(NonNull::from);
+        self.cpu.attach_cdl(ptr);
+        self.mem.attach_cdl(ptr);
+        self.ppu.attach_cdl(ptr);
+    }
+
     pub fn connect(&mut self, port: usize, controller: C) {
         self.mem.connect_controller(port, controller);
     }
@@ -1,8 +1,8 @@
 use core::slice;
-use std::{cell::Cell, collections::VecDeque, env, ffi::{c_char, c_int, c_void}, fs, mem::MaybeUninit, process::ExitCode, ptr::{self, NonNull}, time::{Duration, Instant}};
+use std::{cell::Cell, collections::VecDeque, env, ffi::{c_char, c_int, c_void}, fs::{self, File}, io::BufWriter, mem::MaybeUninit, process::ExitCode, ptr::{self, NonNull}, time::{Duration, Instant}};
 
-use nes::Nes;
-use sdl3::{event::Event, keyboard::Keycode, sys::{audio::*, events::*, init::*, main::*, pixels::*, render::*, video::*}};
+use nes::{gdb::GdbStub, image::Image, ntsc::{self, Filter, Preset}, palette::{Ntsc, Palette}, profile::Profiler, region::Region, trace::{TraceFormat, Tracer}, video::{Aspect, Overscan, Screen}, Nes};
+use sdl3::{event::{Event, WindowEvent}, keyboard::Keycode, sys::{audio::*, events::*, init::*, main::*, pixels::*, render::*, surface::*, video::*}};
 
 struct App {
     // video
@@ -14,8 +14,12 @@ struct App {
     texture_width: usize,
     texture_height: usize,
     nes_width: usize,
-    nes_height: usize,
     nes_pitch: usize,
+    // the ntsc filter's output, if it's on
+    filter: Option<Filter>,
+    filtered: Box<[u32]>,
+    // cropping and aspect ratio
+    screen: Screen,
 
     // audio
     stream: *mut SDL_AudioStream,
@@ -26,6 +30,15 @@ struct App {
 
     // debug
     now: VecDeque<Instant>,
+    gdb: Option<GdbStub>,
+    cdl: Option<String>,
+    profile: Option<String>,
+    // ppu viewers, f1 opens and closes them, f2 saves them as png,
+    // f3 changes the palette used for the pattern tables
+    debug_window: *mut SDL_Window,
+    debug_renderer: *mut SDL_Renderer,
+    debug_texture: *mut SDL_Texture,
+    debug_palette: usize,
 }
 
 enum AppResult<T> {
@@ -34,21 +47,212 @@ enum AppResult<T> {
     Failure,
 }
 
+struct Args {
+    game: String,
+    trace: Option<String>,
+    trace_range: Option<(u16, u16)>,
+    trace_format: Option<String>,
+    gdb: Option<u16>,
+    cdl: Option<String>,
+    profile: Option<String>,
+    no_sprite_limit: bool,
+    // none goes by the rom header
+    region: Option<Region>,
+    palette: Option<PaletteArg>,
+    filter: Option<Preset>,
+    screen: Screen,
+}
+
+enum PaletteArg {
+    File(String),
+    Ntsc(Ntsc),
+}
+
+// <hue>,<saturation>,<contrast>,<brightness>,<gamma>, any left out
+// keep their defaults
+fn parse_ntsc(settings: &str) -> Option<Ntsc> {
+    let mut ntsc = Ntsc::default();
+    if settings.is_empty() {
+        return Some(ntsc);
+    }
+    let fields = [&mut ntsc.hue, &mut ntsc.saturation, &mut ntsc.contrast, &mut ntsc.brightness, &mut ntsc.gamma];
+    let values = settings.split(',');
+    if values.clone().count() > fields.len() {
+        return None;
+    }
+    for (field, value) in fields.into_iter().zip(values) {
+        *field = value.parse().ok()?;
+    }
+    Some(ntsc)
+}
+
+impl Args {
+    fn parse() -> Option<Self> {
+        let mut args = env::args().skip(1);
+        let mut game = None;
+        let mut trace = None;
+        let mut trace_range = None;
+        let mut trace_format = None;
+        let mut gdb = None;
+        let mut cdl = None;
+        let mut profile = None;
+        let mut no_sprite_limit = false;
+        let mut region = None;
+        let mut palette = None;
+        let mut filter = None;
+        let mut screen = Screen::default();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--trace" => trace = Some(args.next()?),
+                "--trace-range" => {
+                    // e.g. c000-c0ff
+                    let range = args.next()?;
+                    let (start, end) = range.split_once('-')?;
+                    trace_range = Some((
+                        u16::from_str_radix(start.trim_start_matches('$'), 16).ok()?,
+                        u16::from_str_radix(end.trim_start_matches('$'), 16).ok()?,
+                    ));
+                },
+                "--trace-format" => trace_format = Some(args.next()?),
+                "--gdb" => gdb = Some(args.next()?.parse().ok()?),
+                "--cdl" => cdl = Some(args.next()?),
+                "--profile" => profile = Some(args.next()?),
+                "--no-sprite-limit" => no_sprite_limit = true,
+                "--region" => region = match args.next()?.as_str() {
+                    "auto" => None,
+                    name => Some(Region::from_name(name)?),
+                },
+                "--palette" => {
+                    let arg = args.next()?;
+                    palette = Some(if arg == "ntsc" || arg.starts_with("ntsc:") {
+                        PaletteArg::Ntsc(parse_ntsc(arg.trim_start_matches("ntsc").trim_start_matches(':'))?)
+                    } else {
+                        PaletteArg::File(arg)
+                    });
+                },
+                "--filter" => filter = Some(Preset::from_name(&args.next()?)?),
+                "--crop" => {
+                    // top,bottom,left,right
+                    let crop = args.next()?;
+                    let mut edges = crop.split(',').map(|edge| edge.parse().ok());
+                    screen.overscan = Overscan {
+                        top: edges.next()??,
+                        bottom: edges.next()??,
+                        left: edges.next()??,
+                        right: edges.next()??,
+                    };
+                    if edges.next().is_some() {
+                        return None;
+                    }
+                },
+                "--aspect" => screen.aspect = Aspect::from_name(&args.next()?)?,
+                "--integer-scale" => screen.integer_scale = true,
+                _ if game.is_none() && !arg.starts_with("--") => game = Some(arg),
+                _ => return None,
+            }
+        }
+        Some(Self {
+            game: game?,
+            trace,
+            trace_range,
+            trace_format,
+            gdb,
+            cdl,
+            profile,
+            no_sprite_limit,
+            region,
+            palette,
+            filter,
+            screen,
+        })
+    }
+}
+
 impl App {
     fn init() -> AppResult<Box<Self>> {
         // check if we have provided an argument
-        let args: Vec<String> = env::args().collect();
-        if args.len() != 2 {
+        let Some(args) = Args::parse() else {
+            eprintln!("usage: nes <rom.nes> [--trace <file>] [--trace-range <start>-<end>] [--trace-format <format>] [--gdb <port>] [--cdl <file>] [--profile <file>] [--no-sprite-limit] [--region <auto|ntsc|pal|dendy>] [--palette <file.pal|ntsc[:<hue>,<saturation>,<contrast>,<brightness>,<gamma>]>] [--filter <composite|svideo|rgb|monochrome>] [--crop <top>,<bottom>,<left>,<right>] [--aspect <4:3|8:7|1:1|stretch>] [--integer-scale]");
             return AppResult::Failure;
-        }
+        };
         
         // controller init
         let controller_state = Cell::new(ControllerState::new());
 
         // nes init
-        let game = fs::read(&args[1]).unwrap();
-        let nes = Nes::load_from_memory(&game[..])
+        let game = fs::read(&args.game).unwrap();
+        let mut nes = Nes::load_from_memory(&game[..])
             .unwrap();
+        nes.set_region(args.region);
+
+        if let Some(path) = &args.trace {
+            let file = match File::create(path) {
+                Ok(file) => file,
+                Err(e) => {
+                    eprintln!("{}: {}", path, e);
+                    return AppResult::Failure;
+                },
+            };
+            let mut tracer = Tracer::new(Box::new(BufWriter::new(file)));
+            if let Some((start, end)) = args.trace_range {
+                tracer = tracer.range(start..=end);
+            }
+            match args.trace_format.as_deref() {
+                None | Some("nestest") => {},
+                Some(format) => tracer = tracer.format(TraceFormat::Custom(format.to_string())),
+            }
+            nes.set_tracer(Some(tracer));
+        }
+
+        if let Some(path) = &args.cdl {
+            // keep adding to an existing log
+            match fs::read(path) {
+                Ok(data) => if let Err(e) = nes.load_cdl(&data) {
+                    eprintln!("{}: {}", path, e);
+                    return AppResult::Failure;
+                },
+                Err(_) => nes.start_cdl(),
+            }
+        }
+
+        match &args.palette {
+            Some(PaletteArg::File(path)) => match fs::read(path).and_then(|data| Palette::load(&data)) {
+                Ok(palette) => nes.set_palette(palette),
+                Err(e) => {
+                    eprintln!("{}: {}", path, e);
+                    return AppResult::Failure;
+                },
+            },
+            Some(PaletteArg::Ntsc(ntsc)) => nes.set_palette(Palette::ntsc(ntsc)),
+            None => {},
+        }
+
+        // the filter picks up the generated palette's settings
+        let filter = args.filter.map(|preset| match &args.palette {
+            Some(PaletteArg::Ntsc(ntsc)) => Filter::new(preset, *ntsc),
+            _ => Filter::new(preset, Ntsc::default()),
+        });
+        let nes_width = if filter.is_some() { ntsc::WIDTH } else { 256 };
+
+        nes.set_sprite_limit(!args.no_sprite_limit);
+
+        if args.profile.is_some() {
+            nes.set_profiler(Some(Profiler::new()));
+        }
+
+        let gdb = match args.gdb {
+            Some(port) => match GdbStub::bind(port) {
+                Ok(gdb) => {
+                    eprintln!("gdb: waiting for a connection on port {}", port);
+                    Some(gdb)
+                },
+                Err(e) => {
+                    eprintln!("gdb: {}", e);
+                    return AppResult::Failure;
+                },
+            },
+            None => None,
+        };
         
         if !unsafe { SDL_Init(SDL_INIT_VIDEO | SDL_INIT_AUDIO) } {
             return AppResult::Failure;
@@ -61,9 +265,11 @@ impl App {
 
             texture_width: 0,
             texture_height: 0,
-            nes_width: 256,
-            nes_height: 240,
-            nes_pitch: 256*4,
+            nes_width,
+            nes_pitch: nes_width*4,
+            filter,
+            filtered: vec![0; ntsc::WIDTH*ntsc::HEIGHT].into_boxed_slice(),
+            screen: args.screen,
             
             stream: ptr::null_mut(),
 
@@ -71,12 +277,21 @@ impl App {
             controller_state,
 
             now: VecDeque::with_capacity(2048),
+            gdb,
+            cdl: args.cdl,
+            profile: args.profile,
+            debug_window: ptr::null_mut(),
+            debug_renderer: ptr::null_mut(),
+            debug_texture: ptr::null_mut(),
+            debug_palette: 0,
         });
 
         let controller = Controller::new(NonNull::new(&raw mut state.controller_state).unwrap());
         state.nes.connect(0, controller);
 
-        if !unsafe { SDL_CreateWindowAndRenderer(c"nes".as_ptr(), 240*4, 240*3, SDL_WINDOW_HIGH_PIXEL_DENSITY, &mut state.window, &mut state.renderer) } {
+        // three times the height, and as wide as the aspect ratio says
+        let window_width = state.screen.ratio().map_or(240*4, |ratio| (240.0*3.0*ratio).round() as c_int);
+        if !unsafe { SDL_CreateWindowAndRenderer(c"nes".as_ptr(), window_width, 240*3, SDL_WINDOW_HIGH_PIXEL_DENSITY, &mut state.window, &mut state.renderer) } {
             return AppResult::Failure;
         }
 
@@ -126,39 +341,67 @@ impl App {
                 eprintln!("framerate: {}", avg);
             }
 
-            self.nes.run();
-
-            // queue up new audio
-            let mut buf = [0; 798*2];
-            self.nes.play_audio(&mut buf);
-            unsafe { SDL_PutAudioStreamData(self.stream, &buf as *const [i16] as _, (buf.len()*size_of::<i16>()) as _) };
-
-            let src = self.nes.framebuffer();
-            let mut pixels = MaybeUninit::uninit();
-            let mut pitch = MaybeUninit::uninit();
-            if unsafe { SDL_LockTexture(self.texture, ptr::null(), pixels.as_mut_ptr(), pitch.as_mut_ptr()) } {
-                let pixels = unsafe { pixels.assume_init() } as *mut u8;
-                let pitch = unsafe { pitch.assume_init() } as usize;
-
-                // sdl doesn't have nearest neighbor filtering...
-                // do it ourselves
-                let dst = unsafe { slice::from_raw_parts_mut(
-                    pixels, self.texture_height * pitch
-                ) };
-                for y in 0..self.texture_height {
-                    let dst_y = y*pitch;
-                    let src_y = (y*self.nes_height/self.texture_height)*self.nes_pitch;
-                    for x in 0..self.texture_width {
-                        // could be more optimizied?
-                        let dst_x = x<<2;
-                        let src_x = (x*self.nes_width/self.texture_width)<<2;
-                        dst[dst_y+dst_x] = src[src_y+src_x];
-                        dst[dst_y+dst_x+1] = src[src_y+src_x+1];
-                        dst[dst_y+dst_x+2] = src[src_y+src_x+2];
-                        dst[dst_y+dst_x+3] = src[src_y+src_x+3];
+            // gdb decides when the emulator runs
+            let frame = match &mut self.gdb {
+                Some(gdb) => gdb.update(&mut self.nes),
+                None => {
+                    self.nes.run();
+                    true
+                },
+            };
+
+            if frame {
+                // queue up new audio
+                let mut buf = vec![0; (48000.0 / self.nes.region().fps()) as usize * 2];
+                self.nes.play_audio(&mut buf);
+                unsafe { SDL_PutAudioStreamData(self.stream, buf.as_ptr() as _, (buf.len()*size_of::<i16>()) as _) };
+
+                let src = match &mut self.filter {
+                    Some(filter) => {
+                        filter.apply(self.nes.indexed_framebuffer(), &mut self.filtered);
+                        unsafe { slice::from_raw_parts(self.filtered.as_ptr() as *const u8, self.filtered.len()*4) }
+                    },
+                    None => self.nes.framebuffer(),
+                };
+                let mut pixels = MaybeUninit::uninit();
+                let mut pitch = MaybeUninit::uninit();
+                if unsafe { SDL_LockTexture(self.texture, ptr::null(), pixels.as_mut_ptr(), pitch.as_mut_ptr()) } {
+                    let pixels = unsafe { pixels.assume_init() } as *mut u8;
+                    let pitch = unsafe { pitch.assume_init() } as usize;
+
+                    // sdl doesn't have nearest neighbor filtering...
+                    // do it ourselves
+                    let dst = unsafe { slice::from_raw_parts_mut(
+                        pixels, self.texture_height * pitch
+                    ) };
+                    // the cropped part of the picture, the filter's
+                    // output is wider than the nes's 256 pixels
+                    let overscan = self.screen.overscan;
+                    let src_left = overscan.left*self.nes_width/256;
+                    let src_width = overscan.width()*self.nes_width/256;
+                    let (view_x, view_y, view_width, view_height) = self.screen.viewport(self.texture_width, self.texture_height);
+                    for y in 0..self.texture_height {
+                        let dst_y = y*pitch;
+                        let row = &mut dst[dst_y..dst_y+(self.texture_width<<2)];
+                        if !(view_y..view_y+view_height).contains(&y) {
+                            // black bars
+                            row.fill(0);
+                            continue;
+                        }
+                        let src_y = (overscan.top + (y-view_y)*overscan.height()/view_height)*self.nes_pitch;
+                        for x in 0..self.texture_width {
+                            // could be more optimizied?
+                            let dst_x = x<<2;
+                            if !(view_x..view_x+view_width).contains(&x) {
+                                row[dst_x..dst_x+4].fill(0);
+                                continue;
+                            }
+                            let src_x = (src_left + (x-view_x)*src_width/view_width)<<2;
+                            row[dst_x..dst_x+4].copy_from_slice(&src[src_y+src_x..src_y+src_x+4]);
+                        }
                     }
+                    unsafe { SDL_UnlockTexture(self.texture) };
                 }
-                unsafe { SDL_UnlockTexture(self.texture) };
             }
         }
 
@@ -166,15 +409,90 @@ impl App {
 
         unsafe { SDL_RenderPresent(self.renderer) };
 
+        if !self.debug_window.is_null() {
+            let views = self.debug_views();
+            unsafe { SDL_UpdateTexture(self.debug_texture, ptr::null(), views.pixels.as_ptr() as _, (views.width*4) as _) };
+            unsafe { SDL_RenderTexture(self.debug_renderer, self.debug_texture, ptr::null(), ptr::null()) };
+            unsafe { SDL_RenderPresent(self.debug_renderer) };
+        }
+
         AppResult::Continue(())
     }
 
+    // nametables on the left, then the pattern tables, sprites and palette
+    fn debug_views(&self) -> Image {
+        let ppu = self.nes.ppu();
+        let palette = self.nes.palette();
+        let mut views = Image::new(768, 480);
+        views.blit(&ppu.nametable_view(palette), 0, 0);
+        views.blit(&ppu.pattern_table_view(0, self.debug_palette, palette), 512, 0);
+        views.blit(&ppu.pattern_table_view(1, self.debug_palette, palette), 640, 0);
+        views.blit(&ppu.oam_view(palette).scale(2), 512, 128);
+        views.blit(&ppu.palette_view(palette), 640, 128);
+        views
+    }
+
+    fn toggle_debug_window(&mut self) {
+        if !self.debug_window.is_null() {
+            unsafe { SDL_DestroyTexture(self.debug_texture) };
+            unsafe { SDL_DestroyRenderer(self.debug_renderer) };
+            unsafe { SDL_DestroyWindow(self.debug_window) };
+            self.debug_window = ptr::null_mut();
+            return;
+        }
+        if !unsafe { SDL_CreateWindowAndRenderer(c"ppu".as_ptr(), 768, 480, SDL_WINDOW_RESIZABLE, &mut self.debug_window, &mut self.debug_renderer) } {
+            self.debug_window = ptr::null_mut();
+            return;
+        }
+        self.debug_texture = unsafe { SDL_CreateTexture(self.debug_renderer, SDL_PIXELFORMAT_RGBA32, SDL_TEXTUREACCESS_STREAMING, 768, 480) };
+        unsafe { SDL_SetTextureScaleMode(self.debug_texture, SDL_SCALEMODE_NEAREST) };
+    }
+
+    // into the current directory, with the sprites' attributes on stderr
+    fn dump_debug_views(&self) {
+        let ppu = self.nes.ppu();
+        let palette = self.nes.palette();
+        let views = [
+            ("nametables.png", ppu.nametable_view(palette)),
+            ("pattern0.png", ppu.pattern_table_view(0, self.debug_palette, palette)),
+            ("pattern1.png", ppu.pattern_table_view(1, self.debug_palette, palette)),
+            ("oam.png", ppu.oam_view(palette)),
+            ("palette.png", ppu.palette_view(palette)),
+        ];
+        for (path, image) in views {
+            match fs::write(path, image.to_png()) {
+                Ok(()) => eprintln!("wrote {}", path),
+                Err(e) => eprintln!("{}: {}", path, e),
+            }
+        }
+        for (i, sprite) in ppu.oam_entries().iter().enumerate() {
+            eprintln!("{:02}: x={:3} y={:3} tile=${:02x} palette={}{}{}{}", i, sprite.x, sprite.y, sprite.tile, sprite.palette,
+                if sprite.behind { " behind" } else { "" },
+                if sprite.flip_h { " flip-h" } else { "" },
+                if sprite.flip_v { " flip-v" } else { "" });
+        }
+    }
+
     fn event(&mut self, event: &mut Event) -> AppResult<()> {
         // looks like event is called on the main thread (on macos)
         let mut controller_state = self.controller_state.get();
 
         match event {
             Event::Quit {..} => return AppResult::Success,
+            // closing the main window quits even with the debug window open
+            Event::Window { window_id, win_event: WindowEvent::CloseRequested, .. } => {
+                if !self.debug_window.is_null() && *window_id == unsafe { SDL_GetWindowID(self.debug_window) } {
+                    self.toggle_debug_window();
+                } else {
+                    return AppResult::Success;
+                }
+            },
+
+            Event::KeyDown { keycode: Some(Keycode::F1), repeat: false, .. } => self.toggle_debug_window(),
+            Event::KeyDown { keycode: Some(Keycode::F2), repeat: false, .. } => self.dump_debug_views(),
+            Event::KeyDown { keycode: Some(Keycode::F3), repeat: false, .. } => {
+                self.debug_palette = (self.debug_palette + 1) & 0x7;
+            },
 
             Event::KeyDown { keycode: Some(Keycode::X), .. } => {
                 controller_state.set_a(true);
@@ -234,7 +552,24 @@ impl App {
         AppResult::Continue(())
     }
 
-    fn quit(app: Box<Self>, result: AppResult<()>) {
+    fn quit(mut app: Box<Self>, result: AppResult<()>) {
+        // flush the trace file
+        app.nes.set_tracer(None);
+        if let (Some(path), Some(cdl)) = (&app.cdl, app.nes.cdl()) {
+            if let Err(e) = fs::write(path, cdl.to_bytes()) {
+                eprintln!("{}: {}", path, e);
+            }
+        }
+        // folded stacks for flamegraph.pl, with a summary on stderr
+        if let (Some(path), Some(profiler)) = (&app.profile, app.nes.profiler()) {
+            let _ = profiler.write_report(&mut std::io::stderr());
+            if let Err(e) = File::create(path).and_then(|mut file| profiler.write_folded(&mut file)) {
+                eprintln!("{}: {}", path, e);
+            }
+        }
+        if !app.debug_window.is_null() {
+            app.toggle_debug_window();
+        }
         unsafe { SDL_DestroyTexture(app.texture) };
         unsafe { SDL_DestroyAudioStream(app.stream) };
     }
@@ -1,11 +1,16 @@
 use std::ptr::NonNull;
 
-use crate::{apu::Apu, ppu::Ppu, Controller};
+use crate::{apu::Apu, cdl::Cdl, debug::{Access, AddressSpace, Debugger}, disasm::OPCODES, ppu::Ppu, Controller};
 
 pub trait Mem {
     fn read(&mut self, addr: u16) -> u8;
     fn write(&mut self, addr: u16, value: u8);
 
+    // read without any side effects (for debugging)
+    fn peek(&self, addr: u16) -> u8;
+    // write without any side effects (for debugging)
+    fn poke(&mut self, addr: u16, value: u8);
+
     #[inline]
     fn read16(&mut self, addr: u16) -> u16 {
         // default
@@ -18,14 +23,34 @@ pub trait Mem {
         self.write(addr, value as u8);
         self.write(addr.wrapping_add(1), (value >> 8) as u8)
     }
+    #[inline]
+    fn peek16(&self, addr: u16) -> u16 {
+        // default
+        (self.peek(addr) as u16) |
+            ((self.peek(addr.wrapping_add(1)) as u16) << 8)
+    }
 }
 
 pub struct Memory<C: Controller> {
     mem: Box<[u8]>,
+    sram: Box<[u8]>,
+    // prg rom, 16 kb roms are mirrored into both halves of $8000-$ffff
     text: Box<[u8]>,
-    
+    prg_len: usize,
+    // opcode and operand of instructions in prg rom, filled in as they're executed
+    decoded: Box<[Option<(u8, u16)>]>,
+
     apu: NonNull<Apu>,
     ppu: NonNull<Ppu>,
+    debugger: Option<NonNull<Debugger>>,
+    cdl: Option<NonNull<Cdl>>,
+
+    // page written to $4014, the cpu does the copy
+    oam_dma: Option<u8>,
+
+    // the last value read or written, which is what's read back
+    // from addresses that nothing drives
+    bus: u8,
 
     // controllers
     c_strobe: bool,
@@ -36,16 +61,23 @@ pub struct Memory<C: Controller> {
 }
 
 impl<C: Controller> Memory<C> {
-    pub fn new(text: &[u8], apu: NonNull<Apu>, ppu: NonNull<Ppu>) -> Self {
+    pub fn new(prg: &[u8], apu: NonNull<Apu>, ppu: NonNull<Ppu>) -> Self {
+        let mut text = Vec::with_capacity(0x8000);
+        while text.len() < 0x8000 {
+            text.extend_from_slice(prg);
+        }
         Self {
-            mem: unsafe { Box::new_uninit_slice(0x800).assume_init() },
-            text: unsafe {
-                let mut t = Box::new_uninit_slice(text.len()).assume_init();
-                t.copy_from_slice(text);
-                t
-            },
+            mem: vec![0; 0x800].into_boxed_slice(),
+            sram: vec![0; 0x2000].into_boxed_slice(),
+            text: text.into_boxed_slice(),
+            prg_len: prg.len(),
+            decoded: vec![None; 0x8000].into_boxed_slice(),
             apu,
             ppu,
+            debugger: None,
+            cdl: None,
+            oam_dma: None,
+            bus: 0,
             c_strobe: false,
             c1: None,
             c1_index: 0,
@@ -54,6 +86,69 @@ impl<C: Controller> Memory<C> {
         }
     }
 
+    pub fn ppu(&self) -> &Ppu {
+        unsafe { self.ppu.as_ref() }
+    }
+
+    pub fn attach_debugger(&mut self, debugger: Option<NonNull<Debugger>>) {
+        self.debugger = debugger;
+    }
+
+    pub fn attach_cdl(&mut self, cdl: Option<NonNull<Cdl>>) {
+        self.cdl = cdl;
+    }
+
+    #[inline]
+    fn watch(&self, access: Access, addr: u16, value: u8) {
+        if let Some(mut debugger) = self.debugger {
+            unsafe { debugger.as_mut() }.access(AddressSpace::Cpu, access, addr, value);
+        }
+    }
+
+    // instruction at pc if it's in prg rom
+    #[inline]
+    pub fn decoded(&mut self, pc: u16) -> Option<(u8, u16)> {
+        // the operand can't wrap around into ram
+        if !(0x8000..=0xfffd).contains(&pc) {
+            return None;
+        }
+        let i = (pc & 0x7fff) as usize;
+        let (opcode, operand) = match self.decoded[i] {
+            Some(decoded) => decoded,
+            None => {
+                let opcode = self.text[i];
+                let operand = match OPCODES[opcode as usize].mode.operand_len() {
+                    0 => 0,
+                    1 => self.text[i + 1] as u16,
+                    _ => u16::from_le_bytes([self.text[i + 1], self.text[i + 2]]),
+                };
+                self.decoded[i] = Some((opcode, operand));
+                (opcode, operand)
+            },
+        };
+        // watchpoints see the fetch like any other read, and the last
+        // byte fetched is left on the bus
+        for j in 0..=OPCODES[opcode as usize].mode.operand_len() {
+            let value = self.text[i + j as usize];
+            self.watch(Access::Read, pc + j, value);
+            self.bus = value;
+        }
+        Some((opcode, operand))
+    }
+
+    pub fn take_oam_dma(&mut self) -> Option<u8> {
+        self.oam_dma.take()
+    }
+
+    // address of the byte the dmc is waiting for, the cpu does the copy
+    pub fn take_dmc_dma(&mut self) -> Option<u16> {
+        unsafe { self.apu.as_mut() }.take_dmc_dma()
+    }
+
+    pub(crate) fn fill_dmc(&mut self, value: u8) {
+        unsafe { self.apu.as_mut() }.fill_dmc(value);
+    }
+
     pub fn connect_controller(&mut self, port: usize, controller: C) {
         match port {
             0 => self.c1 = Some(controller),
@@ -67,41 +162,20 @@ impl<C: Controller> Mem for Memory<C> {
     fn read(&mut self, addr: u16) -> u8 {
         let apu = unsafe { self.apu.as_mut() };
         let ppu = unsafe { self.ppu.as_mut() };
-        match addr {
+        let value = match addr {
             0x0..=0x1fff => self.mem[(addr & 0x7ff) as usize],
             0x2000..=0x3fff => {
                 // ppu registers mirror every 8 bytes
                 match addr & 0x7 {
-                    0x0 => panic!("memory read out of range: ppuctrl"),
-                    0x1 => panic!("memory read out of range: ppumask"),
                     0x2 => ppu.read_ppustatus(),
-                    0x3 => panic!("memory read out of range: oamaddr"),
-                    0x4 => { eprintln!("read from oamdata!"); 0 },
-                    0x5 => panic!("memory read out of range: ppuscroll"),
-                    0x6 => panic!("memory read out of range: ppuaddr"),
+                    0x4 => ppu.read_oamdata(),
                     0x7 => ppu.read_ppudata(),
-                    _ => unreachable!(),
+                    // the rest are write-only
+                    _ => ppu.read_io_latch(),
                 }
             },
-            0x4000 => panic!("memory read out of range: sq1_vol"),
-            0x4001 => panic!("memory read out of range: sq1_sweep"),
-            0x4002 => panic!("memory read out of range: sq1_lo"),
-            0x4003 => panic!("memory read out of range: sq1_hi"),
-            0x4004 => panic!("memory read out of range: sq2_vol"),
-            0x4005 => panic!("memory read out of range: sq2_sweep"),
-            0x4006 => panic!("memory read out of range: sq2_lo"),
-            0x4007 => panic!("memory read out of range: sq2_hi"),
-            0x4008 => panic!("memory read out of range: tri_linear"),
-            0x400a => panic!("memory read out of range: tri_lo"),
-            0x400b => panic!("memory read out of range: tri_hi"),
-            0x400c => panic!("memory read out of range: noise_vol"),
-            0x400e => panic!("memory read out of range: noise_lo"),
-            0x400f => panic!("memory read out of range: noise_hi"),
-            0x4010 => panic!("memory read out of range: dmc_freq"),
-            0x4011 => panic!("memory read out of range: dmc_raw"),
-            0x4012 => panic!("memory read out of range: dmc_start"),
-            0x4013 => panic!("memory read out of range: dmc_len"),
-            0x4014 => panic!("memory read out of range: oamdma"),
+            // the apu registers are write-only
+            0x4000..=0x4014 => self.bus,
             0x4015 => apu.read_snd_chn(),
             0x4016 => {
                 if self.c_strobe {
@@ -165,28 +239,38 @@ impl<C: Controller> Mem for Memory<C> {
                     0x40 | (state as u8)
                 }
             },
+            // nothing is mapped here without expansion hardware
+            0x4018..=0x5fff => self.bus,
+            0x6000..=0x7fff => self.sram[(addr & 0x1fff) as usize],
             0x8000..=0xffff => self.text[(addr & 0x7fff) as usize],
-            _ => panic!("memory read out of range: ${:x}", addr),
-        }
+        };
+        self.bus = value;
+        self.watch(Access::Read, addr, value);
+        value
     }
     fn write(&mut self, addr: u16, value: u8) {
+        self.bus = value;
+        self.watch(Access::Write, addr, value);
         let apu = unsafe { self.apu.as_mut() };
         let ppu = unsafe { self.ppu.as_mut() };
         match addr {
             0x0..=0x1fff => self.mem[(addr & 0x7ff) as usize] = value,
-            0x2000..=0x3fff =>
+            0x2000..=0x3fff => {
+                ppu.write_io_latch(value);
                 // ppu registers mirror every 8 bytes
                 match addr & 0x7 {
                     0x0 => ppu.write_ppuctrl(value),
                     0x1 => ppu.write_ppumask(value),
-                    0x2 => panic!("memory write out of range: ppustatus"),
-                    0x3 => eprintln!("write to oamaddr: 0x{:x}!", value),
-                    0x4 => eprintln!("write to oamdata: 0x{:x}!", value),
+                    // read-only
+                    0x2 => {},
+                    0x3 => ppu.write_oamaddr(value),
+                    0x4 => ppu.write_oamdata(value),
                     0x5 => ppu.write_ppuscroll(value),
                     0x6 => ppu.write_ppuaddr(value),
                     0x7 => ppu.write_ppudata(value),
                     _ => unreachable!(),
-                },
+                }
+            },
             0x4000 => apu.write_sq1_vol(value),
             0x4001 => apu.write_sq1_sweep(value),
             0x4002 => apu.write_sq1_lo(value),
@@ -196,6 +280,8 @@ impl<C: Controller> Mem for Memory<C> {
             0x4006 => apu.write_sq2_lo(value),
             0x4007 => apu.write_sq2_hi(value),
             0x4008 => apu.write_tri_linear(value),
+            // unused
+            0x4009 | 0x400d => {},
             0x400a => apu.write_tri_lo(value),
             0x400b => apu.write_tri_hi(value),
             0x400c => apu.write_noise_vol(value),
@@ -205,13 +291,7 @@ impl<C: Controller> Mem for Memory<C> {
             0x4011 => apu.write_dmc_raw(value),
             0x4012 => apu.write_dmc_start(value),
             0x4013 => apu.write_dmc_len(value),
-            0x4014 => {
-                // oamdma
-                for addr in ((value as u16) << 8)..=((value as u16) << 8)|0xff {
-                    let value = self.read(addr);
-                    ppu.write_oamdata(value);
-                }
-            }
+            0x4014 => self.oam_dma = Some(value),
             0x4015 => apu.write_snd_chn(value),
             0x4016 => {
                 self.c_strobe = (value & 1) != 0;
@@ -228,7 +308,42 @@ impl<C: Controller> Mem for Memory<C> {
                 }
             },
             0x4017 => apu.write_joy2(value),
-            _ => panic!("memory write out of range: ${:x}", addr),
+            // nothing is mapped here without expansion hardware
+            0x4018..=0x5fff => {},
+            0x6000..=0x7fff => self.sram[(addr & 0x1fff) as usize] = value,
+            // writes to rom are ignored
+            0x8000..=0xffff => {},
+        }
+    }
+    fn peek(&self, addr: u16) -> u8 {
+        let ppu = unsafe { self.ppu.as_ref() };
+        match addr {
+            0x0..=0x1fff => self.mem[(addr & 0x7ff) as usize],
+            0x2000..=0x3fff => ppu.peek(addr),
+            0x4016 | 0x4017 => 0x40,
+            0x6000..=0x7fff => self.sram[(addr & 0x1fff) as usize],
+            0x8000..=0xffff => self.text[(addr & 0x7fff) as usize],
+            _ => 0,
+        }
+    }
+    fn poke(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x0..=0x1fff => self.mem[(addr & 0x7ff) as usize] = value,
+            0x6000..=0x7fff => self.sram[(addr & 0x1fff) as usize] = value,
+            // allows patching rom
+            0x8000..=0xffff => {
+                let i = (addr & 0x7fff) as usize;
+                // and the mirror of a 16 kb rom along with it
+                let mirror = if self.prg_len == 0x4000 { i ^ 0x4000 } else { i };
+                for i in [i, mirror] {
+                    self.text[i] = value;
+                    // forget any instruction this byte is part of
+                    for decoded in &mut self.decoded[i.saturating_sub(2)..=i] {
+                        *decoded = None;
+                    }
+                }
+            },
+            _ => {},
         }
     }
 }
@@ -0,0 +1,143 @@
+use crate::palette::Ntsc;
+
+// 7 output pixels for every 3 nes pixels, the same as blargg's nes_ntsc
+pub const WIDTH: usize = 602;
+pub const HEIGHT: usize = 240;
+
+// the ppu puts out 8 samples a pixel, and the colour subcarrier
+// is 12 samples long
+const SAMPLES: usize = 256 * 8;
+
+// samples averaged for luma and chroma, chroma has less bandwidth
+// which is where the colour bleed comes from
+const LUMA_WINDOW: usize = 8;
+const CHROMA_WINDOW: usize = 24;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Preset {
+    // luma and chroma share a signal so they interfere, giving fringes
+    // and dot crawl
+    #[default]
+    Composite,
+    // separate luma and chroma, only the colour bleed is left
+    SVideo,
+    // sharp with no artefacts
+    Rgb,
+    // luma only, the subcarrier still shows through as dots
+    Monochrome,
+}
+
+impl Preset {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "composite" => Some(Preset::Composite),
+            "svideo" => Some(Preset::SVideo),
+            "rgb" => Some(Preset::Rgb),
+            "monochrome" => Some(Preset::Monochrome),
+            _ => None,
+        }
+    }
+}
+
+// turns the ppu's palette indices into the picture a tv would show
+// from the composite signal
+pub struct Filter {
+    preset: Preset,
+    settings: Ntsc,
+
+    // the signal for each palette index at each phase, and
+    // its average, which is the luma
+    signal: Box<[[f64; 12]]>,
+    luma: Box<[f64]>,
+    // i and q for each palette index
+    chroma: Box<[(f64, f64)]>,
+    carrier: [(f64, f64); 12],
+
+    // the subcarrier phase of the first sample, it moves every frame
+    phase: usize,
+
+    // running sums of y, i and q across a line
+    sums: Box<[(f64, f64, f64)]>,
+}
+
+impl Filter {
+    pub fn new(preset: Preset, settings: Ntsc) -> Self {
+        let signal: Box<[[f64; 12]]> = (0..0x200)
+            .map(|index| std::array::from_fn(|phase| settings.signal(index, phase)))
+            .collect();
+        let carrier = std::array::from_fn(|phase| settings.carrier(phase));
+        let luma = signal.iter().map(|s| s.iter().sum::<f64>() / 12.0).collect();
+        let chroma = signal.iter()
+            .map(|s| (0..12).fold((0.0, 0.0), |(i, q), phase| {
+                let (cos, sin) = carrier[phase];
+                (i + s[phase] * cos / 12.0, q + s[phase] * sin / 12.0)
+            }))
+            .collect();
+        Self {
+            preset,
+            settings,
+            signal,
+            luma,
+            chroma,
+            carrier,
+            phase: 0,
+            sums: vec![(0.0, 0.0, 0.0); SAMPLES + 1].into_boxed_slice(),
+        }
+    }
+
+    pub fn preset(&self) -> Preset {
+        self.preset
+    }
+
+    // pixels are 256x240 palette indices, out is WIDTH x HEIGHT xrgb8888
+    pub fn apply(&mut self, pixels: &[u16], out: &mut [u32]) {
+        for (line, (pixels, out)) in pixels.chunks_exact(256).zip(out.chunks_exact_mut(WIDTH)).enumerate() {
+            // a line is 341 * 8 samples, which moves the phase on by 4
+            let phase = self.phase + line * 4;
+            self.line(pixels, phase, out);
+        }
+        // with rendering on, frames alternate between moving the phase on
+        // by 4 and 8 because of the skipped dot, so it flips between two
+        // phases, which is where the crawl comes from
+        self.phase ^= 4;
+    }
+
+    fn line(&mut self, pixels: &[u16], phase: usize, out: &mut [u32]) {
+        let mut sum = (0.0, 0.0, 0.0);
+        for k in 0..SAMPLES {
+            let index = (pixels[k >> 3] & 0x1ff) as usize;
+            let phase = (phase + k) % 12;
+            let (cos, sin) = self.carrier[phase];
+            let signal = self.signal[index][phase];
+            let (y, i, q) = match self.preset {
+                Preset::Composite => (signal, signal * cos, signal * sin),
+                Preset::SVideo => {
+                    let chroma = signal - self.luma[index];
+                    (self.luma[index], chroma * cos, chroma * sin)
+                },
+                Preset::Rgb => (self.luma[index], self.chroma[index].0, self.chroma[index].1),
+                Preset::Monochrome => (signal, 0.0, 0.0),
+            };
+            sum = (sum.0 + y, sum.1 + i, sum.2 + q);
+            self.sums[k + 1] = sum;
+        }
+
+        let chroma_window = if self.preset == Preset::Rgb { LUMA_WINDOW } else { CHROMA_WINDOW };
+        for (x, out) in out.iter_mut().enumerate() {
+            let centre = (x * SAMPLES + SAMPLES / 2) / WIDTH;
+            let y = self.average(centre, LUMA_WINDOW).0;
+            let (_, i, q) = self.average(centre, chroma_window);
+            *out = self.settings.rgb(y, i, q);
+        }
+    }
+
+    // the mean of the samples in a window around centre, cut short
+    // at the edges of the line
+    fn average(&self, centre: usize, window: usize) -> (f64, f64, f64) {
+        let start = centre.saturating_sub(window / 2);
+        let end = (centre + window / 2).min(SAMPLES);
+        let (a, b) = (self.sums[start], self.sums[end]);
+        let n = (end - start) as f64;
+        ((b.0 - a.0) / n, (b.1 - a.1) / n, (b.2 - a.2) / n)
+    }
+}
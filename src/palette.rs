@@ -0,0 +1,265 @@
+use std::io::{self, ErrorKind};
+
+// pixels are xrgb8888
+pub(crate) const fn rgb(r: u8, g: u8, b: u8) -> u32 {
+    0xff000000 | ((r as u32) << 16) | ((g as u32) << 8) | (b as u32)
+}
+
+// from mesen
+const COLOURS: [u32; 0x40] = [
+    rgb(0x66, 0x66, 0x66),
+    rgb(0x00, 0x2a, 0x88),
+    rgb(0x14, 0x12, 0xa7),
+    rgb(0x3b, 0x00, 0xa4),
+    rgb(0x5c, 0x00, 0x7e),
+    rgb(0x6e, 0x00, 0x40),
+    rgb(0x6c, 0x06, 0x00),
+    rgb(0x56, 0x1d, 0x00),
+    rgb(0x33, 0x35, 0x00),
+    rgb(0x0b, 0x48, 0x00),
+    rgb(0x00, 0x52, 0x00),
+    rgb(0x00, 0x4f, 0x08),
+    rgb(0x00, 0x40, 0x4d),
+    rgb(0x00, 0x00, 0x00),
+    rgb(0x00, 0x00, 0x00),
+    rgb(0x00, 0x00, 0x00),
+
+    rgb(0xad, 0xad, 0xad),
+    rgb(0x15, 0x5f, 0xd9),
+    rgb(0x42, 0x40, 0xff),
+    rgb(0x75, 0x27, 0xfe),
+    rgb(0xa0, 0x1a, 0xcc),
+    rgb(0xb7, 0x1e, 0x7b),
+    rgb(0xb5, 0x31, 0x20),
+    rgb(0x99, 0x4e, 0x00),
+    rgb(0x6b, 0x6d, 0x00),
+    rgb(0x38, 0x87, 0x00),
+    rgb(0x0c, 0x93, 0x00),
+    rgb(0x00, 0x8f, 0x32),
+    rgb(0x00, 0x7c, 0x8d),
+    rgb(0x00, 0x00, 0x00),
+    rgb(0x00, 0x00, 0x00),
+    rgb(0x00, 0x00, 0x00),
+
+    rgb(0xff, 0xfe, 0xff),
+    rgb(0x64, 0xb0, 0xff),
+    rgb(0x92, 0x90, 0xff),
+    rgb(0xc6, 0x76, 0xff),
+    rgb(0xf3, 0x6a, 0xff),
+    rgb(0xfe, 0x6e, 0xcc),
+    rgb(0xfe, 0x81, 0x70),
+    rgb(0xea, 0x9e, 0x22),
+    rgb(0xbc, 0xbe, 0x00),
+    rgb(0x88, 0xd8, 0x00),
+    rgb(0x5c, 0xe4, 0x30),
+    rgb(0x45, 0xe0, 0x82),
+    rgb(0x48, 0xcd, 0xde),
+    rgb(0x4f, 0x4f, 0x4f),
+    rgb(0x00, 0x00, 0x00),
+    rgb(0x00, 0x00, 0x00),
+
+    rgb(0xff, 0xfe, 0xff),
+    rgb(0xc0, 0xdf, 0xff),
+    rgb(0xd3, 0xd2, 0xff),
+    rgb(0xe8, 0xc8, 0xff),
+    rgb(0xfb, 0xc2, 0xff),
+    rgb(0xfe, 0xc4, 0xea),
+    rgb(0xfe, 0xcc, 0xc5),
+    rgb(0xf7, 0xd8, 0xa5),
+    rgb(0xe4, 0xe5, 0x94),
+    rgb(0xcf, 0xef, 0x96),
+    rgb(0xbd, 0xf4, 0xab),
+    rgb(0xb3, 0xf3, 0xcc),
+    rgb(0xb5, 0xeb, 0xf2),
+    rgb(0xb8, 0xb8, 0xb8),
+    rgb(0x00, 0x00, 0x00),
+    rgb(0x00, 0x00, 0x00),
+];
+
+// each emphasis bit darkens the other two channels, $xe and $xf are left
+// alone since they're black whatever the emphasis
+const fn emphasise(colours: &[u32; 0x40]) -> [u32; 0x200] {
+    let mut palette = [0; 0x200];
+    let mut i = 0;
+    while i < 0x200 {
+        let emphasis = i >> 6;
+        let mut colour = colours[i & 0x3f];
+        if emphasis != 0 && (i & 0x0e) != 0x0e {
+            // red, green, blue
+            let mut channel = 0;
+            while channel < 3 {
+                if ((emphasis >> channel) & 1) == 0 {
+                    let shift = 16 - channel * 8;
+                    let value = (colour >> shift) & 0xff;
+                    colour = (colour & !(0xff << shift)) | ((value * 746 / 1000) << shift);
+                }
+                channel += 1;
+            }
+        }
+        palette[i] = colour;
+        i += 1;
+    }
+    palette
+}
+
+// indexed by the emphasis bits of ppumask and a colour
+#[derive(Clone)]
+pub struct Palette {
+    colours: [u32; 0x200],
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self {
+            colours: emphasise(&COLOURS),
+        }
+    }
+}
+
+impl Palette {
+    // a .pal file, rgb triplets for either the 64 colours, with the
+    // emphasis worked out like the default palette, or all 512
+    pub fn load(data: &[u8]) -> io::Result<Self> {
+        let triplet = |i: usize| rgb(data[i * 3], data[i * 3 + 1], data[i * 3 + 2]);
+        match data.len() {
+            0xc0 => Ok(Self {
+                colours: emphasise(&std::array::from_fn(triplet)),
+            }),
+            0x600 => Ok(Self {
+                colours: std::array::from_fn(triplet),
+            }),
+            _ => Err(io::Error::new(ErrorKind::InvalidData, "palette should have 64 or 512 colours")),
+        }
+    }
+
+    // works the colours out from the composite signal the ppu generates
+    pub fn ntsc(ntsc: &Ntsc) -> Self {
+        Self {
+            colours: std::array::from_fn(|i| ntsc.colour(i)),
+        }
+    }
+
+    pub fn colours(&self) -> &[u32; 0x200] {
+        &self.colours
+    }
+
+    // turns the ppu's palette indices into xrgb8888
+    pub fn convert(&self, pixels: &[u16], out: &mut [u32]) {
+        for (out, &pixel) in out.iter_mut().zip(pixels) {
+            *out = self.colours[(pixel & 0x1ff) as usize];
+        }
+    }
+}
+
+// settings for the generated palette
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Ntsc {
+    // in degrees
+    pub hue: f64,
+    pub saturation: f64,
+    pub contrast: f64,
+    pub brightness: f64,
+    pub gamma: f64,
+}
+
+impl Default for Ntsc {
+    fn default() -> Self {
+        Self {
+            hue: 0.0,
+            saturation: 1.0,
+            contrast: 1.0,
+            brightness: 1.0,
+            gamma: 1.8,
+        }
+    }
+}
+
+impl Ntsc {
+    pub fn hue(mut self, hue: f64) -> Self {
+        self.hue = hue;
+        self
+    }
+
+    pub fn saturation(mut self, saturation: f64) -> Self {
+        self.saturation = saturation;
+        self
+    }
+
+    pub fn contrast(mut self, contrast: f64) -> Self {
+        self.contrast = contrast;
+        self
+    }
+
+    pub fn brightness(mut self, brightness: f64) -> Self {
+        self.brightness = brightness;
+        self
+    }
+
+    pub fn gamma(mut self, gamma: f64) -> Self {
+        self.gamma = gamma;
+        self
+    }
+
+    // the signal for a colour is a square wave between two voltages, 12
+    // samples a cycle with the hue as its phase, decoded as yiq
+    // (after bisqwit's palette generator)
+    fn colour(&self, index: usize) -> u32 {
+        let (mut y, mut i, mut q) = (0.0, 0.0, 0.0);
+        for phase in 0..12 {
+            let v = self.signal(index, phase) / 12.0;
+            let (cos, sin) = self.carrier(phase);
+            y += v;
+            i += v * cos;
+            q += v * sin;
+        }
+        self.rgb(y, i, q)
+    }
+
+    // the signal for a colour at one of the 12 phases of the colour
+    // subcarrier, 0 is black and 1 white before contrast and brightness
+    pub(crate) fn signal(&self, index: usize, phase: usize) -> f64 {
+        // voltages for each luma level, low then high
+        const LEVELS: [f64; 8] = [0.350, 0.518, 0.962, 1.550, 1.094, 1.506, 1.962, 1.962];
+        const BLACK: f64 = 0.518;
+        const WHITE: f64 = 1.962;
+        const ATTENUATION: f64 = 0.746;
+
+        let hue = index & 0x0f;
+        // $xe and $xf are black
+        let level = if hue < 0x0e { (index >> 4) & 0x3 } else { 1 };
+        // $x0 is the high level all the way, $xd and up the low level
+        let lo = LEVELS[level + if hue == 0x0 { 4 } else { 0 }];
+        let hi = LEVELS[level + if hue < 0x0d { 4 } else { 0 }];
+        let wave = |p: usize, hue: usize| (hue + p + 8) % 12 < 6;
+
+        let mut spot = if wave(phase, hue) { hi } else { lo };
+        // each emphasis bit attenuates the signal for part of the cycle
+        if ((index & 0x040) != 0 && wave(phase, 12))
+            || ((index & 0x080) != 0 && wave(phase, 4))
+            || ((index & 0x100) != 0 && wave(phase, 8)) {
+            spot *= ATTENUATION;
+        }
+        let v = (spot - BLACK) / (WHITE - BLACK);
+        ((v - 0.5) * self.contrast + 0.5) * self.brightness
+    }
+
+    // the subcarrier the tv decodes i and q against, shifted by the hue
+    pub(crate) fn carrier(&self, phase: usize) -> (f64, f64) {
+        let angle = std::f64::consts::PI / 6.0 * (phase as f64 + self.hue / 30.0);
+        (angle.cos(), angle.sin())
+    }
+
+    pub(crate) fn rgb(&self, y: f64, i: f64, q: f64) -> u32 {
+        let i = i * self.saturation;
+        let q = q * self.saturation;
+        let channel = |v: f64| {
+            let v = if v <= 0.0 { 0.0 } else { v.powf(2.2 / self.gamma) };
+            (v * 255.95).clamp(0.0, 255.0) as u8
+        };
+        rgb(
+            channel(y + 0.946882 * i + 0.623557 * q),
+            channel(y - 0.274788 * i - 0.635691 * q),
+            channel(y - 1.108545 * i + 1.709007 * q),
+        )
+    }
+}
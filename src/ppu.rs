@@ -1,382 +1,900 @@
 use std::{cell::Cell, ptr::NonNull};
 
-use gfx::{Color, Framebuffer, Texture};
-
-// from mesen
-const PALETTE: [Color; 0x40] = [
-    Color::new(0x66, 0x66, 0x66),
-    Color::new(0x00, 0x2a, 0x88),
-    Color::new(0x14, 0x12, 0xa7),
-    Color::new(0x3b, 0x00, 0xa4),
-    Color::new(0x5c, 0x00, 0x7e),
-    Color::new(0x6e, 0x00, 0x40),
-    Color::new(0x6c, 0x06, 0x00),
-    Color::new(0x56, 0x1d, 0x00),
-    Color::new(0x33, 0x35, 0x00),
-    Color::new(0x0b, 0x48, 0x00),
-    Color::new(0x00, 0x52, 0x00),
-    Color::new(0x00, 0x4f, 0x08),
-    Color::new(0x00, 0x40, 0x4d),
-    Color::new(0x00, 0x00, 0x00),
-    Color::new(0x00, 0x00, 0x00),
-    Color::new(0x00, 0x00, 0x00),
-
-    Color::new(0xad, 0xad, 0xad),
-    Color::new(0x15, 0x5f, 0xd9),
-    Color::new(0x42, 0x40, 0xff),
-    Color::new(0x75, 0x27, 0xfe),
-    Color::new(0xa0, 0x1a, 0xcc),
-    Color::new(0xb7, 0x1e, 0x7b),
-    Color::new(0xb5, 0x31, 0x20),
-    Color::new(0x99, 0x4e, 0x00),
-    Color::new(0x6b, 0x6d, 0x00),
-    Color::new(0x38, 0x87, 0x00),
-    Color::new(0x0c, 0x93, 0x00),
-    Color::new(0x00, 0x8f, 0x32),
-    Color::new(0x00, 0x7c, 0x8d),
-    Color::new(0x00, 0x00, 0x00),
-    Color::new(0x00, 0x00, 0x00),
-    Color::new(0x00, 0x00, 0x00),
-
-    Color::new(0xff, 0xfe, 0xff),
-    Color::new(0x64, 0xb0, 0xff),
-    Color::new(0x92, 0x90, 0xff),
-    Color::new(0xc6, 0x76, 0xff),
-    Color::new(0xf3, 0x6a, 0xff),
-    Color::new(0xfe, 0x6e, 0xcc),
-    Color::new(0xfe, 0x81, 0x70),
-    Color::new(0xea, 0x9e, 0x22),
-    Color::new(0xbc, 0xbe, 0x00),
-    Color::new(0x88, 0xd8, 0x00),
-    Color::new(0x5c, 0xe4, 0x30),
-    Color::new(0x45, 0xe0, 0x82),
-    Color::new(0x48, 0xcd, 0xde),
-    Color::new(0x4f, 0x4f, 0x4f),
-    Color::new(0x00, 0x00, 0x00),
-    Color::new(0x00, 0x00, 0x00),
-
-    Color::new(0xff, 0xfe, 0xff),
-    Color::new(0xc0, 0xdf, 0xff),
-    Color::new(0xd3, 0xd2, 0xff),
-    Color::new(0xe8, 0xc8, 0xff),
-    Color::new(0xfb, 0xc2, 0xff),
-    Color::new(0xfe, 0xc4, 0xea),
-    Color::new(0xfe, 0xcc, 0xc5),
-    Color::new(0xf7, 0xd8, 0xa5),
-    Color::new(0xe4, 0xe5, 0x94),
-    Color::new(0xcf, 0xef, 0x96),
-    Color::new(0xbd, 0xf4, 0xab),
-    Color::new(0xb3, 0xf3, 0xcc),
-    Color::new(0xb5, 0xeb, 0xf2),
-    Color::new(0xb8, 0xb8, 0xb8),
-    Color::new(0x00, 0x00, 0x00),
-    Color::new(0x00, 0x00, 0x00),
-];
+use crate::{cdl::{self, Cdl}, debug::{Access, AddressSpace, Debugger}, image::Image, palette::Palette, region::Region};
+
+// ppu cycles a row of oam keeps its contents without being refreshed,
+// about 3000 cpu cycles
+const OAM_DECAY: usize = 9000;
+// and the bits of the i/o latch, about 600 ms
+const IO_DECAY: usize = 341 * 262 * 36;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Mirroring {
+    Horizontal,
+    Vertical,
+}
 
 // PPU
 pub struct Ppu {
-    framebuffer: Framebuffer,
+    // one frame of pixels, filled in as the ppu renders, each is a colour
+    // with the emphasis bits of ppumask above it
+    pixels: Box<[u16]>,
     chr: Box<[u8]>,
-    chr0: Texture<u8>,
-    chr1: Texture<u8>,
-    bg: Color,
-    palette: Texture<Color>,
+    chr_ram: bool,
     mem: [u8; 0x800],
+    mirroring: Mirroring,
     pal: [u8; 0x20],
 
     oam: [u8; 0x100],
+    // oam is dram that's refreshed by rendering and by accessing it,
+    // this is the ppu cycle each 8 byte row was last refreshed on
+    oam_refreshed: [usize; 0x20],
+    // sprites on the line being drawn, at most 8 unless the limit is removed
+    sprites: [Sprite; 0x40],
+    sprite_count: usize,
+    // whether sprite 0 is the first of them
+    sprite_zero: bool,
+    sprite_limit: bool,
+    sprite_overflow: bool,
+    sprite_zero_hit: bool,
 
     // register related values
-    latch: bool,
     pub ppuctrl: u8,
     ppumask: u8,
-    ppustatus: u8,
     oamaddr: u8,
-    ppuscroll_x: u8,
-    ppuscroll_y: u8,
-    ppuaddr: u16,
     ppudata_buf: u8,
+    // the value left on the data bus by the last register access, which is
+    // what write-only registers and unused bits read back as, each bit
+    // fades to 0 a while after it was last driven (in ppu cycles)
+    io_latch: u8,
+    io_refreshed: [usize; 8],
+
+    // scrolling, v is the vram address (and the tile being fetched while
+    // rendering), t the address for the top left of the screen,
+    // x the fine x scroll and w the write toggle for $2005/$2006
+    // v and t are laid out as 0yyy NNYY YYYX XXXX
+    // (fine y, nametable, coarse y, coarse x)
+    v: u16,
+    t: u16,
+    x: u8,
+    w: bool,
+
+    // background fetches
+    tile: u8,
+    attr: u8,
+    pattern_lo: u8,
+    pattern_hi: u8,
+    // two tiles of pixels, the high byte is the one being drawn
+    shift_lo: u16,
+    shift_hi: u16,
+    shift_attr_lo: u16,
+    shift_attr_hi: u16,
 
     // cycles
     cycles: NonNull<Cell<usize>>,
+    region: Region,
+    // in ppu cycles, counted from the start of the cpu's cycles
+    frame_start: usize,
+    // dots rendered so far this frame
+    rendered: usize,
+    odd_frame: bool,
+
+    vblank: bool,
+    // $2002 was read just before vblank, so it isn't set this frame
+    vblank_suppressed: bool,
+    // waiting for the cpu to take it
+    nmi: bool,
+
+    debugger: Option<NonNull<Debugger>>,
+    cdl: Option<NonNull<Cdl>>,
+}
+
+#[derive(Copy, Clone, Default)]
+struct Sprite {
+    x: u8,
+    attr: u8,
+    // pattern with horizontal flipping applied
+    lo: u8,
+    hi: u8,
 }
 
 impl Ppu {
-    pub fn new(chr: &[u8], cycles: NonNull<Cell<usize>>) -> Self {
+    pub fn new(chr: &[u8], chr_ram: bool, mirroring: Mirroring, cycles: NonNull<Cell<usize>>) -> Self {
         Self {
-            framebuffer: Framebuffer::new(256, 240),
+            pixels: vec![0; 256 * 240].into_boxed_slice(),
             chr: unsafe {
                 let mut c = Box::new_uninit_slice(chr.len()).assume_init();
                 c.copy_from_slice(chr);
                 c
             },
-            chr0: Texture::from_2bpp(&chr[0..0x1000], 0x80, 0x80),
-            chr1: Texture::from_2bpp(&chr[0x1000..0x2000], 0x80, 0x80),
-            bg: PALETTE[0],
-            palette: Texture::new(&[
-                Color::TRANSPARENT, PALETTE[0], PALETTE[0], PALETTE[0],
-                Color::TRANSPARENT, PALETTE[0], PALETTE[0], PALETTE[0],
-                Color::TRANSPARENT, PALETTE[0], PALETTE[0], PALETTE[0],
-                Color::TRANSPARENT, PALETTE[0], PALETTE[0], PALETTE[0],
-                Color::TRANSPARENT, PALETTE[0], PALETTE[0], PALETTE[0],
-                Color::TRANSPARENT, PALETTE[0], PALETTE[0], PALETTE[0],
-                Color::TRANSPARENT, PALETTE[0], PALETTE[0], PALETTE[0],
-                Color::TRANSPARENT, PALETTE[0], PALETTE[0], PALETTE[0],
-                ], 4, 8),
+            chr_ram,
             mem: [0; 0x800],
+            mirroring,
             pal: [0; 0x20],
             oam: [0; 0x100],
+            oam_refreshed: [0; 0x20],
+            sprites: [Sprite::default(); 0x40],
+            sprite_count: 0,
+            sprite_zero: false,
+            sprite_limit: true,
+            sprite_overflow: false,
+            sprite_zero_hit: false,
 
-            latch: false,
             ppuctrl: 0,
             ppumask: 0,
-            ppustatus: 0,
             oamaddr: 0,
-            ppuscroll_x: 0,
-            ppuscroll_y: 0,
-            ppuaddr: 0,
             ppudata_buf: 0,
+            io_latch: 0,
+            io_refreshed: [0; 8],
+
+            v: 0,
+            t: 0,
+            x: 0,
+            w: false,
+
+            tile: 0,
+            attr: 0,
+            pattern_lo: 0,
+            pattern_hi: 0,
+            shift_lo: 0,
+            shift_hi: 0,
+            shift_attr_lo: 0,
+            shift_attr_hi: 0,
 
             cycles,
+            region: Region::Ntsc,
+            frame_start: 0,
+            rendered: 0,
+            odd_frame: false,
+
+            vblank: false,
+            vblank_suppressed: false,
+            nmi: false,
+
+            debugger: None,
+            cdl: None,
         }
     }
 }
 
 impl Ppu {
-    pub fn framebuffer(&self) -> &Framebuffer {
-        &self.framebuffer
+    // 256x240 palette indices, see Palette::convert for the colours
+    pub fn pixels(&self) -> &[u16] {
+        &self.pixels
+    }
+
+    pub fn attach_debugger(&mut self, debugger: Option<NonNull<Debugger>>) {
+        self.debugger = debugger;
+    }
+
+    pub fn attach_cdl(&mut self, cdl: Option<NonNull<Cdl>>) {
+        self.cdl = cdl;
+    }
+
+    // without the limit every sprite on a line is drawn, which gets rid of
+    // the flicker games use to show more than 8, the overflow flag still
+    // works as if the limit was there
+    pub fn set_sprite_limit(&mut self, limit: bool) {
+        self.sprite_limit = limit;
+    }
+
+    // changing it starts a new frame at the current cycle
+    pub fn set_region(&mut self, region: Region) {
+        self.catch_up();
+        self.region = region;
+        let now = self.now();
+        self.frame_start = now;
+        self.rendered = 0;
+        self.sprite_count = 0;
+        // the decay timestamps were on the old clock
+        self.oam_refreshed.fill(now);
+        self.io_refreshed.fill(now);
+    }
+
+    #[inline]
+    fn log_chr(&self, addr: u16, len: u16, flags: u8) {
+        if let Some(mut cdl) = self.cdl {
+            unsafe { cdl.as_mut() }.log_chr(addr, len, flags);
+        }
+    }
+
+    #[inline]
+    fn watch(&self, access: Access, addr: u16, value: u8) {
+        if let Some(mut debugger) = self.debugger {
+            unsafe { debugger.as_mut() }.access(AddressSpace::Ppu, access, addr & 0x3fff, value);
+        }
+    }
+
+    // whether the current frame has finished, at which point
+    // start_frame should be called
+    pub fn frame_done(&self) -> bool {
+        self.frame_cycles() >= self.frame_len()
+    }
+
+    // ppu cycles in a frame, odd ntsc frames are one shorter when rendering
+    fn frame_len(&self) -> usize {
+        341 * self.region.lines()
+    }
+
+    // the dot vblank starts on
+    fn vblank_dot(&self) -> usize {
+        self.region.vblank_line() * 341 + 1
     }
 
-    pub fn clear(&mut self) {
-        self.framebuffer.clear(self.bg);
+    fn pre_render_line(&self) -> usize {
+        self.region.lines() - 1
     }
 
-    fn draw_bg(&mut self, bg: usize) {
-        for i in 0x0..0x3c0 {
-            let tile = self.mem[(bg<<10)|i];
-            let pal = (self.mem[(bg<<10)|0x3c0|((i>>4)&0x38)|((i>>2)&0x7)]>>(((i>>4)&0x4)|(i&0x2)))&0x3;
-            let u = ((tile&0xf)<<3) as usize;
-            let v = ((tile>>4)<<3) as usize;
-            let x = (((i&0x1f)<<3) as isize) - (self.ppuscroll_x as isize) + ((((self.ppuctrl & 0x01) ^ ((bg & 0x01) as u8)) as isize) << 8);
-            let y = (((i>>5)<<3) as isize) - (self.ppuscroll_y as isize);
-            self.framebuffer.draw_paletted(&self.chr1, x, y, u, v, 8, 8, &self.palette, pal as usize, false, false);
+    // finishes the current frame, the next one starts where it ended
+    // rather than at the current cycle, so the ppu drifts against
+    // the cpu instructions like it should
+    pub fn start_frame(&mut self) {
+        self.catch_up();
+        self.frame_start += self.frame_len();
+        self.rendered = 0;
+        self.sprite_count = 0;
+        self.odd_frame ^= true;
+    }
+
+    // ppu cycles since the start of the cpu's cycles
+    fn now(&self) -> usize {
+        let (num, den) = self.region.ppu_ratio();
+        (unsafe { self.cycles.as_ref().get() }) * num / den
+    }
+
+    // ppu cycles since the start of the frame
+    fn frame_cycles(&self) -> usize {
+        self.now() - self.frame_start
+    }
+
+    // returns true once for each nmi
+    pub fn nmi(&mut self) -> bool {
+        // nothing else might make the ppu render up to vblank
+        let vblank = self.vblank_dot();
+        if self.rendered <= vblank && self.frame_cycles() > vblank {
+            self.catch_up();
         }
+        std::mem::take(&mut self.nmi)
+    }
+
+    pub fn scanline(&self) -> usize {
+        self.frame_cycles() / 341
     }
 
-    fn draw_sprite(&mut self, spr: usize) {
-        let y = self.oam[(spr<<2)|0] as isize + 1;
-        let tile = self.oam[(spr<<2)|1];
-        let pal = self.oam[(spr<<2)|2] & 0x03;
-        let flip_x = (self.oam[(spr<<2)|2] & 0x40) != 0;
-        let flip_y = (self.oam[(spr<<2)|2] & 0x80) != 0;
-        let x = self.oam[(spr<<2)|3] as isize;
+    pub fn dot(&self) -> usize {
+        self.frame_cycles() % 341
+    }
 
-        let u = ((tile as usize)&0xf)<<3;
-        let v = ((tile as usize)>>4)<<3;
+    // index into vram for a nametable address
+    fn nametable(&self, addr: u16) -> usize {
+        match self.mirroring {
+            Mirroring::Horizontal => (((addr >> 1) & 0x400) | (addr & 0x3ff)) as usize,
+            Mirroring::Vertical => (addr & 0x7ff) as usize,
+        }
+    }
 
-        self.framebuffer.draw_paletted(&self.chr0, x, y, u, v, 8, 8, &self.palette, 4+pal as usize, flip_x, flip_y);
+    // renders everything up to the current cycle, called before anything
+    // that could change what's on screen
+    fn catch_up(&mut self) {
+        let end = self.frame_cycles().min(self.frame_len());
+        while self.rendered < end {
+            self.tick();
+        }
     }
 
-    pub fn draw(&mut self) {
-        // sprites behind the background
-        if (self.ppumask & 0x10) != 0 {
-            for spr in (0x0..0x40).rev() {
-                if (self.oam[(spr<<2)|2] & 0x20) != 0 {
-                    self.draw_sprite(spr);
+    // a single dot, lines 0-239 are visible and the last line is the
+    // pre-render line which fetches the first tiles of the next frame
+    fn tick(&mut self) {
+        let line = self.rendered / 341;
+        let dot = self.rendered % 341;
+        let rendering = self.rendering();
+        let pre_render = line == self.pre_render_line();
+        self.rendered += 1;
+
+        if line == self.region.vblank_line() && dot == 1 && !self.vblank_suppressed {
+            self.vblank = true;
+            self.nmi |= (self.ppuctrl & 0x80) != 0;
+        }
+        if pre_render && dot == 1 {
+            self.vblank = false;
+            self.vblank_suppressed = false;
+            self.sprite_overflow = false;
+            self.sprite_zero_hit = false;
+        }
+
+        if rendering {
+            if (2..=257).contains(&dot) || (321..=337).contains(&dot) {
+                self.shift_lo <<= 1;
+                self.shift_hi <<= 1;
+                self.shift_attr_lo <<= 1;
+                self.shift_attr_hi <<= 1;
+
+                match (dot - 1) & 0x7 {
+                    0 => {
+                        self.load_shifters();
+                        self.fetch_tile();
+                    },
+                    2 => self.fetch_attr(),
+                    4 => self.pattern_lo = self.fetch_pattern(0),
+                    6 => self.pattern_hi = self.fetch_pattern(0x8),
+                    7 => self.increment_x(),
+                    _ => {},
                 }
             }
+            if dot == 256 {
+                self.increment_y();
+            }
+            if (257..=320).contains(&dot) {
+                self.oamaddr = 0;
+            }
+            if dot == 257 {
+                self.load_shifters();
+                // the horizontal scroll is picked up for the next line
+                self.v = (self.v & !0x041f) | (self.t & 0x041f);
+                self.evaluate_sprites(line);
+            }
+            if pre_render && (280..=304).contains(&dot) {
+                // and the vertical scroll for the next frame
+                self.v = (self.v & !0x7be0) | (self.t & 0x7be0);
+            }
+            if pre_render && dot == 339 && self.odd_frame && self.region == Region::Ntsc {
+                // odd frames skip the last dot of the pre-render line,
+                // pal ppus don't
+                self.rendered += 1;
+                self.frame_start -= 1;
+            }
+        }
+
+        if line < 240 && (1..=256).contains(&dot) {
+            self.pixel(dot - 1, line);
+        }
+    }
+
+    // whether the ppu is fetching, which is when rendering is enabled
+    // on the visible and pre-render lines
+    fn rendering(&self) -> bool {
+        let line = self.rendered / 341;
+        (self.ppumask & 0x18) != 0 && (line < 240 || line == self.pre_render_line())
+    }
+
+    fn increment_x(&mut self) {
+        if (self.v & 0x001f) == 0x1f {
+            // wrap into the next nametable
+            self.v = (self.v & !0x001f) ^ 0x0400;
+        } else {
+            self.v += 1;
+        }
+    }
+
+    fn increment_y(&mut self) {
+        if (self.v & 0x7000) != 0x7000 {
+            self.v += 0x1000;
+            return;
+        }
+        self.v &= !0x7000;
+        let mut y = (self.v & 0x03e0) >> 5;
+        if y == 29 {
+            // wrap into the next nametable
+            y = 0;
+            self.v ^= 0x0800;
+        } else if y == 31 {
+            // rows 30 and 31 are the attribute table,
+            // scrolling into them wraps without switching nametables
+            y = 0;
+        } else {
+            y += 1;
+        }
+        self.v = (self.v & !0x03e0) | (y << 5);
+    }
+
+    fn fetch_tile(&mut self) {
+        let addr = 0x2000 | (self.v & 0x0fff);
+        self.tile = self.mem[self.nametable(addr)];
+    }
+
+    fn fetch_attr(&mut self) {
+        let addr = 0x23c0 | (self.v & 0x0c00) | ((self.v >> 4) & 0x38) | ((self.v >> 2) & 0x07);
+        let shift = ((self.v >> 4) & 0x4) | (self.v & 0x2);
+        self.attr = (self.mem[self.nametable(addr)] >> shift) & 0x3;
+    }
+
+    fn fetch_pattern(&mut self, plane: u16) -> u8 {
+        let table = ((self.ppuctrl & 0x10) as u16) << 8;
+        let addr = table | ((self.tile as u16) << 4) | plane | (self.v >> 12);
+        self.log_chr(addr, 1, cdl::RENDERED);
+        self.chr[addr as usize]
+    }
+
+    fn load_shifters(&mut self) {
+        self.shift_lo = (self.shift_lo & 0xff00) | self.pattern_lo as u16;
+        self.shift_hi = (self.shift_hi & 0xff00) | self.pattern_hi as u16;
+        self.shift_attr_lo = (self.shift_attr_lo & 0xff00) | if (self.attr & 0x1) != 0 { 0xff } else { 0x00 };
+        self.shift_attr_hi = (self.shift_attr_hi & 0xff00) | if (self.attr & 0x2) != 0 { 0xff } else { 0x00 };
+    }
+
+    // finds the sprites on the next line and fetches their patterns
+    fn evaluate_sprites(&mut self, line: usize) {
+        self.sprite_count = 0;
+        self.sprite_zero = false;
+        if line >= 240 {
+            return;
+        }
+        for row in 0..0x20 {
+            self.refresh_oam(row << 3);
+        }
+        let height = self.sprite_height();
+        let in_range = |y: u8| line.wrapping_sub(y as usize) < height;
+
+        // the first 8 sprites in range go into secondary oam
+        let mut n = 0;
+        while n < 0x40 && self.sprite_count < 8 {
+            if in_range(self.oam[n << 2]) {
+                self.sprite_zero |= n == 0;
+                self.load_sprite(n, line);
+            }
+            n += 1;
+        }
+        if self.sprite_count < 8 {
+            return;
+        }
+
+        // looking for a 9th sprite, the ppu increments the byte within
+        // each sprite along with the sprite index, so it compares tile
+        // numbers, attributes and x positions as if they were y
+        let mut m = 0;
+        for i in n..0x40 {
+            if in_range(self.oam[(i << 2) | m]) {
+                self.sprite_overflow = true;
+                break;
+            }
+            m = (m + 1) & 0x3;
+        }
+
+        if !self.sprite_limit {
+            for i in n..0x40 {
+                if in_range(self.oam[i << 2]) {
+                    self.load_sprite(i, line);
+                }
+            }
+        }
+    }
+
+    fn sprite_height(&self) -> usize {
+        if (self.ppuctrl & 0x20) != 0 { 16 } else { 8 }
+    }
+
+    fn load_sprite(&mut self, i: usize, line: usize) {
+        let row = line - self.oam[i << 2] as usize;
+        let tile = self.oam[(i << 2) | 1] as u16;
+        let attr = self.oam[(i << 2) | 2];
+        let row = if (attr & 0x80) != 0 { self.sprite_height() - 1 - row } else { row } as u16;
+        let addr = self.sprite_pattern(tile, row);
+        if line < 239 {
+            // the sprites found on the last line are never drawn
+            self.log_chr(addr, 1, cdl::RENDERED);
+            self.log_chr(addr | 0x8, 1, cdl::RENDERED);
+        }
+        let (mut lo, mut hi) = (self.chr[addr as usize], self.chr[(addr | 0x8) as usize]);
+        if (attr & 0x40) != 0 {
+            lo = lo.reverse_bits();
+            hi = hi.reverse_bits();
+        }
+        self.sprites[self.sprite_count] = Sprite {
+            x: self.oam[(i << 2) | 3],
+            attr,
+            lo,
+            hi,
+        };
+        self.sprite_count += 1;
+    }
+
+    // the address of a row of a sprite's pattern
+    fn sprite_pattern(&self, tile: u16, row: u16) -> u16 {
+        if (self.ppuctrl & 0x20) != 0 {
+            // 8x16 sprites take the table from bit 0 of the tile number,
+            // and the bottom half is the next tile
+            ((tile & 0x01) << 12) | ((tile & 0xfe) << 4) | ((row & 0x08) << 1) | (row & 0x07)
+        } else {
+            (((self.ppuctrl & 0x08) as u16) << 9) | (tile << 4) | row
         }
-        
-        if (self.ppumask & 0x08) != 0 {
-            for bg in 0..2 {
-                self.draw_bg(bg);
+    }
+
+    fn pixel(&mut self, x: usize, y: usize) {
+        // the left 8 pixels of the background and sprites can be hidden
+        let left = x >= 8;
+        let mut bg = 0;
+        if (self.ppumask & 0x08) != 0 && (left || (self.ppumask & 0x02) != 0) {
+            let bit = 15 - self.x;
+            let pixel = (((self.shift_hi >> bit) & 1) << 1) | ((self.shift_lo >> bit) & 1);
+            let attr = (((self.shift_attr_hi >> bit) & 1) << 1) | ((self.shift_attr_lo >> bit) & 1);
+            if pixel != 0 {
+                bg = ((attr << 2) | pixel) as usize;
             }
         }
 
-        // sprites in front of the background
-        if (self.ppumask & 0x10) != 0 {
-            for spr in (0x0..0x40).rev() {
-                if (self.oam[(spr<<2)|2] & 0x20) == 0 {
-                    self.draw_sprite(spr);
+        // the first opaque sprite in oam wins, even if it's behind the background
+        let mut sprite = None;
+        if (self.ppumask & 0x10) != 0 && (left || (self.ppumask & 0x04) != 0) {
+            for (i, spr) in self.sprites[..self.sprite_count].iter().enumerate() {
+                let col = x.wrapping_sub(spr.x as usize);
+                if col >= 8 {
+                    continue;
+                }
+                let pixel = (((spr.hi >> (7 - col)) & 1) << 1) | ((spr.lo >> (7 - col)) & 1);
+                if pixel != 0 {
+                    sprite = Some(((0x10 | ((spr.attr & 0x3) << 2) | pixel) as usize, (spr.attr & 0x20) != 0));
+                    // sprite 0 hits an opaque background pixel, except at x=255
+                    if i == 0 && self.sprite_zero && bg != 0 && x != 255 {
+                        self.sprite_zero_hit = true;
+                    }
+                    break;
                 }
             }
         }
+
+        let index = match sprite {
+            Some((sprite, behind)) if bg == 0 || !behind => sprite,
+            _ => bg,
+        };
+        let mut colour = self.pal[index] & 0x3f;
+        if (self.ppumask & 0x01) != 0 {
+            // greyscale
+            colour &= 0x30;
+        }
+        let emphasis = ((self.ppumask & 0xe0) as u16) << 1;
+        self.pixels[(y << 8) | x] = emphasis | colour as u16;
     }
 
     pub fn write_ppuctrl(&mut self, value: u8) {
+        self.catch_up();
+        // enabling nmi during vblank sends one straight away
+        if self.vblank && (self.ppuctrl & 0x80) == 0 && (value & 0x80) != 0 {
+            self.nmi = true;
+        }
         self.ppuctrl = value;
+        self.t = (self.t & !0x0c00) | (((value & 0x03) as u16) << 10);
     }
 
     pub fn write_ppumask(&mut self, value: u8) {
+        self.catch_up();
         self.ppumask = value;
     }
 
-    pub fn read_ppustatus(&mut self) -> u8 {
-        // check for sprite 0 hit
-        // todo: account for switching nametables
-        // todo: account for screen scroll
-        // todo: account for sprite flipping vertically/horizontally
-        let cycles = unsafe { self.cycles.as_ref().get() };
-        let y = (cycles * 3) / 341;
-        let x = (cycles * 3) % 341;
-        let spr0_y = self.oam[0] as usize + 1;
-        let spr0_tile = self.oam[1] as usize;
-        let spr0_x = self.oam[3] as usize;
-        let mut spr0hit = false;
-        for i in spr0_y..(spr0_y+8).min(y).min(0xf0) {
-            for j in spr0_x..(spr0_x+8).min(0x100) {
-                let tile = self.mem[((i>>3)<<5)|(j>>3)] as usize;
-                if self.chr1[((tile>>4)<<3)+(i&0x7)][((tile&0xf)<<3)+(j&0xf)] != 0
-                    && self.chr0[((spr0_tile>>4)<<3)+(i-spr0_y)][((spr0_tile&0xf)<<3)+(j-spr0_x)] != 0 {
-                    spr0hit = true;
-                    break;
-                }
+    // a write to any register, including $2002, sets the latch
+    pub fn write_io_latch(&mut self, value: u8) {
+        self.drive_io_latch(value, 0xff);
+    }
+
+    // reading a write-only register
+    pub fn read_io_latch(&mut self) -> u8 {
+        let now = self.now();
+        for bit in 0..8 {
+            if now - self.io_refreshed[bit] > IO_DECAY {
+                self.io_latch &= !(1 << bit);
             }
         }
-        if y >= spr0_y && y < (spr0_y+8).min(0xf0) {
-            for j in spr0_x..(spr0_x+8).min(x+1).min(0x100) {
-                let tile = self.mem[((y>>3)<<5)|(j>>3)] as usize;
-                if self.chr1[((tile>>4)<<3)+(y&0x7)][((tile&0xf)<<3)+(j&0xf)] != 0
-                    && self.chr0[((spr0_tile>>4)<<3)+(y-spr0_y)][((spr0_tile&0xf)<<3)+(j-spr0_x)] != 0 {
-                    spr0hit = true;
-                    break;
-                }
+        self.io_latch
+    }
+
+    // sets the bits in mask, the others are left to decay
+    fn drive_io_latch(&mut self, value: u8, mask: u8) {
+        let now = self.now();
+        for bit in 0..8 {
+            if (mask & (1 << bit)) != 0 {
+                self.io_refreshed[bit] = now;
             }
         }
+        self.io_latch = (self.io_latch & !mask) | (value & mask);
+    }
+
+    pub fn read_ppustatus(&mut self) -> u8 {
+        self.catch_up();
+        // the low 5 bits are open bus
+        let mut status = self.status() | (self.read_io_latch() & 0x1f);
+
+        // reading just as vblank starts races with the flag being set
+        let vblank = self.vblank_dot();
+        if self.rendered == vblank - 1 {
+            // too early to see it, and it won't be set this frame
+            self.vblank_suppressed = true;
+        } else if self.rendered == vblank {
+            // seen, but cleared by the read before the nmi
+            status |= 0x80;
+            self.vblank_suppressed = true;
+        } else if self.rendered == vblank + 1 {
+            self.nmi = false;
+        }
 
-        self.ppustatus = (((y>240) as u8)<<7)|((spr0hit as u8)<<6);
+        self.vblank = false;
+        self.w = false;
+        self.drive_io_latch(status, 0xe0);
+        status
+    }
 
-        self.latch = false;
+    fn status(&self) -> u8 {
+        ((self.vblank as u8) << 7) | ((self.sprite_zero_hit as u8) << 6) | ((self.sprite_overflow as u8) << 5)
+    }
 
-        self.ppustatus
+    // register contents without read side effects
+    pub fn peek(&self, addr: u16) -> u8 {
+        match addr & 0x7 {
+            0x0 => self.ppuctrl,
+            0x1 => self.ppumask,
+            0x2 => self.status(),
+            0x3 => self.oamaddr,
+            0x4 => self.oam[self.oamaddr as usize],
+            0x7 => self.ppudata_buf,
+            _ => 0,
+        }
     }
 
     pub fn write_oamaddr(&mut self, value: u8) {
         self.oamaddr = value;
     }
 
-    pub fn read_oamdata(&self) -> u8 {
-        self.oam[self.oamaddr as usize]
+    // loses the row's contents if it's gone too long without a refresh,
+    // only on ntsc, pal ppus refresh oam themselves during their long
+    // vblank and the dendy clones use static ram
+    fn refresh_oam(&mut self, addr: u8) {
+        let now = self.frame_start + self.rendered;
+        let row = (addr >> 3) as usize;
+        if self.region == Region::Ntsc && now - self.oam_refreshed[row] > OAM_DECAY {
+            self.oam[row << 3..(row + 1) << 3].fill(0);
+        }
+        self.oam_refreshed[row] = now;
+    }
+
+    pub fn read_oamdata(&mut self) -> u8 {
+        self.catch_up();
+        self.refresh_oam(self.oamaddr);
+        let value = self.oam[self.oamaddr as usize];
+        self.drive_io_latch(value, 0xff);
+        value
     }
 
     pub fn write_oamdata(&mut self, value: u8) {
+        self.catch_up();
+        if self.rendering() {
+            // the write is dropped, but the address is still bumped
+            // as if it were the sprite evaluation moving to the next sprite
+            self.oamaddr = self.oamaddr.wrapping_add(4);
+            return;
+        }
+        self.refresh_oam(self.oamaddr);
+        // the attribute byte has no bits 2-4
+        let value = if (self.oamaddr & 0x3) == 2 { value & 0xe3 } else { value };
         self.oam[self.oamaddr as usize] = value;
         self.oamaddr = self.oamaddr.wrapping_add(1);
     }
 
     pub fn write_ppuscroll(&mut self, value: u8) {
-        if !self.latch {
-            self.ppuscroll_x = value;
+        self.catch_up();
+        if !self.w {
+            self.t = (self.t & !0x001f) | ((value >> 3) as u16);
+            self.x = value & 0x07;
         } else {
-            self.ppuscroll_y = value;
+            self.t = (self.t & !0x73e0) | (((value & 0x07) as u16) << 12) | (((value & 0xf8) as u16) << 2);
         }
-        self.latch ^= true;
+        self.w ^= true;
     }
 
     pub fn write_ppuaddr(&mut self, value: u8) {
-        if !self.latch {
-            self.ppuaddr = ((value as u16) << 8) | (self.ppuaddr & 0x00ff);
+        self.catch_up();
+        if !self.w {
+            // bit 14 is cleared too
+            self.t = (((value & 0x3f) as u16) << 8) | (self.t & 0x00ff);
+        } else {
+            self.t = (self.t & 0xff00) | (value as u16);
+            // takes effect immediately, so this changes the scroll mid-frame
+            self.v = self.t;
+        }
+        self.w ^= true;
+    }
+
+    // $2007 accesses move to the next address, while rendering this
+    // bumps both the coarse x and y scroll instead
+    fn increment_v(&mut self) {
+        if self.rendering() {
+            self.increment_x();
+            self.increment_y();
         } else {
-            self.ppuaddr = (self.ppuaddr & 0xff00) | (value as u16);
+            self.v = self.v.wrapping_add(if (self.ppuctrl & 0x04) == 0 { 0x01 } else { 0x20 }) & 0x7fff;
         }
-        self.latch ^= true;
+    }
+
+    // palette ram, $3f10/$3f14/$3f18/$3f1c are the same as $3f00/$3f04/...
+    fn palette_index(addr: u16) -> usize {
+        let addr = addr & 0x1f;
+        (if (addr & 0x13) == 0x10 { addr & 0x0f } else { addr }) as usize
     }
 
     pub fn read_ppudata(&mut self) -> u8 {
-        let ppuaddr = self.ppuaddr;
-        self.ppuaddr = self.ppuaddr.wrapping_add(if (self.ppuctrl & 0x04) == 0 { 0x01 } else { 0x20 });
-        let ppudata = self.ppudata_buf;
-        self.ppudata_buf = match ppuaddr & 0x3fff {
-            0x0000..0x1000 => {
-                // pattern table 1
-                self.chr[ppuaddr as usize]
-                // let chr = &self.chr0;
-                // let y = (((ppuaddr>>5)&0x78)|(ppuaddr&7)) as usize;
-                // let x = ((ppuaddr>>1)&0x78) as usize;
-                // let shift = ((ppuaddr>>3)&1) as usize;
-                // (((chr[y][x] >> shift) & 1) << 7)
-                //     | (((chr[y][x|1] >> shift) & 1) << 6)
-                //     | (((chr[y][x|2] >> shift) & 1) << 5)
-                //     | (((chr[y][x|3] >> shift) & 1) << 4)
-                //     | (((chr[y][x|4] >> shift) & 1) << 3)
-                //     | (((chr[y][x|5] >> shift) & 1) << 2)
-                //     | (((chr[y][x|6] >> shift) & 1) << 1)
-                //     | ((chr[y][x|7] >> shift) & 1)
-            },
-            0x1000..0x2000 => {
-                // pattern table 2
-                self.chr[ppuaddr as usize]
-                // let chr = &self.chr1;
-                // let y = (((ppuaddr>>5)&0x78)|(ppuaddr&7)) as usize;
-                // let x = ((ppuaddr>>1)&0x78) as usize;
-                // let shift = ((ppuaddr>>3)&1) as usize;
-                // (((chr[y][x] >> shift) & 1) << 7)
-                //     | (((chr[y][x|1] >> shift) & 1) << 6)
-                //     | (((chr[y][x|2] >> shift) & 1) << 5)
-                //     | (((chr[y][x|3] >> shift) & 1) << 4)
-                //     | (((chr[y][x|4] >> shift) & 1) << 3)
-                //     | (((chr[y][x|5] >> shift) & 1) << 2)
-                //     | (((chr[y][x|6] >> shift) & 1) << 1)
-                //     | ((chr[y][x|7] >> shift) & 1)
-            },
-            0x2000..0x2400 => {
-                // nametable 1
-                self.mem[(ppuaddr & 0x7ff) as usize]
-            },
-            0x2400..0x2800 => {
-                // nametable 2
-                self.mem[(ppuaddr & 0x7ff) as usize]
-            },
-            0x2800..0x2c00 => {
-                // nametable 3
-                self.mem[(ppuaddr & 0x7ff) as usize]
-            },
-            0x2c00..0x3000 => {
-                // nametable 4
-                self.mem[(ppuaddr & 0x7ff) as usize]
-            },
-            0x3f00..0x4000 => {
-                // palette ram
-                self.pal[(ppuaddr & 0x1f) as usize]
-            },
-            _ => panic!("memory read out of range: 0x{:x}", ppuaddr),
+        self.catch_up();
+        let ppuaddr = self.v & 0x3fff;
+        self.increment_v();
+        if ppuaddr < 0x2000 {
+            self.log_chr(ppuaddr, 1, cdl::READ);
+        }
+        let ppudata = if ppuaddr >= 0x3f00 {
+            // palette reads skip the buffer, the top 2 bits are open bus
+            let mut colour = self.pal[Self::palette_index(ppuaddr)] & 0x3f;
+            if (self.ppumask & 0x01) != 0 {
+                colour &= 0x30;
+            }
+            let value = colour | (self.read_io_latch() & 0xc0);
+            self.drive_io_latch(value, 0x3f);
+            self.watch(Access::Read, ppuaddr, colour);
+            value
+        } else {
+            self.drive_io_latch(self.ppudata_buf, 0xff);
+            self.ppudata_buf
+        };
+        // the buffer is filled from the nametable under the palette,
+        // $3000-$3eff mirrors $2000-$2eff
+        self.ppudata_buf = match ppuaddr {
+            0x0000..0x2000 => self.chr[ppuaddr as usize],
+            _ => self.mem[self.nametable(ppuaddr)],
         };
+        if ppuaddr < 0x3f00 {
+            self.watch(Access::Read, ppuaddr, self.ppudata_buf);
+        }
 
         ppudata
     }
 
     pub fn write_ppudata(&mut self, value: u8) {
-        let ppuaddr = self.ppuaddr;
-        self.ppuaddr = self.ppuaddr.wrapping_add(if (self.ppuctrl & 0x04) == 0 { 0x01 } else { 0x20 });
-        match ppuaddr & 0x3fff {
-            0x2000..0x2400 => {
-                // nametable 1
-                self.mem[(ppuaddr & 0x7ff) as usize] = value;
-            },
-            0x2400..0x2800 => {
-                // nametable 2
-                self.mem[(ppuaddr & 0x7ff) as usize] = value;
-            },
-            0x2800..0x2c00 => {
-                // nametable 3
-                self.mem[(ppuaddr & 0x7ff) as usize] = value;
+        self.catch_up();
+        let ppuaddr = self.v & 0x3fff;
+        self.watch(Access::Write, ppuaddr, value);
+        self.increment_v();
+        match ppuaddr {
+            0x0000..0x2000 => {
+                // pattern tables
+                if self.chr_ram {
+                    self.chr[ppuaddr as usize] = value;
+                }
             },
-            0x2c00..0x3000 => {
-                // nametable 4
-                self.mem[(ppuaddr & 0x7ff) as usize] = value;
+            0x3f00..0x4000 => self.pal[Self::palette_index(ppuaddr)] = value,
+            // $3000-$3eff mirrors $2000-$2eff
+            _ => {
+                let addr = self.nametable(ppuaddr);
+                self.mem[addr] = value;
             },
-            0x3f00..0x4000 => {
-                // palette ram
-                let mut addr = ppuaddr & 0x1f;
-                if (addr & 0x13) == 0x10 { addr &= 0xf; }
-                self.pal[addr as usize] = value;
-                if addr == 0x0 {
-                    self.bg = PALETTE[value as usize];
-                } else if (addr & 0x3) != 0 {
-                    self.palette[(addr as usize) >> 2][(addr as usize) & 0x3] = PALETTE[value as usize];
+        }
+    }
+}
+
+// a sprite as the oam viewer sees it
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct OamEntry {
+    pub x: u8,
+    pub y: u8,
+    pub tile: u8,
+    pub palette: u8,
+    pub behind: bool,
+    pub flip_h: bool,
+    pub flip_v: bool,
+}
+
+// debug views, none of these have side effects
+impl Ppu {
+    // a pixel of a tile, 0-3
+    fn chr_pixel(&self, addr: u16, x: usize) -> u8 {
+        let (lo, hi) = (self.chr[addr as usize], self.chr[(addr | 0x8) as usize]);
+        (((hi >> (7 - x)) & 1) << 1) | ((lo >> (7 - x)) & 1)
+    }
+
+    // a pixel drawn with one of the 8 palettes, 0 is the backdrop
+    fn pal_colour(&self, palette: &Palette, pal: usize, pixel: u8) -> u32 {
+        let index = if pixel == 0 { 0 } else { (pal << 2) | pixel as usize };
+        palette.colours()[(self.pal[index] & 0x3f) as usize]
+    }
+
+    // all four nametables at 512x480, with the area the next frame will
+    // show outlined
+    pub fn nametable_view(&self, palette: &Palette) -> Image {
+        let mut image = Image::new(512, 480);
+        let table = ((self.ppuctrl & 0x10) as u16) << 8;
+        for nt in 0..4 {
+            let base = 0x2000 | (nt << 10);
+            for ty in 0..30 {
+                for tx in 0..32 {
+                    let tile = self.mem[self.nametable(base | (ty << 5) | tx)] as u16;
+                    let attr = self.mem[self.nametable(base | 0x3c0 | ((ty >> 2) << 3) | (tx >> 2))];
+                    let pal = (attr >> (((ty & 0x2) << 1) | (tx & 0x2))) & 0x3;
+                    for row in 0..8 {
+                        for col in 0..8 {
+                            let pixel = self.chr_pixel(table | (tile << 4) | row as u16, col);
+                            let x = ((nt & 1) as usize) * 256 + (tx as usize) * 8 + col;
+                            let y = ((nt >> 1) as usize) * 240 + (ty as usize) * 8 + row;
+                            image.set(x, y, self.pal_colour(palette, pal as usize, pixel));
+                        }
+                    }
                 }
-            },
-            _ => panic!("memory write out of range: 0x{:x}", ppuaddr&0x3fff),
+            }
+        }
+
+        // the scroll from t, which is copied into v at the start of the frame
+        let scroll_x = ((((self.t & 0x1f) << 3) as usize) | self.x as usize) + (((self.t >> 10) & 1) as usize) * 256;
+        let scroll_y = (((((self.t >> 5) & 0x1f) << 3) | ((self.t >> 12) & 0x7)) as usize) + (((self.t >> 11) & 1) as usize) * 240;
+        let mut invert = |x: usize, y: usize| {
+            let (x, y) = (x % 512, y % 480);
+            image.set(x, y, !image.get(x, y));
+        };
+        for i in 0..256 {
+            invert(scroll_x + i, scroll_y);
+            invert(scroll_x + i, scroll_y + 239);
+        }
+        for i in 1..239 {
+            invert(scroll_x, scroll_y + i);
+            invert(scroll_x + 255, scroll_y + i);
+        }
+        image
+    }
+
+    // a pattern table at 128x128 drawn with one of the 8 palettes,
+    // 0-3 are the background's and 4-7 the sprites'
+    pub fn pattern_table_view(&self, table: usize, pal: usize, palette: &Palette) -> Image {
+        let mut image = Image::new(128, 128);
+        for tile in 0..0x100 {
+            for row in 0..8 {
+                for col in 0..8 {
+                    let addr = ((table as u16 & 1) << 12) | (tile << 4) | row as u16;
+                    let pixel = self.chr_pixel(addr, col);
+                    let x = ((tile & 0xf) as usize) * 8 + col;
+                    let y = ((tile >> 4) as usize) * 8 + row;
+                    image.set(x, y, self.pal_colour(palette, pal & 0x7, pixel));
+                }
+            }
+        }
+        image
+    }
+
+    // the 64 sprites drawn as they would be, 8 to a row in 8x16 cells
+    // so tall sprites fit, 64x128 in all
+    pub fn oam_view(&self, palette: &Palette) -> Image {
+        let mut image = Image::new(64, 128);
+        let height = self.sprite_height();
+        for (i, entry) in self.oam_entries().iter().enumerate() {
+            for row in 0..height {
+                let pattern_row = if entry.flip_v { height - 1 - row } else { row };
+                let addr = self.sprite_pattern(entry.tile as u16, pattern_row as u16);
+                for col in 0..8 {
+                    let pixel = self.chr_pixel(addr, if entry.flip_h { 7 - col } else { col });
+                    let colour = self.pal_colour(palette, 4 | entry.palette as usize, pixel);
+                    image.set((i & 0x7) * 8 + col, (i >> 3) * 16 + row, colour);
+                }
+            }
+        }
+        image
+    }
+
+    pub fn oam_entries(&self) -> Vec<OamEntry> {
+        self.oam.chunks_exact(4)
+            .map(|sprite| OamEntry {
+                x: sprite[3],
+                y: sprite[0],
+                tile: sprite[1],
+                palette: sprite[2] & 0x3,
+                behind: (sprite[2] & 0x20) != 0,
+                flip_h: (sprite[2] & 0x40) != 0,
+                flip_v: (sprite[2] & 0x80) != 0,
+            })
+            .collect()
+    }
+
+    // the 32 palette entries as 8x8 swatches, background on the top row
+    // and sprites on the bottom, 128x16
+    pub fn palette_view(&self, palette: &Palette) -> Image {
+        let mut image = Image::new(128, 16);
+        for i in 0..0x20 {
+            let colour = palette.colours()[(self.pal[Self::palette_index(0x3f00 | i as u16)] & 0x3f) as usize];
+            for y in 0..8 {
+                for x in 0..8 {
+                    image.set((i & 0xf) * 8 + x, (i >> 4) * 8 + y, colour);
+                }
+            }
         }
+        image
     }
 }
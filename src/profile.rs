@@ -0,0 +1,211 @@
+use std::{collections::HashMap, fmt, io::{self, Write}};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Kind {
+    // whatever was running when profiling started
+    Root,
+    Subroutine,
+    Nmi,
+    Irq,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Routine {
+    pub kind: Kind,
+    pub addr: u16,
+}
+
+impl fmt::Display for Routine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            Kind::Root => write!(f, "root"),
+            Kind::Subroutine => write!(f, "${:04X}", self.addr),
+            Kind::Nmi => write!(f, "nmi:${:04X}", self.addr),
+            Kind::Irq => write!(f, "irq:${:04X}", self.addr),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Stats {
+    pub calls: usize,
+    // cycles including everything called from the routine
+    pub inclusive: usize,
+    // cycles spent in the routine itself
+    pub exclusive: usize,
+    // most inclusive cycles spent in a single frame
+    pub frame_max: usize,
+
+    frame: usize,
+}
+
+struct Frame {
+    routine: Routine,
+    // stack pointer before the call, the frame is gone once sp is back above it
+    sp: u8,
+    entry: usize,
+    path: usize,
+}
+
+pub struct Profiler {
+    stack: Vec<Frame>,
+    stats: HashMap<Routine, Stats>,
+
+    // call paths for folded stacks, each is (parent path, routine)
+    paths: Vec<(usize, Routine)>,
+    path_ids: HashMap<(usize, Routine), usize>,
+    path_cycles: Vec<usize>,
+
+    last: usize,
+    frame_start: usize,
+    frames: usize,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        let root = Routine { kind: Kind::Root, addr: 0 };
+        let mut stats = HashMap::new();
+        stats.insert(root, Stats { calls: 1, ..Stats::default() });
+        Self {
+            stack: vec![Frame {
+                routine: root,
+                sp: 0,
+                entry: 0,
+                path: 0,
+            }],
+            stats,
+            paths: vec![(0, root)],
+            path_ids: HashMap::new(),
+            path_cycles: vec![0],
+            last: 0,
+            frame_start: 0,
+            frames: 0,
+        }
+    }
+
+    // called when the profiler is attached
+    pub(crate) fn start(&mut self, now: usize) {
+        self.last = now;
+        self.frame_start = now;
+        for frame in &mut self.stack {
+            frame.entry = now;
+        }
+    }
+
+    // give the cycles since the last event to the routine on top of the stack
+    fn attribute(&mut self, now: usize) {
+        let cycles = now - self.last;
+        self.last = now;
+        let top = self.stack.last().unwrap();
+        self.path_cycles[top.path] += cycles;
+        self.stats.get_mut(&top.routine).unwrap().exclusive += cycles;
+    }
+
+    // a jsr or interrupt, sp is the stack pointer before anything was pushed
+    pub(crate) fn call(&mut self, kind: Kind, addr: u16, sp: u8, now: usize) {
+        self.attribute(now);
+
+        let routine = Routine { kind, addr };
+        let parent = self.stack.last().unwrap().path;
+        let path = *self.path_ids.entry((parent, routine)).or_insert_with(|| {
+            self.paths.push((parent, routine));
+            self.path_cycles.push(0);
+            self.paths.len() - 1
+        });
+        self.stats.entry(routine).or_default().calls += 1;
+        self.stack.push(Frame {
+            routine,
+            sp,
+            entry: now,
+            path,
+        });
+    }
+
+    // an rts or rti, sp is the stack pointer after returning
+    pub(crate) fn ret(&mut self, sp: u8, now: usize) {
+        self.attribute(now);
+
+        // pops every frame the stack has unwound past, which also copes with
+        // code that discards return addresses or returns with an rts trick
+        while self.stack.len() > 1 && self.stack.last().unwrap().sp <= sp {
+            let frame = self.stack.pop().unwrap();
+            let recursive = self.stack.iter().any(|f| f.routine == frame.routine);
+            let stats = self.stats.get_mut(&frame.routine).unwrap();
+            if !recursive {
+                stats.inclusive += now - frame.entry;
+                stats.frame += now - frame.entry.max(self.frame_start);
+            }
+        }
+    }
+
+    pub(crate) fn end_frame(&mut self, now: usize) {
+        self.attribute(now);
+
+        // routines still running count towards this frame too
+        let mut seen = Vec::with_capacity(self.stack.len());
+        for frame in &self.stack {
+            if !seen.contains(&frame.routine) {
+                seen.push(frame.routine);
+                let stats = self.stats.get_mut(&frame.routine).unwrap();
+                stats.frame += now - frame.entry.max(self.frame_start);
+            }
+        }
+        for stats in self.stats.values_mut() {
+            stats.frame_max = stats.frame_max.max(stats.frame);
+            stats.frame = 0;
+        }
+        self.frame_start = now;
+        self.frames += 1;
+    }
+
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    // sorted by inclusive cycles, routines still on the stack
+    // only include the cycles up to their last return
+    pub fn stats(&self) -> Vec<(Routine, Stats)> {
+        let mut stats: Vec<_> = self.stats.iter().map(|(&r, &s)| (r, s)).collect();
+        stats.sort_by(|a, b| b.1.inclusive.cmp(&a.1.inclusive).then(a.0.cmp(&b.0)));
+        stats
+    }
+
+    pub fn write_report(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "{:<12} {:>8} {:>12} {:>12} {:>10}", "routine", "calls", "inclusive", "exclusive", "frame max")?;
+        for (routine, stats) in self.stats() {
+            writeln!(out, "{:<12} {:>8} {:>12} {:>12} {:>10}",
+                routine.to_string(), stats.calls, stats.inclusive, stats.exclusive, stats.frame_max)?;
+        }
+        Ok(())
+    }
+
+    // exclusive cycles per call stack in the folded format used by flamegraph.pl
+    // e.g. root;nmi:$C123;$C456 1234
+    pub fn write_folded(&self, out: &mut dyn Write) -> io::Result<()> {
+        let mut names = Vec::new();
+        for (path, &cycles) in self.path_cycles.iter().enumerate() {
+            if cycles == 0 {
+                continue;
+            }
+            names.clear();
+            let mut p = path;
+            loop {
+                let (parent, routine) = self.paths[p];
+                names.push(routine.to_string());
+                if p == 0 {
+                    break;
+                }
+                p = parent;
+            }
+            names.reverse();
+            writeln!(out, "{} {}", names.join(";"), cycles)?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
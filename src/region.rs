@@ -0,0 +1,79 @@
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Region {
+    #[default]
+    Ntsc,
+    Pal,
+    // famiclones, with the pal clock but a ppu that's closer to ntsc
+    Dendy,
+}
+
+impl Region {
+    // from an ines header, only nes 2.0 headers can say dendy
+    pub fn from_header(hdr: &[u8]) -> Self {
+        if (hdr[7] & 0x0c) == 0x08 {
+            match hdr[12] & 0x03 {
+                0x1 => Region::Pal,
+                0x3 => Region::Dendy,
+                // 0x2 is a game that works in either
+                _ => Region::Ntsc,
+            }
+        } else if (hdr[9] & 0x01) != 0 {
+            Region::Pal
+        } else {
+            Region::Ntsc
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "ntsc" => Some(Region::Ntsc),
+            "pal" => Some(Region::Pal),
+            "dendy" => Some(Region::Dendy),
+            _ => None,
+        }
+    }
+
+    // cpu cycles per second
+    pub fn cpu_clock(self) -> f64 {
+        match self {
+            Region::Ntsc => 236_250_000.0 / 11.0 / 12.0,
+            Region::Pal => 26_601_712.5 / 16.0,
+            Region::Dendy => 26_601_712.5 / 15.0,
+        }
+    }
+
+    pub fn fps(self) -> f64 {
+        let (ppu, cpu) = self.ppu_ratio();
+        let dots = match self {
+            // every other frame is a dot shorter
+            Region::Ntsc => 341.0 * 262.0 - 0.5,
+            _ => (341 * self.lines()) as f64,
+        };
+        self.cpu_clock() * ppu as f64 / cpu as f64 / dots
+    }
+
+    // ppu cycles per cpu cycle, as a fraction
+    pub(crate) const fn ppu_ratio(self) -> (usize, usize) {
+        match self {
+            Region::Ntsc | Region::Dendy => (3, 1),
+            Region::Pal => (16, 5),
+        }
+    }
+
+    // lines in a frame, the last is the pre-render line
+    pub(crate) const fn lines(self) -> usize {
+        match self {
+            Region::Ntsc => 262,
+            Region::Pal | Region::Dendy => 312,
+        }
+    }
+
+    // the line vblank starts on, dendy waits 50 lines after the picture
+    // so its vblank is as long as ntsc's
+    pub(crate) const fn vblank_line(self) -> usize {
+        match self {
+            Region::Ntsc | Region::Pal => 241,
+            Region::Dendy => 291,
+        }
+    }
+}
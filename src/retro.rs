@@ -15,15 +15,24 @@ pub const DEVICE_ID_JOYPAD_DOWN: c_uint = 5;
 pub const DEVICE_ID_JOYPAD_LEFT: c_uint = 6;
 pub const DEVICE_ID_JOYPAD_RIGHT: c_uint = 7;
 
+pub const ENVIRONMENT_GET_SYSTEM_DIRECTORY: c_uint = 9;
+
 pub const ENVIRONMENT_SET_PIXEL_FORMAT: c_uint = 10;
 
+pub const ENVIRONMENT_GET_VARIABLE: c_uint = 15;
+
+pub const ENVIRONMENT_SET_VARIABLES: c_uint = 16;
+
 pub const ENVIRONMENT_GET_VARIABLE_UPDATE: c_uint = 17;
 
 pub const ENVIRONMENT_SET_SUPPORT_NO_GAME: c_uint = 18;
 
 pub const ENVIRONMENT_GET_LOG_INTERFACE: c_uint = 27;
 
+pub const ENVIRONMENT_SET_SYSTEM_AV_INFO: c_uint = 32;
+
 pub const REGION_NTSC: c_uint = 0;
+pub const REGION_PAL: c_uint = 1;
 
 #[repr(C)]
 pub enum log_level {
@@ -225,6 +234,38 @@ impl system_av_info {
     }
 }
 
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct variable<'a> {
+    pub key: *const c_char,
+    pub value: *const c_char,
+    pub _marker: PhantomData<&'a ()>,
+}
+
+impl Default for variable<'_> {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            key: null(),
+            value: null(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<'a> variable<'a> {
+    #[inline]
+    pub fn key(mut self, key: &'a CStr) -> Self {
+        self.key = key.as_ptr();
+        self
+    }
+    #[inline]
+    pub fn value(mut self, value: &'a CStr) -> Self {
+        self.value = value.as_ptr();
+        self
+    }
+}
+
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
 pub struct game_info<'a> {
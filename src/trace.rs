@@ -0,0 +1,137 @@
+use std::{fmt::Write as _, io::{self, Write}, ops::RangeInclusive};
+
+use crate::{cpu::Cpu, disasm::{self, Instruction, Mnemonic, Mode}, mem::Memory, Controller};
+
+pub enum TraceFormat {
+    // same layout as nestest.log:
+    // C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7
+    Nestest,
+    // template with {pc} {bytes} {disasm} {a} {x} {y} {p} {flags} {sp}
+    // {scanline} {dot} and {cycle} placeholders
+    Custom(String),
+}
+
+pub struct Tracer {
+    out: Box<dyn Write>,
+    format: TraceFormat,
+    range: Option<RangeInclusive<u16>>,
+    line: String,
+}
+
+impl Tracer {
+    pub fn new(out: Box<dyn Write>) -> Self {
+        Self {
+            out,
+            format: TraceFormat::Nestest,
+            range: None,
+            line: String::new(),
+        }
+    }
+
+    #[inline]
+    pub fn format(mut self, format: TraceFormat) -> Self {
+        self.format = format;
+        self
+    }
+    #[inline]
+    pub fn range(mut self, range: RangeInclusive<u16>) -> Self {
+        self.range = Some(range);
+        self
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    // called before the instruction at pc executes
+    pub fn trace<C: Controller>(&mut self, cpu: &Cpu<C>, mem: &Memory<C>) {
+        if let Some(range) = &self.range {
+            if !range.contains(&cpu.pc) {
+                return;
+            }
+        }
+
+        let instr = disasm::disassemble(mem, cpu.pc);
+        let ppu = mem.ppu();
+
+        self.line.clear();
+        match &self.format {
+            TraceFormat::Nestest => {
+                let _ = write!(self.line, "{:04X}  {:<9}{}{:<32}A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} PPU:{:>3},{:>3} CYC:{}",
+                    cpu.pc,
+                    hex_bytes(&instr),
+                    if instr.official { ' ' } else { '*' },
+                    annotate(&instr, cpu, mem),
+                    cpu.a, cpu.x, cpu.y, cpu.sr, cpu.sp,
+                    ppu.scanline(), ppu.dot(),
+                    cpu.cycles(),
+                );
+            },
+            TraceFormat::Custom(template) => {
+                let mut rest = template.as_str();
+                while let Some(start) = rest.find('{') {
+                    self.line.push_str(&rest[..start]);
+                    rest = &rest[start..];
+                    let Some(end) = rest.find('}') else { break };
+                    let _ = match &rest[1..end] {
+                        "pc" => write!(self.line, "{:04X}", cpu.pc),
+                        "bytes" => write!(self.line, "{:<8}", hex_bytes(&instr)),
+                        "disasm" => write!(self.line, "{}", annotate(&instr, cpu, mem)),
+                        "a" => write!(self.line, "{:02X}", cpu.a),
+                        "x" => write!(self.line, "{:02X}", cpu.x),
+                        "y" => write!(self.line, "{:02X}", cpu.y),
+                        "p" => write!(self.line, "{:02X}", cpu.sr),
+                        "flags" => {
+                            for (i, c) in "NV--DIZC".chars().enumerate() {
+                                let set = (cpu.sr & (0x80 >> i)) != 0;
+                                self.line.push(if set { c } else { c.to_ascii_lowercase() });
+                            }
+                            Ok(())
+                        },
+                        "sp" => write!(self.line, "{:02X}", cpu.sp),
+                        "scanline" => write!(self.line, "{}", ppu.scanline()),
+                        "dot" => write!(self.line, "{}", ppu.dot()),
+                        "cycle" => write!(self.line, "{}", cpu.cycles()),
+                        // unknown placeholders are copied through as-is
+                        _ => self.line.write_str(&rest[..=end]),
+                    };
+                    rest = &rest[end + 1..];
+                }
+                self.line.push_str(rest);
+            },
+        }
+        self.line.push('\n');
+
+        // a trace is best-effort, don't stop emulation if the write fails
+        let _ = self.out.write_all(self.line.as_bytes());
+    }
+}
+
+fn hex_bytes(instr: &Instruction) -> String {
+    let mut s = String::with_capacity(8);
+    for (i, b) in instr.bytes().iter().enumerate() {
+        if i != 0 {
+            s.push(' ');
+        }
+        let _ = write!(s, "{:02X}", b);
+    }
+    s
+}
+
+// disassembly with the effective address annotations used by nestest.log
+fn annotate<C: Controller>(instr: &Instruction, cpu: &Cpu<C>, mem: &Memory<C>) -> String {
+    let mut s = instr.to_string();
+    let Some(eff) = instr.effective(mem, cpu.x, cpu.y) else {
+        return s;
+    };
+    let _ = match instr.mode {
+        Mode::Zeropage | Mode::Absolute => write!(s, " = {:02X}", eff.value),
+        Mode::ZeropageX | Mode::ZeropageY => write!(s, " @ {:02X} = {:02X}", eff.addr, eff.value),
+        Mode::AbsoluteX | Mode::AbsoluteY => write!(s, " @ {:04X} = {:02X}", eff.addr, eff.value),
+        Mode::Indirect if instr.mnemonic == Mnemonic::Jmp => write!(s, " = {:04X}", eff.addr),
+        Mode::IndirectX => write!(s, " @ {:02X} = {:04X} = {:02X}", eff.pointer.unwrap_or(0), eff.addr, eff.value),
+        Mode::IndirectY => write!(s, " = {:04X} @ {:04X} = {:02X}", eff.pointer.unwrap_or(0), eff.addr, eff.value),
+        _ => Ok(()),
+    };
+    s
+}
@@ -0,0 +1,115 @@
+// how the 256x240 picture is cropped and shaped for display
+
+// lines and columns cut from each edge, in nes pixels
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Overscan {
+    pub top: usize,
+    pub bottom: usize,
+    pub left: usize,
+    pub right: usize,
+}
+
+impl Overscan {
+    pub fn width(&self) -> usize {
+        256usize.saturating_sub(self.left + self.right).max(1)
+    }
+
+    pub fn height(&self) -> usize {
+        240usize.saturating_sub(self.top + self.bottom).max(1)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Aspect {
+    // the whole picture fills a 4:3 screen, so cropping keeps the
+    // pixels the same shape
+    #[default]
+    FourThree,
+    // the pixel shape of an ntsc tv
+    PixelAspect,
+    // square pixels
+    Square,
+    // fills whatever it's shown in
+    Stretch,
+}
+
+impl Aspect {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "4:3" => Some(Aspect::FourThree),
+            "8:7" => Some(Aspect::PixelAspect),
+            "1:1" => Some(Aspect::Square),
+            "stretch" => Some(Aspect::Stretch),
+            _ => None,
+        }
+    }
+
+    // width over height of a pixel
+    fn pixel(self) -> Option<f64> {
+        match self {
+            Aspect::FourThree => Some((4.0 / 3.0) / (256.0 / 240.0)),
+            Aspect::PixelAspect => Some(8.0 / 7.0),
+            Aspect::Square => Some(1.0),
+            Aspect::Stretch => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Screen {
+    pub overscan: Overscan,
+    pub aspect: Aspect,
+    // only scale by whole numbers, vertically at least
+    pub integer_scale: bool,
+}
+
+impl Screen {
+    pub fn overscan(mut self, overscan: Overscan) -> Self {
+        self.overscan = overscan;
+        self
+    }
+
+    pub fn aspect(mut self, aspect: Aspect) -> Self {
+        self.aspect = aspect;
+        self
+    }
+
+    pub fn integer_scale(mut self, integer_scale: bool) -> Self {
+        self.integer_scale = integer_scale;
+        self
+    }
+
+    // width over height of the cropped picture, none when stretched
+    pub fn ratio(&self) -> Option<f64> {
+        let pixel = self.aspect.pixel()?;
+        Some(self.overscan.width() as f64 * pixel / self.overscan.height() as f64)
+    }
+
+    // where the picture goes in an area of the given size, as x, y,
+    // width and height, centred with black bars around it
+    pub fn viewport(&self, width: usize, height: usize) -> (usize, usize, usize, usize) {
+        let (src_w, src_h) = (self.overscan.width(), self.overscan.height());
+        let (w, h) = match (self.ratio(), self.integer_scale) {
+            (None, false) => (width, height),
+            (None, true) => (src_w * (width / src_w).max(1), src_h * (height / src_h).max(1)),
+            (Some(ratio), false) => {
+                let w = (height as f64 * ratio).round() as usize;
+                if w <= width {
+                    (w, height)
+                } else {
+                    (width, (width as f64 / ratio).round() as usize)
+                }
+            },
+            (Some(ratio), true) => {
+                // the biggest multiple of the height that still fits across
+                let mut n = (height / src_h).max(1);
+                while n > 1 && (src_h as f64 * n as f64 * ratio).round() as usize > width {
+                    n -= 1;
+                }
+                ((src_h as f64 * n as f64 * ratio).round() as usize, src_h * n)
+            },
+        };
+        let (w, h) = (w.min(width), h.min(height));
+        ((width - w) / 2, (height - h) / 2, w, h)
+    }
+}
@@ -0,0 +1,216 @@
+use nes::{apu::SAMPLE_RATE, cdl, Nes};
+
+mod common;
+
+use common::NoInput;
+
+// pulse 1 with the length counter loaded with 10, $4000 comes from $f0
+// and $4015 is read into $00 in a loop
+fn length_counter(vol: u8) -> Nes<NoInput> {
+    let mut nes = common::synthetic(&[
+        (0x8000, &[
+            0xa9, 0x01, 0x8d, 0x15, 0x40, // lda #$01, sta $4015
+            0xa5, 0xf0, 0x8d, 0x00, 0x40, // lda $f0, sta $4000
+            0xa9, 0x00, 0x8d, 0x03, 0x40, // lda #$00, sta $4003
+            0xad, 0x15, 0x40,             // lda $4015
+            0x85, 0x00,                   // sta $00
+            0x4c, 0x0f, 0x80,             // jmp $800f
+        ]),
+    ]);
+    nes.poke(0x00f0, vol);
+    nes
+}
+
+#[test]
+fn length_counter_expires() {
+    let mut nes = length_counter(0x00);
+    nes.run();
+    assert_eq!(nes.peek(0x0000) & 0x01, 0x01);
+    // clocked twice a frame
+    for _ in 0..6 {
+        nes.run();
+    }
+    assert_eq!(nes.peek(0x0000) & 0x01, 0x00);
+
+    // unless it's halted
+    let mut nes = length_counter(0x20);
+    for _ in 0..7 {
+        nes.run();
+    }
+    assert_eq!(nes.peek(0x0000) & 0x01, 0x01);
+}
+
+#[test]
+fn clear_registers() {
+    // the usual loop clearing every register, including the unused ones
+    let mut nes = common::synthetic(&[
+        (0x8000, &[
+            0xa2, 0x00,       // ldx #$00
+            0xa9, 0x00,       // lda #$00
+            0x9d, 0x00, 0x40, // sta $4000,x
+            0xe8,             // inx
+            0xe0, 0x18,       // cpx #$18
+            0xd0, 0xf8,       // bne $8004
+            0x8d, 0x00, 0x50, // sta $5000
+            0x86, 0x00,       // stx $00
+            0x4c, 0x11, 0x80, // jmp $8011
+        ]),
+    ]);
+    nes.poke(0x0000, 0x00);
+    nes.run();
+    assert_eq!(nes.peek(0x0000), 0x18);
+}
+
+// $4017 is written from $f0, and the irq handler counts irqs in $01
+fn frame_irq(mode: u8) -> Nes<NoInput> {
+    let mut nes = common::synthetic(&[
+        (0x8000, &[
+            0xa5, 0xf0, 0x8d, 0x17, 0x40, // lda $f0, sta $4017
+            0x58,                         // cli
+            0x4c, 0x06, 0x80,             // jmp $8006
+        ]),
+        (0x8100, &[
+            0xe6, 0x01,                   // inc $01
+            0xad, 0x15, 0x40,             // lda $4015
+            0x40,                         // rti
+        ]),
+        (0xfffe, &[0x00, 0x81]),
+    ]);
+    nes.poke(0x0001, 0);
+    nes.poke(0x00f0, mode);
+    for _ in 0..10 {
+        nes.run();
+    }
+    nes
+}
+
+#[test]
+fn frame_counter_irq() {
+    // once every 29830 cycles in 4 step mode
+    let irqs = frame_irq(0x00).peek(0x0001);
+    assert!((9..=10).contains(&irqs), "{} irqs", irqs);
+    // but not when inhibited or in 5 step mode
+    assert_eq!(frame_irq(0x40).peek(0x0001), 0);
+    assert_eq!(frame_irq(0x80).peek(0x0001), 0);
+}
+
+// plays a sample from $c400 at the fastest rate with the irq enabled,
+// $4013 comes from $f0 and $4015 is read into $00 in a loop
+fn dmc(len: u8) -> Nes<NoInput> {
+    let mut nes = common::synthetic(&[
+        (0x8000, &[
+            0xa9, 0x8f, 0x8d, 0x10, 0x40, // lda #$8f, sta $4010
+            0xa9, 0x10, 0x8d, 0x12, 0x40, // lda #$10, sta $4012
+            0xa5, 0xf0, 0x8d, 0x13, 0x40, // lda $f0, sta $4013
+            0xa9, 0x10, 0x8d, 0x15, 0x40, // lda #$10, sta $4015
+            0xad, 0x15, 0x40,             // lda $4015
+            0x85, 0x00,                   // sta $00
+            0x4c, 0x14, 0x80,             // jmp $8014
+        ]),
+    ]);
+    nes.poke(0x00f0, len);
+    nes.start_cdl();
+    nes.run();
+    nes
+}
+
+#[test]
+fn dmc_sample() {
+    // 17 bytes at 432 cycles each finish within a frame
+    let nes = dmc(0x01);
+    assert_eq!(nes.peek(0x0000) & 0x90, 0x80);
+    // every byte was read by dma
+    let prg = nes.cdl().unwrap().prg();
+    assert!(prg[0x400..0x411].iter().all(|&flags| (flags & cdl::PCM) != 0));
+    assert_eq!(prg[0x411] & cdl::PCM, 0);
+
+    // a long one is still going
+    let nes = dmc(0xff);
+    assert_eq!(nes.peek(0x0000) & 0x90, 0x10);
+}
+
+// one frame of audio
+fn audio(nes: &mut Nes<NoInput>) -> Vec<i16> {
+    let mut buf = vec![0; (SAMPLE_RATE as f64 / nes.region().fps()) as usize * 2];
+    nes.play_audio(&mut buf);
+    buf
+}
+
+#[test]
+fn pulse_tone() {
+    let mut nes = common::synthetic(&[
+        (0x8000, &[
+            0xa9, 0x01, 0x8d, 0x15, 0x40, // lda #$01, sta $4015
+            0xa9, 0xbf, 0x8d, 0x00, 0x40, // lda #$bf, sta $4000
+            0xa9, 0xfd, 0x8d, 0x02, 0x40, // lda #$fd, sta $4002
+            0xa9, 0x00, 0x8d, 0x03, 0x40, // lda #$00, sta $4003
+            0x4c, 0x14, 0x80,             // jmp $8014
+        ]),
+    ]);
+    nes.run();
+    audio(&mut nes);
+    nes.run();
+    let buf = audio(&mut nes);
+    let (min, max) = (buf.iter().min().unwrap(), buf.iter().max().unwrap());
+    assert!(max - min > 3000, "{}..{}", min, max);
+    // the same on both sides
+    assert!(buf.chunks_exact(2).all(|pair| pair[0] == pair[1]));
+
+    // and nothing without it, once the filter has taken out the dc
+    // offset of the triangle sitting at its first step
+    let mut nes = common::synthetic(&[(0x8000, &[0x4c, 0x00, 0x80])]);
+    for _ in 0..2 {
+        nes.run();
+        audio(&mut nes);
+    }
+    nes.run();
+    assert!(audio(&mut nes).iter().all(|&sample| sample.abs() <= 10));
+}
+
+#[test]
+fn save_state() {
+    let mut nes = length_counter(0x00);
+    nes.run();
+    let state = nes.apu_mut().save_state();
+    for _ in 0..6 {
+        nes.run();
+    }
+    assert_eq!(nes.apu_mut().read_snd_chn() & 0x01, 0x00);
+
+    // a state of the wrong size changes nothing
+    let after = nes.apu_mut().save_state();
+    assert!(nes.apu_mut().load_state(&state[..state.len() - 1]).is_err());
+    assert!(nes.apu_mut().load_state(&[state.as_slice(), &[0]].concat()).is_err());
+    assert_eq!(nes.apu_mut().save_state(), after);
+
+    // the length counter is back
+    nes.apu_mut().load_state(&state).unwrap();
+    assert_eq!(nes.apu_mut().save_state(), state);
+    assert_eq!(nes.apu_mut().read_snd_chn() & 0x01, 0x01);
+}
+
+#[test]
+fn load_garbage_state() {
+    // whatever a state holds, loading it can't make the apu panic
+    let mut nes = length_counter(0x00);
+    let len = nes.apu_mut().save_state().len();
+    let mut states: Vec<Vec<u8>> = [0x00, 0x55, 0xaa, 0xff].iter().map(|&byte| vec![byte; len]).collect();
+    // and mixed bytes from an lcg, with plenty of zeros for the flags
+    // and $ff for the extremes
+    for seed in 0..64u32 {
+        let mut x = seed;
+        states.push((0..len).map(|_| {
+            x = x.wrapping_mul(1664525).wrapping_add(1013904223);
+            match x >> 30 {
+                0 => 0x00,
+                1 => 0xff,
+                _ => (x >> 16) as u8,
+            }
+        }).collect());
+    }
+    for state in states {
+        nes.apu_mut().load_state(&state).unwrap();
+        nes.run();
+        audio(&mut nes);
+    }
+}
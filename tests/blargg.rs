@@ -0,0 +1,78 @@
+mod common;
+
+// roms that report through the $6000 status protocol
+macro_rules! status_test {
+    ($name:ident, $rom:expr) => {
+        #[test]
+        #[ignore = "needs the test roms in tests/roms"]
+        fn $name() {
+            let mut nes = common::load($rom);
+            if let Err(e) = common::run_blargg(&mut nes, 60 * 60) {
+                panic!("{}: {}", $rom, e);
+            }
+        }
+    };
+}
+
+// roms that only report on screen
+macro_rules! framebuffer_test {
+    ($name:ident, $rom:expr, $frames:expr) => {
+        #[test]
+        #[ignore = "needs the test roms in tests/roms"]
+        fn $name() {
+            let mut nes = common::load($rom);
+            common::check_framebuffer($rom, &mut nes, $frames);
+        }
+    };
+}
+
+status_test!(instr_basics, "instr_test-v5/rom_singles/01-basics.nes");
+status_test!(instr_implied, "instr_test-v5/rom_singles/02-implied.nes");
+status_test!(instr_immediate, "instr_test-v5/rom_singles/03-immediate.nes");
+status_test!(instr_zero_page, "instr_test-v5/rom_singles/04-zero_page.nes");
+status_test!(instr_zp_xy, "instr_test-v5/rom_singles/05-zp_xy.nes");
+status_test!(instr_absolute, "instr_test-v5/rom_singles/06-absolute.nes");
+status_test!(instr_abs_xy, "instr_test-v5/rom_singles/07-abs_xy.nes");
+status_test!(instr_ind_x, "instr_test-v5/rom_singles/08-ind_x.nes");
+status_test!(instr_ind_y, "instr_test-v5/rom_singles/09-ind_y.nes");
+status_test!(instr_branches, "instr_test-v5/rom_singles/10-branches.nes");
+status_test!(instr_stack, "instr_test-v5/rom_singles/11-stack.nes");
+status_test!(instr_jmp_jsr, "instr_test-v5/rom_singles/12-jmp_jsr.nes");
+status_test!(instr_rts, "instr_test-v5/rom_singles/13-rts.nes");
+status_test!(instr_rti, "instr_test-v5/rom_singles/14-rti.nes");
+status_test!(instr_brk, "instr_test-v5/rom_singles/15-brk.nes");
+status_test!(instr_special, "instr_test-v5/rom_singles/16-special.nes");
+
+framebuffer_test!(cpu_timing, "cpu_timing_test6/cpu_timing_test.nes", 60 * 20);
+
+status_test!(ppu_vbl_basics, "ppu_vbl_nmi/rom_singles/01-vbl_basics.nes");
+status_test!(ppu_vbl_set_time, "ppu_vbl_nmi/rom_singles/02-vbl_set_time.nes");
+status_test!(ppu_vbl_clear_time, "ppu_vbl_nmi/rom_singles/03-vbl_clear_time.nes");
+status_test!(ppu_nmi_control, "ppu_vbl_nmi/rom_singles/04-nmi_control.nes");
+status_test!(ppu_nmi_timing, "ppu_vbl_nmi/rom_singles/05-nmi_timing.nes");
+status_test!(ppu_suppression, "ppu_vbl_nmi/rom_singles/06-suppression.nes");
+status_test!(ppu_nmi_on_timing, "ppu_vbl_nmi/rom_singles/07-nmi_on_timing.nes");
+status_test!(ppu_nmi_off_timing, "ppu_vbl_nmi/rom_singles/08-nmi_off_timing.nes");
+status_test!(ppu_even_odd_frames, "ppu_vbl_nmi/rom_singles/09-even_odd_frames.nes");
+status_test!(ppu_even_odd_timing, "ppu_vbl_nmi/rom_singles/10-even_odd_timing.nes");
+
+framebuffer_test!(sprite_hit_basics, "sprite_hit_tests_2005.10.05/01.basics.nes", 60);
+framebuffer_test!(sprite_hit_alignment, "sprite_hit_tests_2005.10.05/02.alignment.nes", 60);
+framebuffer_test!(sprite_hit_corners, "sprite_hit_tests_2005.10.05/03.corners.nes", 60);
+framebuffer_test!(sprite_hit_flip, "sprite_hit_tests_2005.10.05/04.flip.nes", 60);
+framebuffer_test!(sprite_hit_left_clip, "sprite_hit_tests_2005.10.05/05.left_clip.nes", 60);
+framebuffer_test!(sprite_hit_right_edge, "sprite_hit_tests_2005.10.05/06.right_edge.nes", 60);
+framebuffer_test!(sprite_hit_screen_bottom, "sprite_hit_tests_2005.10.05/07.screen_bottom.nes", 60);
+framebuffer_test!(sprite_hit_double_height, "sprite_hit_tests_2005.10.05/08.double_height.nes", 60);
+framebuffer_test!(sprite_hit_timing_basics, "sprite_hit_tests_2005.10.05/09.timing_basics.nes", 120);
+framebuffer_test!(sprite_hit_timing_order, "sprite_hit_tests_2005.10.05/10.timing_order.nes", 120);
+framebuffer_test!(sprite_hit_edge_timing, "sprite_hit_tests_2005.10.05/11.edge_timing.nes", 120);
+
+status_test!(apu_len_ctr, "apu_test/rom_singles/1-len_ctr.nes");
+status_test!(apu_len_table, "apu_test/rom_singles/2-len_table.nes");
+status_test!(apu_irq_flag, "apu_test/rom_singles/3-irq_flag.nes");
+status_test!(apu_jitter, "apu_test/rom_singles/4-jitter.nes");
+status_test!(apu_len_timing, "apu_test/rom_singles/5-len_timing.nes");
+status_test!(apu_irq_flag_timing, "apu_test/rom_singles/6-irq_flag_timing.nes");
+status_test!(apu_dmc_basics, "apu_test/rom_singles/7-dmc_basics.nes");
+status_test!(apu_dmc_rates, "apu_test/rom_singles/8-dmc_rates.nes");
@@ -0,0 +1,47 @@
+use nes::cdl::{self, Cdl};
+
+mod common;
+
+#[test]
+fn code_and_data() {
+    let mut nes = common::synthetic(&[
+        (0x8000, &[
+            0xad, 0x00, 0x81, // lda $8100
+            0x6c, 0x02, 0x81, // jmp ($8102)
+        ]),
+        (0x8100, &[0x55, 0x00, 0x10, 0x80]),
+        (0x8010, &[
+            0xa0, 0x00,       // ldy #$00
+            0xa9, 0x00,       // lda #$00
+            0x85, 0x00,       // sta $00
+            0xa9, 0x81,       // lda #$81
+            0x85, 0x01,       // sta $01
+            0xb1, 0x00,       // lda ($00),y
+            0x4c, 0x1c, 0x80, // jmp $801c
+        ]),
+    ]);
+    nes.start_cdl();
+    for _ in 0..12 {
+        nes.step();
+    }
+
+    let cdl = nes.cdl().unwrap();
+    let prg = cdl.prg();
+    assert_eq!(prg.len(), 0x4000);
+    assert_eq!(prg[0x0000], cdl::CODE);
+    assert_eq!(prg[0x0002], cdl::CODE);
+    assert_eq!(prg[0x0102], cdl::DATA);
+    assert_eq!(prg[0x0101], 0);
+    assert_eq!(prg[0x0010], cdl::CODE | cdl::INDIRECT_CODE);
+    assert_eq!(prg[0x0012], cdl::CODE);
+    // read directly and through a pointer
+    assert_eq!(prg[0x0100], cdl::DATA | cdl::INDIRECT_DATA);
+
+    // round trip through the file format
+    let data = cdl.to_bytes();
+    assert!(Cdl::load(&data, 0x4000, 0).is_ok());
+    assert!(Cdl::load(&data, 0x8000, 0).is_err());
+    nes.stop_cdl();
+    nes.load_cdl(&data).unwrap();
+    assert_eq!(nes.cdl().unwrap().prg(), &data[..]);
+}
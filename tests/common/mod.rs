@@ -0,0 +1,141 @@
+#![allow(dead_code)]
+
+use std::{cell::RefCell, fs, io::{self, Write}, path::PathBuf, rc::Rc};
+
+use nes::{Controller, Nes};
+
+// a controller with nothing pressed
+pub struct NoInput;
+
+impl Controller for NoInput {
+    fn poll(&mut self) {}
+    fn a(&self) -> bool { false }
+    fn b(&self) -> bool { false }
+    fn select(&self) -> bool { false }
+    fn start(&self) -> bool { false }
+    fn up(&self) -> bool { false }
+    fn down(&self) -> bool { false }
+    fn left(&self) -> bool { false }
+    fn right(&self) -> bool { false }
+}
+
+// collects trace output so it can be compared line by line
+#[derive(Clone, Default)]
+pub struct Log(Rc<RefCell<Vec<u8>>>);
+
+impl Log {
+    // everything written since the last take
+    pub fn take(&self) -> String {
+        String::from_utf8(self.0.borrow_mut().split_off(0)).unwrap()
+    }
+}
+
+impl Write for Log {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+pub fn rom_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/roms").join(name)
+}
+
+// test roms aren't distributed with the repository, so the tests that
+// need them are ignored by default and fail if the rom is missing
+pub fn load(name: &str) -> Nes<NoInput> {
+    let path = rom_path(name);
+    let game = fs::read(&path)
+        .unwrap_or_else(|e| panic!("{}: {} (see tests/roms/README.md)", path.display(), e));
+    let mut nes = Nes::load_from_memory(&game)
+        .unwrap_or_else(|| panic!("{}: unsupported mapper", path.display()));
+    nes.connect(0, NoInput);
+    nes
+}
+
+// blargg's test status protocol:
+// $6000 holds the status, $6001-$6003 hold de b0 61 once it's valid,
+// and $6004 holds a null terminated message
+pub fn run_blargg(nes: &mut Nes<NoInput>, max_frames: usize) -> Result<String, String> {
+    let mut reset_at = None;
+    for frame in 0..max_frames {
+        nes.run();
+
+        if nes.peek(0x6001) != 0xde || nes.peek(0x6002) != 0xb0 || nes.peek(0x6003) != 0x61 {
+            continue;
+        }
+        match nes.peek(0x6000) {
+            // still running
+            0x80 => {},
+            // the test wants a reset after at least 100 ms
+            0x81 => match reset_at {
+                None => reset_at = Some(frame + 7),
+                Some(at) if frame >= at => {
+                    reset_at = None;
+                    nes.reset();
+                },
+                Some(_) => {},
+            },
+            0x00 => return Ok(message(nes)),
+            code => return Err(format!("result {:#04x}: {}", code, message(nes))),
+        }
+    }
+    Err(format!("timed out after {} frames: {}", max_frames, message(nes)))
+}
+
+fn message(nes: &Nes<NoInput>) -> String {
+    let mut s = Vec::new();
+    for addr in 0x6004..0x7000 {
+        match nes.peek(addr) {
+            0 => break,
+            c => s.push(c),
+        }
+    }
+    String::from_utf8_lossy(&s).trim().to_string()
+}
+
+// fnv-1a
+pub fn hash(data: &[u8]) -> u64 {
+    let mut h = 0xcbf29ce484222325u64;
+    for &b in data {
+        h ^= b as u64;
+        h = h.wrapping_mul(0x100000001b3);
+    }
+    h
+}
+
+// compare the framebuffer after a number of frames against the hash
+// recorded next to the rom in <rom>.hash
+pub fn check_framebuffer(name: &str, nes: &mut Nes<NoInput>, frames: usize) {
+    for _ in 0..frames {
+        nes.run();
+    }
+    let actual = hash(nes.framebuffer());
+
+    let path = rom_path(&format!("{}.hash", name));
+    let Ok(expected) = fs::read_to_string(&path) else {
+        panic!("{}: not found, the framebuffer hash is {:016x}", path.display(), actual);
+    };
+    let expected = u64::from_str_radix(expected.trim(), 16)
+        .unwrap_or_else(|_| panic!("{}: invalid hash", path.display()));
+    assert_eq!(actual, expected, "{}: framebuffer hash {:016x} doesn't match {:016x}", name, actual, expected);
+}
+
+// builds a 16 kb nrom image with code placed at the given addresses,
+// the reset vector points at $8000
+pub fn synthetic(code: &[(u16, &[u8])]) -> Nes<NoInput> {
+    let mut game = vec![0; 0x10 + 0x4000];
+    game[..8].copy_from_slice(b"NES\x1a\x01\x00\x00\x00");
+    for &(addr, bytes) in code {
+        let start = 0x10 + (addr as usize & 0x3fff);
+        game[start..start + bytes.len()].copy_from_slice(bytes);
+    }
+    game[0x10 + 0x3ffc] = 0x00;
+    game[0x10 + 0x3ffd] = 0x80;
+    let mut nes = Nes::load_from_memory(&game).unwrap();
+    nes.connect(0, NoInput);
+    nes
+}
@@ -0,0 +1,339 @@
+use nes::{cpu::CpuVariant, Nes};
+
+mod common;
+
+use common::NoInput;
+
+const C: u8 = 0x01;
+const Z: u8 = 0x02;
+const D: u8 = 0x08;
+const V: u8 = 0x40;
+const N: u8 = 0x80;
+
+#[test]
+fn patched_rom_is_decoded_again() {
+    let mut nes = common::synthetic(&[
+        (0x8000, &[
+            0xa9, 0x01,       // lda #$01
+            0x4c, 0x00, 0x80, // jmp $8000
+        ]),
+    ]);
+    let start = nes.cpu().cycles();
+    nes.step();
+    assert_eq!(nes.cpu().a, 0x01);
+    assert_eq!(nes.cpu().cycles() - start, 2);
+    nes.step();
+    assert_eq!(nes.cpu().cycles() - start, 5);
+
+    // the operand changes after the instruction has been cached
+    nes.poke(0x8001, 0x02);
+    nes.step();
+    assert_eq!(nes.cpu().a, 0x02);
+
+    // and so does the opcode, ldx #$03
+    nes.step();
+    nes.poke(0x8000, 0xa2);
+    nes.poke(0x8001, 0x03);
+    nes.step();
+    assert_eq!(nes.cpu().x, 0x03);
+    assert_eq!(nes.cpu().pc, 0x8002);
+
+    // a 16 kb rom is patched in both halves, including whatever was
+    // already decoded from the mirror
+    nes.cpu_mut().pc = 0xc000;
+    nes.step();
+    assert_eq!(nes.cpu().x, 0x03);
+    assert_eq!(nes.peek(0xc001), 0x03);
+    nes.poke(0xc001, 0x04);
+    nes.cpu_mut().pc = 0xc000;
+    nes.step();
+    assert_eq!(nes.cpu().x, 0x04);
+    nes.cpu_mut().pc = 0x8000;
+    nes.step();
+    assert_eq!(nes.cpu().x, 0x04);
+    assert_eq!(nes.peek(0x8001), 0x04);
+}
+
+// result and nvzc flags following "decimal mode" by bruce clark,
+// which describes what an nmos 6502 does for every input including invalid bcd
+fn reference(sbc: bool, decimal: bool, a: u8, value: u8, carry: bool) -> (u8, u8) {
+    let c = carry as i32;
+    let (ai, vi) = (a as i32, value as i32);
+
+    // binary mode, sbc is adc of the complement
+    let operand = if sbc { !value } else { value } as i32;
+    let sum = ai + operand + c;
+    let binary = sum as u8;
+    let flags = |n: bool, v: bool, z: bool, c: bool| {
+        (if n { N } else { 0 }) | (if v { V } else { 0 }) | (if z { Z } else { 0 }) | (if c { C } else { 0 })
+    };
+    let overflow = ((a ^ binary) & (operand as u8 ^ binary) & 0x80) != 0;
+    let binary_flags = flags(binary & 0x80 != 0, overflow, binary == 0, sum > 0xff);
+
+    if !decimal {
+        return (binary, binary_flags);
+    }
+
+    if sbc {
+        let mut al = (ai & 0x0f) - (vi & 0x0f) + c - 1;
+        if al < 0 {
+            al = ((al - 0x06) & 0x0f) - 0x10;
+        }
+        let mut result = (ai & 0xf0) - (vi & 0xf0) + al;
+        if result < 0 {
+            result -= 0x60;
+        }
+        (result as u8, binary_flags)
+    } else {
+        let mut al = (ai & 0x0f) + (vi & 0x0f) + c;
+        if al >= 0x0a {
+            al = ((al + 0x06) & 0x0f) + 0x10;
+        }
+        let mut result = (ai & 0xf0) + (vi & 0xf0) + al;
+        // n and v use the signed sum before the high digit is adjusted
+        let signed = (ai & 0xf0) as u8 as i8 as i32 + (vi & 0xf0) as u8 as i8 as i32 + al;
+        if result >= 0xa0 {
+            result += 0x60;
+        }
+        (result as u8, flags(signed & 0x80 != 0, !(-128..=127).contains(&signed), binary == 0, result >= 0x100))
+    }
+}
+
+fn check_all(nes: &mut Nes<NoInput>, variant: CpuVariant) {
+    nes.cpu_mut().variant = variant;
+    for (pc, sbc) in [(0x8000, false), (0x8002, true)] {
+        for d in [false, true] {
+            for carry in [false, true] {
+                for a in 0..=0xff {
+                    for value in 0..=0xff {
+                        nes.poke(0x0010, value);
+                        let cpu = nes.cpu_mut();
+                        cpu.pc = pc;
+                        cpu.a = a;
+                        cpu.sr = 0x24 | if d { D } else { 0 } | if carry { C } else { 0 };
+                        nes.step();
+
+                        let decimal = d && variant == CpuVariant::Nmos6502;
+                        let expected = reference(sbc, decimal, a, value, carry);
+                        let cpu = nes.cpu();
+                        assert_eq!((cpu.a, cpu.sr & (N | V | Z | C)), expected,
+                            "{:?} {} a={:02x} value={:02x} c={} d={}",
+                            variant, if sbc { "sbc" } else { "adc" }, a, value, carry, d);
+                        assert_eq!(cpu.sr & D, if d { D } else { 0 });
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn adc_sbc_all_inputs() {
+    let mut nes = common::synthetic(&[
+        (0x8000, &[
+            0x65, 0x10, // adc $10
+            0xe5, 0x10, // sbc $10
+        ]),
+    ]);
+    check_all(&mut nes, CpuVariant::Ricoh2A03);
+    check_all(&mut nes, CpuVariant::Nmos6502);
+}
+
+#[test]
+fn brk() {
+    let mut nes = common::synthetic(&[
+        (0x8000, &[0x00, 0xff]), // brk, and its padding byte
+        (0x8100, &[0x40]),       // rti
+        (0xfffe, &[0x00, 0x81]),
+    ]);
+    let cpu = nes.cpu_mut();
+    cpu.sp = 0xfd;
+    cpu.sr = 0x20 | C;
+    let start = nes.cpu().cycles();
+    nes.step();
+    assert_eq!(nes.cpu().cycles() - start, 7);
+    assert_eq!(nes.cpu().pc, 0x8100);
+    assert_eq!(nes.cpu().sp, 0xfa);
+    assert_eq!(nes.cpu().sr & 0x04, 0x04);
+    // the return address skips the padding byte, and b is set in the pushed sr
+    assert_eq!([nes.peek(0x01fd), nes.peek(0x01fc), nes.peek(0x01fb)], [0x80, 0x02, 0x30 | C]);
+
+    nes.step();
+    assert_eq!(nes.cpu().pc, 0x8002);
+    assert_eq!(nes.cpu().sr & (0x04 | C), C);
+}
+
+#[test]
+fn immediate_unofficial_opcodes() {
+    let mut nes = common::synthetic(&[(0x8000, &[0xea, 0xea])]);
+    // opcode, operand, a, x, c in, then a, x and nvzc out
+    let cases = [
+        (0x0b, 0x80, 0xff, 0x00, false, 0x80, 0x00, N | C),      // anc
+        (0x2b, 0x01, 0x03, 0x00, true, 0x01, 0x00, 0),           // anc
+        (0x4b, 0x03, 0xff, 0x00, false, 0x01, 0x00, C),          // alr
+        (0x4b, 0x01, 0x01, 0x00, false, 0x00, 0x00, Z | C),      // alr
+        (0x6b, 0xff, 0xc0, 0x00, true, 0xe0, 0x00, N | C),       // arr
+        (0x6b, 0xff, 0x40, 0x00, false, 0x20, 0x00, V),          // arr
+        (0xcb, 0x01, 0x0f, 0xf3, false, 0x0f, 0x02, C),          // axs
+        (0xcb, 0x05, 0x0f, 0xf3, true, 0x0f, 0xfe, N),           // axs
+        (0xab, 0x5a, 0xff, 0x00, false, 0x5a, 0x5a, 0),          // lax
+        (0x8b, 0xff, 0x00, 0x0f, false, 0x0e, 0x0f, 0),          // xaa
+    ];
+    for (opcode, operand, a, x, carry, result, result_x, flags) in cases {
+        nes.poke(0x8000, opcode);
+        nes.poke(0x8001, operand);
+        let cpu = nes.cpu_mut();
+        cpu.pc = 0x8000;
+        cpu.a = a;
+        cpu.x = x;
+        cpu.sr = 0x24 | if carry { C } else { 0 };
+        let start = nes.cpu().cycles();
+        nes.step();
+
+        let cpu = nes.cpu();
+        assert_eq!((cpu.a, cpu.x, cpu.sr & (N | V | Z | C)), (result, result_x, flags), "opcode {:02x}", opcode);
+        assert_eq!(cpu.cycles() - start, 2);
+        assert_eq!(cpu.pc, 0x8002);
+    }
+}
+
+#[test]
+fn open_bus() {
+    let mut nes = common::synthetic(&[
+        (0x8000, &[
+            0xad, 0x00, 0x40, // lda $4000
+            0xa0, 0x04,       // ldy #$04
+            0xb1, 0x10,       // lda ($10),y
+            0x4c, 0x00, 0x03, // jmp $0300
+        ]),
+    ]);
+    // the write-only apu registers read back the last byte on the bus,
+    // the high byte of the address for absolute addressing
+    nes.poke(0x0010, 0x10);
+    nes.poke(0x0011, 0x40);
+    nes.step();
+    assert_eq!(nes.cpu().a, 0x40);
+    // or the pointer's high byte for indirect addressing, not the operand
+    nes.step();
+    nes.cpu_mut().a = 0x00;
+    nes.step();
+    assert_eq!(nes.cpu().a, 0x40);
+
+    // and the same when running from ram, lda $4014
+    nes.poke(0x0300, 0xad);
+    nes.poke(0x0301, 0x14);
+    nes.poke(0x0302, 0x40);
+    nes.step();
+    nes.step();
+    assert_eq!(nes.cpu().a, 0x40);
+
+    // as does the unmapped space above the apu, lda $5000
+    nes.poke(0x0303, 0xad);
+    nes.poke(0x0304, 0x00);
+    nes.poke(0x0305, 0x50);
+    nes.step();
+    assert_eq!(nes.cpu().a, 0x50);
+}
+
+#[test]
+fn nmi() {
+    let mut nes = common::synthetic(&[
+        (0x8100, &[0x40]), // rti
+        (0xfffa, &[0x00, 0x81]),
+    ]);
+    let cpu = nes.cpu_mut();
+    cpu.pc = 0x8000;
+    cpu.sp = 0xfd;
+    cpu.sr = 0x30 | C;
+    let start = cpu.cycles();
+    cpu.nmi();
+    assert_eq!(nes.cpu().cycles() - start, 7);
+    assert_eq!(nes.cpu().pc, 0x8100);
+    assert_eq!(nes.cpu().sp, 0xfa);
+    // irqs are held off in the handler
+    assert_eq!(nes.cpu().sr & 0x04, 0x04);
+    // b is clear in the pushed sr
+    assert_eq!([nes.peek(0x01fd), nes.peek(0x01fc), nes.peek(0x01fb)], [0x80, 0x00, 0x20 | C]);
+
+    nes.step();
+    assert_eq!(nes.cpu().pc, 0x8000);
+    assert_eq!(nes.cpu().sr & (0x04 | C), C);
+}
+
+#[test]
+fn jam() {
+    let mut nes = common::synthetic(&[
+        (0x8000, &[
+            0xa9, 0x01, // lda #$01
+            0x02,       // jam
+            0xa9, 0x02, // lda #$02
+        ]),
+    ]);
+    nes.step();
+    nes.step();
+    assert!(nes.cpu().jammed());
+    assert_eq!(nes.cpu().pc, 0x8002);
+
+    // nothing runs and interrupts are ignored, but frames still finish
+    let sp = nes.cpu().sp;
+    nes.cpu_mut().nmi();
+    nes.run();
+    assert_eq!(nes.cpu().a, 0x01);
+    assert_eq!(nes.cpu().pc, 0x8002);
+    assert_eq!(nes.cpu().sp, sp);
+
+    nes.reset();
+    assert!(!nes.cpu().jammed());
+    nes.step();
+    assert_eq!(nes.cpu().a, 0x01);
+}
+
+#[test]
+fn unstable_stores() {
+    let mut nes = common::synthetic(&[
+        (0x8000, &[
+            0x9c, 0x00, 0x06, // shy $0600,x
+            0x9e, 0x10, 0x06, // shx $0610,y
+            0x9f, 0x20, 0x06, // ahx $0620,y
+            0x93, 0x10,       // ahx ($10),y
+            0x9b, 0x30, 0x06, // tas $0630,y
+            0xbb, 0x40, 0x06, // las $0640,y
+            0x9c, 0xff, 0x06, // shy $06ff,x
+        ]),
+    ]);
+    let cpu = nes.cpu_mut();
+    cpu.a = 0xfc;
+    cpu.x = 0x05;
+    cpu.y = 0x06;
+    nes.poke(0x10, 0x40);
+    nes.poke(0x11, 0x06);
+
+    // the value is masked with the high byte of the base plus one
+    let start = nes.cpu().cycles();
+    nes.step();
+    assert_eq!(nes.cpu().cycles() - start, 5);
+    assert_eq!(nes.peek(0x0605), 0x06);
+    nes.step();
+    assert_eq!(nes.peek(0x0616), 0x05);
+    nes.step();
+    assert_eq!(nes.peek(0x0626), 0x04);
+    nes.step();
+    assert_eq!(nes.peek(0x0646), 0x04);
+
+    // tas sets sp to a & x
+    nes.step();
+    assert_eq!(nes.cpu().sp, 0x04);
+    assert_eq!(nes.peek(0x0636), 0x04);
+
+    // las loads a, x and sp with the value and sp
+    nes.poke(0x0646, 0x3c);
+    nes.step();
+    assert_eq!((nes.cpu().a, nes.cpu().x, nes.cpu().sp), (0x04, 0x04, 0x04));
+    assert_eq!(nes.cpu().sr & (Z | N), 0);
+
+    // crossing a page replaces the high byte of the address
+    nes.cpu_mut().x = 0x05;
+    nes.step();
+    assert_eq!(nes.peek(0x0604), 0x06);
+    assert_eq!(nes.peek(0x0704), 0x00);
+}
@@ -0,0 +1,100 @@
+use nes::{debug::{Access, AddressSpace, StopReason, Watchpoint}, Nes};
+
+mod common;
+
+use common::NoInput;
+
+fn program() -> Nes<NoInput> {
+    common::synthetic(&[
+        (0x8000, &[
+            0xa2, 0xff,       // ldx #$ff
+            0x9a,             // txs
+            0x20, 0x10, 0x80, // jsr $8010
+            0x8d, 0x00, 0x02, // sta $0200
+            0x4c, 0x03, 0x80, // jmp $8003
+        ]),
+        (0x8010, &[
+            0xa9, 0x42,       // lda #$42
+            0x20, 0x20, 0x80, // jsr $8020
+            0x60,             // rts
+        ]),
+        (0x8020, &[
+            0xe8,             // inx
+            0x60,             // rts
+        ]),
+    ])
+}
+
+#[test]
+fn breakpoint_and_step_out() {
+    let mut nes = program();
+    nes.debugger().add_breakpoint(0x8010);
+    assert_eq!(nes.debug_continue(), StopReason::Breakpoint(0x8010));
+    assert_eq!(nes.cpu().pc, 0x8010);
+
+    // resuming doesn't stop on the same breakpoint again
+    assert_eq!(nes.step_into(), StopReason::Step);
+    assert_eq!(nes.cpu().pc, 0x8012);
+
+    assert_eq!(nes.step_out(), StopReason::Step);
+    assert_eq!(nes.cpu().pc, 0x8006);
+    assert_eq!(nes.cpu().sp, 0xff);
+}
+
+#[test]
+fn step_over() {
+    let mut nes = program();
+    nes.step_into();
+    nes.step_into();
+    assert_eq!(nes.cpu().pc, 0x8003);
+    assert_eq!(nes.step_over(), StopReason::Step);
+    assert_eq!(nes.cpu().pc, 0x8006);
+    assert_eq!(nes.cpu().a, 0x42);
+    assert_eq!(nes.cpu().x, 0x00);
+}
+
+#[test]
+fn watchpoints() {
+    let mut nes = program();
+    nes.debugger().add_watchpoint(Watchpoint::new(AddressSpace::Cpu, 0x0200..=0x02ff).write(true).value(0x42));
+    assert_eq!(nes.debug_continue(), StopReason::Watchpoint {
+        index: 0,
+        space: AddressSpace::Cpu,
+        access: Access::Write,
+        addr: 0x0200,
+        value: 0x42,
+    });
+    assert_eq!(nes.cpu().pc, 0x8009);
+
+    // a value that's never written doesn't stop execution
+    nes.debugger().clear();
+    nes.debugger().add_watchpoint(Watchpoint::new(AddressSpace::Cpu, 0x0200..=0x0200).write(true).value(0x43));
+    assert_eq!(nes.debug_continue(), StopReason::Frame);
+
+    nes.debugger().clear();
+    nes.debugger().add_watchpoint(Watchpoint::new(AddressSpace::Cpu, 0x8020..=0x8020).exec(true));
+    assert!(matches!(nes.debug_continue(), StopReason::Watchpoint { access: Access::Exec, addr: 0x8020, .. }));
+    assert_eq!(nes.cpu().pc, 0x8020);
+
+    // fetching an operand from prg rom is a read
+    nes.debugger().clear();
+    nes.debugger().add_watchpoint(Watchpoint::new(AddressSpace::Cpu, 0x8011..=0x8011).read(true));
+    assert_eq!(nes.debug_continue(), StopReason::Watchpoint {
+        index: 0,
+        space: AddressSpace::Cpu,
+        access: Access::Read,
+        addr: 0x8011,
+        value: 0x42,
+    });
+    assert_eq!(nes.cpu().pc, 0x8012);
+}
+
+#[test]
+fn run_to_scanline() {
+    let mut nes = program();
+    assert_eq!(nes.run_to_scanline(100), StopReason::Scanline(100));
+    assert_eq!(nes.ppu().scanline(), 100);
+
+    // nmis are disabled so this gives up
+    assert_eq!(nes.run_to_nmi(), StopReason::Frame);
+}
@@ -0,0 +1,154 @@
+use nes::{disasm::{self, Effective, Mnemonic, Mode, OPCODES}, mem::Mem};
+
+// 64 kb of plain ram
+struct Flat(Vec<u8>);
+
+impl Flat {
+    fn new(code: &[(u16, &[u8])]) -> Self {
+        let mut mem = Flat(vec![0; 0x10000]);
+        for &(addr, bytes) in code {
+            for (i, &b) in bytes.iter().enumerate() {
+                mem.0[addr as usize + i] = b;
+            }
+        }
+        mem
+    }
+}
+
+impl Mem for Flat {
+    fn read(&mut self, addr: u16) -> u8 { self.0[addr as usize] }
+    fn write(&mut self, addr: u16, value: u8) { self.0[addr as usize] = value }
+    fn peek(&self, addr: u16) -> u8 { self.0[addr as usize] }
+    fn poke(&mut self, addr: u16, value: u8) { self.0[addr as usize] = value }
+}
+
+#[test]
+fn every_mode() {
+    let cases: [(&[u8], Mnemonic, Mode, &str); 13] = [
+        (&[0xe8], Mnemonic::Inx, Mode::Implied, "INX"),
+        (&[0x0a], Mnemonic::Asl, Mode::Accumulator, "ASL A"),
+        (&[0xa9, 0x42], Mnemonic::Lda, Mode::Immediate, "LDA #$42"),
+        (&[0xa5, 0x10], Mnemonic::Lda, Mode::Zeropage, "LDA $10"),
+        (&[0xb5, 0x10], Mnemonic::Lda, Mode::ZeropageX, "LDA $10,X"),
+        (&[0xb6, 0x10], Mnemonic::Ldx, Mode::ZeropageY, "LDX $10,Y"),
+        (&[0xad, 0x34, 0x12], Mnemonic::Lda, Mode::Absolute, "LDA $1234"),
+        (&[0xbd, 0x34, 0x12], Mnemonic::Lda, Mode::AbsoluteX, "LDA $1234,X"),
+        (&[0xb9, 0x34, 0x12], Mnemonic::Lda, Mode::AbsoluteY, "LDA $1234,Y"),
+        (&[0x6c, 0x34, 0x12], Mnemonic::Jmp, Mode::Indirect, "JMP ($1234)"),
+        (&[0xa1, 0x10], Mnemonic::Lda, Mode::IndirectX, "LDA ($10,X)"),
+        (&[0xb1, 0x10], Mnemonic::Lda, Mode::IndirectY, "LDA ($10),Y"),
+        (&[0xd0, 0x02], Mnemonic::Bne, Mode::Relative, "BNE $8004"),
+    ];
+    for (bytes, mnemonic, mode, text) in cases {
+        let mem = Flat::new(&[(0x8000, bytes)]);
+        let instr = disasm::disassemble(&mem, 0x8000);
+        assert_eq!((instr.mnemonic, instr.mode), (mnemonic, mode), "{}", text);
+        assert_eq!(instr.to_string(), text);
+        assert_eq!(instr.bytes(), bytes, "{}", text);
+        assert_eq!(instr.size() as usize, bytes.len(), "{}", text);
+        assert_eq!(instr.next(), 0x8000 + bytes.len() as u16, "{}", text);
+        assert_eq!(instr.opcode, bytes[0]);
+        assert!(instr.official);
+    }
+
+    // unofficial opcodes are marked as such
+    let instr = disasm::disassemble(&Flat::new(&[(0x8000, &[0xa7, 0x10])]), 0x8000);
+    assert_eq!((instr.to_string().as_str(), instr.official), ("LAX $10", false));
+    assert_eq!(OPCODES.iter().filter(|op| op.official).count(), 151);
+
+    // the operand wraps around the top of memory
+    let instr = disasm::disassemble(&Flat::new(&[(0xfffe, &[0xad, 0x34]), (0x0000, &[0x12])]), 0xfffe);
+    assert_eq!(instr.to_string(), "LDA $1234");
+    assert_eq!(instr.next(), 0x0001);
+}
+
+#[test]
+fn effective() {
+    let mem = Flat::new(&[
+        (0x0000, &[0x00, 0x03]),
+        (0x0010, &[0x00, 0x02]),
+        (0x00ff, &[0x40]),
+        (0x0204, &[0xaa]),
+        (0x0340, &[0xbb]),
+        (0x1234, &[0xcc]),
+        (0x1236, &[0xdd]),
+    ]);
+    let eff = |bytes: &[u8], x, y| {
+        let mut code = Flat::new(&[(0x8000, bytes)]);
+        code.0[..0x8000].copy_from_slice(&mem.0[..0x8000]);
+        disasm::disassemble(&code, 0x8000).effective(&code, x, y)
+    };
+    let at = |addr, value| Some(Effective { pointer: None, addr, value });
+
+    // no memory operand
+    assert_eq!(eff(&[0xe8], 0, 0), None);
+    assert_eq!(eff(&[0x0a], 0, 0), None);
+    assert_eq!(eff(&[0xa9, 0x42], 0, 0), None);
+    assert_eq!(eff(&[0xd0, 0x02], 0, 0), None);
+    assert_eq!(eff(&[0x4c, 0x34, 0x12], 0, 0), None);
+    assert_eq!(eff(&[0x20, 0x34, 0x12], 0, 0), None);
+
+    assert_eq!(eff(&[0xa5, 0xff], 0, 0), at(0x00ff, 0x40));
+    assert_eq!(eff(&[0xad, 0x34, 0x12], 0, 0), at(0x1234, 0xcc));
+    assert_eq!(eff(&[0xbd, 0x34, 0x12], 2, 0), at(0x1236, 0xdd));
+    assert_eq!(eff(&[0xb9, 0x34, 0x12], 0, 2), at(0x1236, 0xdd));
+    // zeropage indexing wraps within the zeropage
+    assert_eq!(eff(&[0xb5, 0xf0], 0x20, 0), at(0x0010, 0x00));
+    assert_eq!(eff(&[0xb6, 0xf0], 0, 0x0f), at(0x00ff, 0x40));
+    // but absolute indexing carries into the next page
+    assert_eq!(eff(&[0xbd, 0xf0, 0x01], 0x14, 0), at(0x0204, 0xaa));
+
+    // (zp,x) reads its pointer from the zeropage
+    assert_eq!(eff(&[0xa1, 0x0e], 2, 0), Some(Effective { pointer: Some(0x0010), addr: 0x0200, value: 0x00 }));
+    // and wraps at $ff, the high byte comes from $00
+    assert_eq!(eff(&[0xa1, 0xfe], 1, 0), Some(Effective { pointer: Some(0x00ff), addr: 0x0040, value: 0x00 }));
+    assert_eq!(eff(&[0xa1, 0x00], 0xff, 0), Some(Effective { pointer: Some(0x00ff), addr: 0x0040, value: 0x00 }));
+    // (zp),y adds y to the pointer
+    assert_eq!(eff(&[0xb1, 0x10], 0, 4), Some(Effective { pointer: Some(0x0200), addr: 0x0204, value: 0xaa }));
+    // which also wraps at $ff
+    assert_eq!(eff(&[0xb1, 0xff], 0, 0), Some(Effective { pointer: Some(0x0040), addr: 0x0040, value: 0x00 }));
+    assert_eq!(eff(&[0xb1, 0xff], 0, 0xff), Some(Effective { pointer: Some(0x0040), addr: 0x013f, value: 0x00 }));
+}
+
+#[test]
+fn jmp_indirect_page_wrap() {
+    let mem = Flat::new(&[
+        (0x8000, &[0x6c, 0xff, 0x02]), // jmp ($02ff)
+        (0x8003, &[0x6c, 0x00, 0x03]), // jmp ($0300)
+        (0x02ff, &[0x34]),
+        (0x0200, &[0x12]),
+        (0x0300, &[0x56, 0x78]),
+    ]);
+    // the high byte comes from $0200 rather than $0300
+    let eff = disasm::disassemble(&mem, 0x8000).effective(&mem, 0, 0).unwrap();
+    assert_eq!(eff.addr, 0x1234);
+    let eff = disasm::disassemble(&mem, 0x8003).effective(&mem, 0, 0).unwrap();
+    assert_eq!(eff.addr, 0x7856);
+}
+
+#[test]
+fn branch_targets() {
+    let mem = Flat::new(&[
+        (0x8000, &[0xd0, 0x10]), // bne, forward
+        (0x8002, &[0xf0, 0xfc]), // beq, backward
+        (0x8004, &[0x90, 0x00]), // bcc, to the next instruction
+        (0x80f0, &[0xb0, 0x7f]), // bcs, as far forward as it goes
+        (0x8100, &[0x30, 0x80]), // bmi, as far back as it goes
+        (0x8006, &[0x4c, 0x34, 0x12]),
+        (0x8009, &[0x20, 0x78, 0x56]),
+        (0x800c, &[0x6c, 0x34, 0x12]),
+        (0xfffe, &[0x10, 0x01]), // bpl, wraps around
+    ]);
+    let target = |addr| disasm::disassemble(&mem, addr).target();
+    assert_eq!(target(0x8000), Some(0x8012));
+    assert_eq!(target(0x8002), Some(0x8000));
+    assert_eq!(target(0x8004), Some(0x8006));
+    assert_eq!(target(0x80f0), Some(0x8171));
+    assert_eq!(target(0x8100), Some(0x8082));
+    assert_eq!(target(0xfffe), Some(0x0001));
+    assert_eq!(disasm::disassemble(&mem, 0x8002).to_string(), "BEQ $8000");
+    // jmp and jsr, but not an indirect jmp
+    assert_eq!(target(0x8006), Some(0x1234));
+    assert_eq!(target(0x8009), Some(0x5678));
+    assert_eq!(target(0x800c), None);
+}
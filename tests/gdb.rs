@@ -0,0 +1,202 @@
+use std::{io::{ErrorKind, Read, Write}, net::TcpStream, thread, time::Duration};
+
+use nes::{gdb::GdbStub, Nes};
+
+mod common;
+
+use common::NoInput;
+
+// a gdb connected to a stub on a free port, the stub is serviced
+// from the same thread while waiting for replies
+struct Session {
+    gdb: GdbStub,
+    nes: Nes<NoInput>,
+    conn: TcpStream,
+    buf: Vec<u8>,
+}
+
+impl Session {
+    fn new() -> Self {
+        let nes = common::synthetic(&[
+            (0x8000, &[
+                0xa2, 0xff,       // ldx #$ff
+                0x9a,             // txs
+                0xa9, 0x42,       // lda #$42
+                0x8d, 0x00, 0x02, // sta $0200
+                0xe8,             // inx
+                0x4c, 0x08, 0x80, // jmp $8008
+            ]),
+        ]);
+        let gdb = GdbStub::bind(0).unwrap();
+        let conn = TcpStream::connect(gdb.local_addr().unwrap()).unwrap();
+        conn.set_nonblocking(true).unwrap();
+        let mut session = Self { gdb, nes, conn, buf: Vec::new() };
+        while !session.gdb.connected() {
+            session.gdb.update(&mut session.nes);
+        }
+        session
+    }
+
+    fn send(&mut self, data: &[u8]) {
+        self.conn.write_all(data).unwrap();
+    }
+
+    // services the stub until the reply is complete, or a number of times
+    fn poll(&mut self, times: usize, done: fn(&[u8]) -> bool) -> Vec<u8> {
+        let mut chunk = [0; 0x400];
+        for _ in 0..times {
+            self.gdb.update(&mut self.nes);
+            match self.conn.read(&mut chunk) {
+                Ok(n) => self.buf.extend_from_slice(&chunk[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => thread::sleep(Duration::from_millis(1)),
+                Err(e) => panic!("{}", e),
+            }
+            if done(&self.buf) {
+                break;
+            }
+        }
+        self.buf.split_off(0)
+    }
+
+    // sends a packet and returns the data of the reply, after checking
+    // it was acked and the reply's checksum
+    fn command(&mut self, data: &str) -> String {
+        self.send(packet(data).as_bytes());
+        let reply = self.poll(1000, |buf| buf.len() >= 4 && buf[buf.len() - 3] == b'#');
+        let reply = String::from_utf8(reply).unwrap();
+        let Some(reply) = reply.strip_prefix('+') else {
+            panic!("{}: not acked, got {:?}", data, reply);
+        };
+        let (reply, checksum) = reply.strip_prefix('$')
+            .and_then(|reply| reply.split_once('#'))
+            .unwrap_or_else(|| panic!("{}: bad framing {:?}", data, reply));
+        assert_eq!(checksum, format!("{:02x}", sum(reply.as_bytes())), "{}: bad checksum", data);
+        reply.to_string()
+    }
+}
+
+fn sum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b))
+}
+
+fn packet(data: &str) -> String {
+    format!("${}#{:02x}", data, sum(data.as_bytes()))
+}
+
+#[test]
+fn framing() {
+    let mut session = Session::new();
+    assert_eq!(packet("g"), "$g#67");
+    assert_eq!(session.command("?"), "S05");
+
+    // a bad checksum is nakked and not answered, which the next
+    // command checks by expecting its ack first
+    session.send(b"$g#00");
+    assert_eq!(session.poll(1000, |buf| !buf.is_empty()), b"-");
+    // the checksum has to be hex
+    session.send(b"$g#zz");
+    assert_eq!(session.poll(1000, |buf| !buf.is_empty()), b"-");
+
+    // acks and naks from gdb are skipped over
+    session.send(b"+-");
+    assert_eq!(session.command("?"), "S05");
+
+    // a packet can arrive in pieces
+    session.send(b"$?");
+    assert_eq!(session.poll(10, |_| false), b"");
+    session.send(b"#3");
+    assert_eq!(session.poll(10, |_| false), b"");
+    session.send(b"f");
+    assert_eq!(session.poll(1000, |buf| buf.len() >= 8), b"+$S05#b8");
+
+    // an empty packet and an unknown command get an empty reply
+    assert_eq!(session.command(""), "");
+    assert_eq!(session.command("vMustReplyEmpty"), "");
+
+    // a command that isn't ascii is an error
+    assert_eq!(session.command("\u{e9}"), "E01");
+}
+
+#[test]
+fn registers() {
+    let mut session = Session::new();
+    assert_eq!(session.command("g"), "00000024fd0080");
+
+    assert_eq!(session.command("G01020304050880"), "OK");
+    assert_eq!(session.command("g"), "01020304050880");
+    let cpu = session.nes.cpu();
+    assert_eq!((cpu.a, cpu.x, cpu.y, cpu.sr, cpu.sp, cpu.pc), (0x01, 0x02, 0x03, 0x04, 0x05, 0x8008));
+    assert_eq!(session.command("G0102"), "E01");
+    assert_eq!(session.command("G0102030405088"), "E01");
+
+    assert_eq!(session.command("p0"), "01");
+    assert_eq!(session.command("p4"), "05");
+    assert_eq!(session.command("p5"), "0880");
+    assert_eq!(session.command("p6"), "E01");
+    assert_eq!(session.command("px"), "E01");
+
+    assert_eq!(session.command("P0=aa"), "OK");
+    assert_eq!(session.command("P5=0080"), "OK");
+    assert_eq!(session.command("p0"), "aa");
+    assert_eq!(session.nes.cpu().pc, 0x8000);
+    assert_eq!(session.command("P0=aabb"), "E01");
+    assert_eq!(session.command("P5=00"), "E01");
+    assert_eq!(session.command("P6=00"), "E01");
+    assert_eq!(session.command("P0"), "E01");
+}
+
+#[test]
+fn memory() {
+    let mut session = Session::new();
+    assert_eq!(session.command("m8000,3"), "a2ff9a");
+    assert_eq!(session.command("m8000,0"), "");
+    assert_eq!(session.command("m8000"), "E01");
+
+    assert_eq!(session.command("M0300,2:abcd"), "OK");
+    assert_eq!(session.command("m300,2"), "abcd");
+    assert_eq!(session.nes.peek(0x0301), 0xcd);
+    // the length has to match the data
+    assert_eq!(session.command("M0300,2:ab"), "E01");
+    assert_eq!(session.command("M0300,1:abc"), "E01");
+    assert_eq!(session.command("M0300,2"), "E01");
+    assert_eq!(session.command("m300,2"), "abcd");
+}
+
+#[test]
+fn breakpoints_and_stepping() {
+    let mut session = Session::new();
+    assert_eq!(session.command("Z0,8008,1"), "OK");
+    assert_eq!(session.command("c"), "S05");
+    assert_eq!(session.nes.cpu().pc, 0x8008);
+    assert_eq!(session.nes.cpu().a, 0x42);
+
+    assert_eq!(session.command("s"), "S05");
+    assert_eq!(session.nes.cpu().pc, 0x8009);
+    assert_eq!(session.nes.cpu().x, 0x00);
+    // stops again after going around the loop
+    assert_eq!(session.command("c"), "S05");
+    assert_eq!(session.nes.cpu().pc, 0x8008);
+    assert_eq!(session.nes.cpu().x, 0x00);
+
+    // and resuming at an address
+    assert_eq!(session.command("s8003"), "S05");
+    assert_eq!(session.nes.cpu().pc, 0x8005);
+
+    // a write watchpoint reports the address
+    assert_eq!(session.command("z0,8008,1"), "OK");
+    assert_eq!(session.command("Z2,200,1"), "OK");
+    assert_eq!(session.command("c"), "T05watch:0200;");
+    assert_eq!(session.nes.cpu().pc, 0x8008);
+    assert_eq!(session.command("z2,200,1"), "OK");
+
+    // unsupported types and missing arguments
+    assert_eq!(session.command("Z9,200,1"), "");
+    assert_eq!(session.command("Z0,8008"), "E01");
+
+    // with nothing to stop on, there's no reply until ctrl-c
+    session.send(packet("c8000").as_bytes());
+    assert_eq!(session.poll(1000, |buf| !buf.is_empty()), b"+");
+    session.send(&[0x03]);
+    assert_eq!(session.poll(1000, |buf| buf.len() >= 7), b"$S02#b5");
+    assert_eq!(session.command("g").len(), 14);
+}
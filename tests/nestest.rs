@@ -0,0 +1,31 @@
+use std::fs;
+
+use nes::trace::Tracer;
+
+mod common;
+
+use common::Log;
+
+#[test]
+#[ignore = "needs the test roms in tests/roms"]
+fn nestest() {
+    let mut nes = common::load("nestest/nestest.nes");
+    let path = common::rom_path("nestest/nestest.log");
+    let golden = fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+
+    // automation mode starts at $c000 instead of the reset vector
+    nes.cpu_mut().pc = 0xc000;
+    let log = Log::default();
+    nes.set_tracer(Some(Tracer::new(Box::new(log.clone()))));
+
+    for (i, expected) in golden.lines().enumerate() {
+        nes.step();
+        let line = log.take();
+        assert_eq!(line.trim_end(), expected.trim_end(), "nestest.log mismatch on line {}", i + 1);
+    }
+
+    // error codes for official and unofficial opcodes
+    assert_eq!(nes.peek(0x0002), 0x00, "official opcode test failed");
+    assert_eq!(nes.peek(0x0003), 0x00, "unofficial opcode test failed");
+}
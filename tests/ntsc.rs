@@ -0,0 +1,73 @@
+use nes::{ntsc::{Filter, Preset, HEIGHT, WIDTH}, palette::{Ntsc, Palette}};
+
+fn filter(preset: Preset, pixels: &[u16]) -> Vec<u32> {
+    let mut out = vec![0; WIDTH * HEIGHT];
+    Filter::new(preset, Ntsc::default()).apply(pixels, &mut out);
+    out
+}
+
+fn close(a: u32, b: u32) -> bool {
+    (0..3).all(|i| ((a >> (i * 8)) as u8).abs_diff((b >> (i * 8)) as u8) <= 2)
+}
+
+#[test]
+fn flat_colours() {
+    let palette = Palette::ntsc(&Ntsc::default());
+    for index in [0x00, 0x0f, 0x16, 0x2a, 0x30, 0x121] {
+        let pixels = vec![index; 256 * 240];
+        for preset in [Preset::SVideo, Preset::Rgb] {
+            let out = filter(preset, &pixels);
+            let colour = out[100 * WIDTH + 300];
+            assert!(close(colour, palette.colours()[index as usize]), "{:?} {:03x}: {:08x}", preset, index, colour);
+        }
+    }
+    // greys have no chroma to get into the luma
+    for index in [0x00, 0x10, 0x20, 0x3d] {
+        let out = filter(Preset::Composite, &vec![index; 256 * 240]);
+        assert!(close(out[100 * WIDTH + 300], palette.colours()[index as usize]));
+    }
+}
+
+#[test]
+fn monochrome() {
+    let out = filter(Preset::Monochrome, &vec![0x16; 256 * 240]);
+    for &colour in &out[100 * WIDTH..101 * WIDTH] {
+        let (r, g, b) = ((colour >> 16) as u8, (colour >> 8) as u8, colour as u8);
+        assert!(r == g && g == b);
+    }
+}
+
+#[test]
+fn dot_crawl() {
+    // a saturated colour leaves some of the subcarrier in the luma,
+    // which moves between frames
+    let pixels = vec![0x16; 256 * 240];
+    let mut filter = Filter::new(Preset::Composite, Ntsc::default());
+    let mut first = vec![0; WIDTH * HEIGHT];
+    let mut second = vec![0; WIDTH * HEIGHT];
+    filter.apply(&pixels, &mut first);
+    filter.apply(&pixels, &mut second);
+    assert_ne!(first, second);
+    // and from line to line
+    assert_ne!(first[100 * WIDTH..101 * WIDTH], first[101 * WIDTH..102 * WIDTH]);
+
+    // s-video keeps them apart, away from the edges of the picture
+    let mut filter = Filter::new(Preset::SVideo, Ntsc::default());
+    filter.apply(&pixels, &mut first);
+    filter.apply(&pixels, &mut second);
+    assert_eq!(first[100 * WIDTH + 50..100 * WIDTH + 550], second[100 * WIDTH + 50..100 * WIDTH + 550]);
+}
+
+#[test]
+fn colour_bleed() {
+    // red on the left half, white on the right
+    let pixels: Vec<u16> = (0..256 * 240).map(|i| if (i & 0xff) < 128 { 0x16 } else { 0x30 }).collect();
+    let edge = WIDTH / 2;
+    let rgb = filter(Preset::Rgb, &pixels);
+    let svideo = filter(Preset::SVideo, &pixels);
+    // just into the white, rgb is clean but the red has bled
+    // into it with s-video
+    let white = Palette::ntsc(&Ntsc::default()).colours()[0x30];
+    assert!(close(rgb[100 * WIDTH + edge + 2], white));
+    assert!(!close(svideo[100 * WIDTH + edge + 2], white));
+}
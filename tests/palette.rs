@@ -0,0 +1,60 @@
+mod common;
+
+use nes::palette::{Ntsc, Palette};
+
+fn rgb(colour: u32) -> (u8, u8, u8) {
+    ((colour >> 16) as u8, (colour >> 8) as u8, colour as u8)
+}
+
+#[test]
+fn load_pal_files() {
+    // 64 colours get the emphasis worked out
+    let mut data = vec![0; 0xc0];
+    data[0x30 * 3..0x30 * 3 + 3].copy_from_slice(&[0xff, 0xff, 0xff]);
+    let palette = Palette::load(&data).unwrap();
+    assert_eq!(palette.colours()[0x030], 0xffffffff);
+    // red emphasis darkens green and blue
+    assert_eq!(palette.colours()[0x070], 0xffffbebe);
+
+    // 512 are used as they are
+    let mut data = vec![0; 0x600];
+    data[0x70 * 3..0x70 * 3 + 3].copy_from_slice(&[0x12, 0x34, 0x56]);
+    let palette = Palette::load(&data).unwrap();
+    assert_eq!(palette.colours()[0x070], 0xff123456);
+
+    assert!(Palette::load(&[0; 0x100]).is_err());
+}
+
+#[test]
+fn ntsc_palette() {
+    let palette = Palette::ntsc(&Ntsc::default());
+    let colours = palette.colours();
+    assert_eq!(colours[0x0f], 0xff000000);
+    let (r, g, b) = rgb(colours[0x30]);
+    assert!(r > 0xf0 && g > 0xf0 && b > 0xf0);
+    let (r, g, b) = rgb(colours[0x16]);
+    assert!(r > g && r > b);
+    let (r, g, b) = rgb(colours[0x12]);
+    assert!(b > r && b > g);
+    // all three emphasis bits darken everything
+    let (r, g, b) = rgb(colours[0x20]);
+    let (er, eg, eb) = rgb(colours[0x1e0]);
+    assert!(er < r && eg < g && eb < b);
+
+    // more saturation moves the channels apart
+    let spread = |(r, g, b): (u8, u8, u8)| r.max(g).max(b) - r.min(g).min(b);
+    let saturated = Palette::ntsc(&Ntsc::default().saturation(1.5));
+    assert!(spread(rgb(saturated.colours()[0x16])) > spread(rgb(colours[0x16])));
+}
+
+#[test]
+fn set_palette() {
+    let mut nes = common::synthetic(&[(0x8000, &[0x4c, 0x00, 0x80])]);
+    let mut data = vec![0; 0xc0];
+    data[..3].copy_from_slice(&[0x12, 0x34, 0x56]);
+    nes.set_palette(Palette::load(&data).unwrap());
+    nes.run();
+    nes.run();
+    // rendering is off so the whole screen is the backdrop, colour $00
+    assert_eq!(&nes.framebuffer()[..4], &0xff123456u32.to_ne_bytes());
+}
@@ -0,0 +1,772 @@
+use nes::{palette::Palette, Nes};
+
+mod common;
+
+use common::NoInput;
+
+const BLACK: u32 = 0xff000000;
+const WHITE: u32 = 0xfffffeff;
+const RED: u32 = 0xffb53120;
+
+fn pixel(nes: &mut Nes<NoInput>, x: usize, y: usize) -> u32 {
+    let i = ((y << 8) | x) << 2;
+    let fb = nes.framebuffer();
+    u32::from_ne_bytes([fb[i], fb[i + 1], fb[i + 2], fb[i + 3]])
+}
+
+// a solid tile at column 2, row 3 of the first nametable, drawn in
+// white on black
+fn program() -> Nes<NoInput> {
+    common::synthetic(&[
+        (0x8000, &[
+            0xa9, 0x00, 0x8d, 0x06, 0x20, // lda #$00, sta $2006
+            0xa9, 0x10, 0x8d, 0x06, 0x20, // lda #$10, sta $2006
+            0xa9, 0xff,                   // lda #$ff
+            0xa2, 0x08,                   // ldx #$08
+            0x8d, 0x07, 0x20,             // sta $2007
+            0xca,                         // dex
+            0xd0, 0xfa,                   // bne $800e
+            0xa9, 0x20, 0x8d, 0x06, 0x20, // lda #$20, sta $2006
+            0xa9, 0x62, 0x8d, 0x06, 0x20, // lda #$62, sta $2006
+            0xa9, 0x01, 0x8d, 0x07, 0x20, // lda #$01, sta $2007
+            0xa9, 0x3f, 0x8d, 0x06, 0x20, // lda #$3f, sta $2006
+            0xa9, 0x00, 0x8d, 0x06, 0x20, // lda #$00, sta $2006
+            0xa9, 0x0f, 0x8d, 0x07, 0x20, // lda #$0f, sta $2007
+            0xa9, 0x30, 0x8d, 0x07, 0x20, // lda #$30, sta $2007
+            0xa9, 0x00, 0x8d, 0x00, 0x20, // lda #$00, sta $2000
+            0x8d, 0x05, 0x20,             // sta $2005
+            0x8d, 0x05, 0x20,             // sta $2005
+            0xa9, 0x08, 0x8d, 0x01, 0x20, // lda #$08, sta $2001
+            0x4c, 0x47, 0x80,             // jmp $8047
+        ]),
+        // turns rendering off and sets the backdrop to red
+        (0x8100, &[
+            0xa9, 0x00, 0x8d, 0x01, 0x20, // lda #$00, sta $2001
+            0xa9, 0x3f, 0x8d, 0x06, 0x20, // lda #$3f, sta $2006
+            0xa9, 0x00, 0x8d, 0x06, 0x20, // lda #$00, sta $2006
+            0xa9, 0x16, 0x8d, 0x07, 0x20, // lda #$16, sta $2007
+            0x4c, 0x14, 0x81,             // jmp $8114
+        ]),
+    ])
+}
+
+#[test]
+fn background() {
+    let mut nes = program();
+    nes.run();
+    nes.run();
+    for y in 0..240 {
+        for x in 0..256 {
+            let expected = if (16..24).contains(&x) && (24..32).contains(&y) { WHITE } else { BLACK };
+            assert_eq!(pixel(&mut nes, x, y), expected, "({}, {})", x, y);
+        }
+    }
+}
+
+#[test]
+fn mid_frame_palette_change() {
+    let mut nes = program();
+    nes.run();
+    nes.run();
+    nes.run_to_scanline(120);
+    nes.cpu_mut().pc = 0x8100;
+    nes.run();
+
+    assert_eq!(pixel(&mut nes, 0, 0), BLACK);
+    assert_eq!(pixel(&mut nes, 0, 119), BLACK);
+    assert_eq!(pixel(&mut nes, 20, 28), WHITE);
+    assert_eq!(pixel(&mut nes, 0, 122), RED);
+    assert_eq!(pixel(&mut nes, 255, 239), RED);
+}
+
+// a white bar down column 2, with routines at $8100 and $8200 that
+// scroll it 8 pixels left through $2005 and $2006
+fn bar() -> Nes<NoInput> {
+    common::synthetic(&[
+        (0x8000, &[
+            0xa9, 0x00, 0x8d, 0x06, 0x20, // lda #$00, sta $2006
+            0xa9, 0x10, 0x8d, 0x06, 0x20, // lda #$10, sta $2006
+            0xa9, 0xff,                   // lda #$ff
+            0xa2, 0x08,                   // ldx #$08
+            0x8d, 0x07, 0x20,             // sta $2007
+            0xca,                         // dex
+            0xd0, 0xfa,                   // bne $800e
+            0xa9, 0x04, 0x8d, 0x00, 0x20, // lda #$04, sta $2000
+            0xa9, 0x20, 0x8d, 0x06, 0x20, // lda #$20, sta $2006
+            0xa9, 0x02, 0x8d, 0x06, 0x20, // lda #$02, sta $2006
+            0xa9, 0x01,                   // lda #$01
+            0xa2, 0x1e,                   // ldx #$1e
+            0x8d, 0x07, 0x20,             // sta $2007
+            0xca,                         // dex
+            0xd0, 0xfa,                   // bne $8027
+            0xa9, 0x00, 0x8d, 0x00, 0x20, // lda #$00, sta $2000
+            0xa9, 0x3f, 0x8d, 0x06, 0x20, // lda #$3f, sta $2006
+            0xa9, 0x00, 0x8d, 0x06, 0x20, // lda #$00, sta $2006
+            0xa9, 0x0f, 0x8d, 0x07, 0x20, // lda #$0f, sta $2007
+            0xa9, 0x30, 0x8d, 0x07, 0x20, // lda #$30, sta $2007
+            0xa9, 0x00, 0x8d, 0x00, 0x20, // lda #$00, sta $2000
+            0x8d, 0x05, 0x20,             // sta $2005
+            0x8d, 0x05, 0x20,             // sta $2005
+            0xa9, 0x08, 0x8d, 0x01, 0x20, // lda #$08, sta $2001
+            0x4c, 0x56, 0x80,             // jmp $8056
+        ]),
+        (0x8100, &[
+            0xa9, 0x08, 0x8d, 0x05, 0x20, // lda #$08, sta $2005
+            0xa9, 0x00, 0x8d, 0x05, 0x20, // lda #$00, sta $2005
+            0x4c, 0x0a, 0x81,             // jmp $810a
+        ]),
+        (0x8200, &[
+            0xa9, 0x20, 0x8d, 0x06, 0x20, // lda #$20, sta $2006
+            0xa9, 0x01, 0x8d, 0x06, 0x20, // lda #$01, sta $2006
+            0x4c, 0x0a, 0x82,             // jmp $820a
+        ]),
+    ])
+}
+
+fn split_at(split: u16) -> Nes<NoInput> {
+    let mut nes = bar();
+    nes.run();
+    nes.run();
+    nes.run_to_scanline(120);
+    nes.cpu_mut().pc = split;
+    nes.run();
+    nes
+}
+
+fn check_split(nes: &mut Nes<NoInput>) {
+    for y in (0..120).chain(122..240) {
+        let left = if y < 120 { 16 } else { 8 };
+        for x in 0..256 {
+            let expected = if (left..left + 8).contains(&x) { WHITE } else { BLACK };
+            assert_eq!(pixel(nes, x, y), expected, "({}, {})", x, y);
+        }
+    }
+}
+
+#[test]
+fn split_scroll() {
+    // the new horizontal scroll is used from the next line
+    check_split(&mut split_at(0x8100));
+}
+
+#[test]
+fn split_scroll_through_ppuaddr() {
+    // $2006 writes go straight to the scroll position
+    check_split(&mut split_at(0x8200));
+}
+
+// solid white sprites from oam at $0200, $2002 is read into $00 in a loop
+fn sprites(oam: &[[u8; 4]]) -> Nes<NoInput> {
+    let mut nes = common::synthetic(&[
+        (0x8000, &[
+            0xa9, 0x00, 0x8d, 0x06, 0x20, // lda #$00, sta $2006
+            0xa9, 0x10, 0x8d, 0x06, 0x20, // lda #$10, sta $2006
+            0xa9, 0xff,                   // lda #$ff
+            0xa2, 0x08,                   // ldx #$08
+            0x8d, 0x07, 0x20,             // sta $2007
+            0xca,                         // dex
+            0xd0, 0xfa,                   // bne $800e
+            0xa9, 0x3f, 0x8d, 0x06, 0x20, // lda #$3f, sta $2006
+            0xa9, 0x11, 0x8d, 0x06, 0x20, // lda #$11, sta $2006
+            0xa9, 0x30, 0x8d, 0x07, 0x20, // lda #$30, sta $2007
+            0xa9, 0x3f, 0x8d, 0x06, 0x20, // lda #$3f, sta $2006
+            0xa9, 0x00, 0x8d, 0x06, 0x20, // lda #$00, sta $2006
+            0xa9, 0x0f, 0x8d, 0x07, 0x20, // lda #$0f, sta $2007
+            0xa9, 0x00, 0x8d, 0x00, 0x20, // lda #$00, sta $2000
+            0xa9, 0x02, 0x8d, 0x14, 0x40, // lda #$02, sta $4014
+            0xa9, 0x14, 0x8d, 0x01, 0x20, // lda #$14, sta $2001
+            0xad, 0x02, 0x20,             // lda $2002
+            0x85, 0x00,                   // sta $00
+            0x4c, 0x41, 0x80,             // jmp $8041
+        ]),
+    ]);
+    for i in 0..0x100 {
+        nes.poke(0x0200 + i as u16, 0xff);
+    }
+    for (i, sprite) in oam.iter().enumerate() {
+        for (j, &value) in sprite.iter().enumerate() {
+            nes.poke(0x0200 + (i * 4 + j) as u16, value);
+        }
+    }
+    nes.run();
+    nes
+}
+
+// nine sprites in a row on lines 50-57
+fn nine() -> Vec<[u8; 4]> {
+    (0..9).map(|i| [49, 1, 0, i * 16]).collect()
+}
+
+fn overflow(nes: &mut Nes<NoInput>) -> bool {
+    nes.run_to_scanline(100);
+    (nes.peek(0x0000) & 0x20) != 0
+}
+
+#[test]
+fn sprite_limit() {
+    let mut nes = sprites(&nine());
+    nes.run();
+    for i in 0..9 {
+        let expected = if i < 8 { WHITE } else { BLACK };
+        assert_eq!(pixel(&mut nes, i * 16 + 4, 53), expected, "sprite {}", i);
+    }
+    assert_eq!(pixel(&mut nes, 4, 49), BLACK);
+    assert_eq!(pixel(&mut nes, 4, 50), WHITE);
+    assert_eq!(pixel(&mut nes, 4, 57), WHITE);
+    assert_eq!(pixel(&mut nes, 4, 58), BLACK);
+    assert!(overflow(&mut nes));
+
+    // cleared at the end of vblank
+    nes.run_to_scanline(20);
+    assert_eq!(nes.peek(0x0000) & 0x20, 0);
+}
+
+#[test]
+fn no_sprite_limit() {
+    let mut nes = sprites(&nine());
+    nes.set_sprite_limit(false);
+    nes.run();
+    assert_eq!(pixel(&mut nes, 8 * 16 + 4, 53), WHITE);
+    assert!(overflow(&mut nes));
+}
+
+#[test]
+fn sprite_overflow_bug() {
+    // eight sprites in range don't overflow
+    let mut oam = nine();
+    oam[8][0] = 0xff;
+    assert!(!overflow(&mut sprites(&oam)));
+
+    // but after eight, the ppu looks at the 10th sprite's tile
+    // number as its y position
+    oam.push([0xff, 49, 0, 0]);
+    assert!(overflow(&mut sprites(&oam)));
+}
+
+// a scene built from data: tiles are (pattern address, 16 bytes) in the
+// first 16 tiles of either table, the nametable is filled from $0300-$06ff
+// and oam from $0200, ppuctrl and ppumask come from $f0/$f1, and $2002 is
+// read into $00 in a loop, the background is white and sprites are red
+fn scene(ctrl: u8, mask: u8, tiles: &[(u16, [u8; 16])], nametable: &[(u16, u8)], oam: &[[u8; 4]]) -> Nes<NoInput> {
+    let mut data = vec![0; 0x220];
+    for &(addr, tile) in tiles {
+        let start = ((addr >> 4) & 0x100) as usize | (addr & 0xff) as usize;
+        data[start..start + 16].copy_from_slice(&tile);
+    }
+    data[0x200..0x204].copy_from_slice(&[0x0f, 0x30, 0x30, 0x30]);
+    data[0x210..0x214].copy_from_slice(&[0x0f, 0x16, 0x16, 0x16]);
+    let mut nes = common::synthetic(&[
+        (0x8000, &[
+            0xa9, 0x00,                    // lda #$00
+            0x8d, 0x00, 0x20,              // sta $2000
+            0x8d, 0x01, 0x20,              // sta $2001
+            0x8d, 0x06, 0x20,              // sta $2006
+            0x8d, 0x06, 0x20,              // sta $2006
+            0xa2, 0x00,                    // ldx #$00
+            0xbd, 0x00, 0x90,              // lda $9000,x
+            0x8d, 0x07, 0x20,              // sta $2007
+            0xe8,                          // inx
+            0xd0, 0xf7,                    // bne $8010
+            0xa9, 0x10,                    // lda #$10
+            0x8d, 0x06, 0x20,              // sta $2006
+            0xa9, 0x00,                    // lda #$00
+            0x8d, 0x06, 0x20,              // sta $2006
+            0xbd, 0x00, 0x91,              // lda $9100,x
+            0x8d, 0x07, 0x20,              // sta $2007
+            0xe8,                          // inx
+            0xd0, 0xf7,                    // bne $8023
+            0xa9, 0x20,                    // lda #$20
+            0x8d, 0x06, 0x20,              // sta $2006
+            0xa9, 0x00,                    // lda #$00
+            0x8d, 0x06, 0x20,              // sta $2006
+            0xbd, 0x00, 0x03,              // lda $0300,x
+            0x8d, 0x07, 0x20,              // sta $2007
+            0xe8,                          // inx
+            0xd0, 0xf7,                    // bne $8036
+            0xbd, 0x00, 0x04,              // lda $0400,x
+            0x8d, 0x07, 0x20,              // sta $2007
+            0xe8,                          // inx
+            0xd0, 0xf7,                    // bne $803f
+            0xbd, 0x00, 0x05,              // lda $0500,x
+            0x8d, 0x07, 0x20,              // sta $2007
+            0xe8,                          // inx
+            0xd0, 0xf7,                    // bne $8048
+            0xbd, 0x00, 0x06,              // lda $0600,x
+            0x8d, 0x07, 0x20,              // sta $2007
+            0xe8,                          // inx
+            0xd0, 0xf7,                    // bne $8051
+            0xa9, 0x3f,                    // lda #$3f
+            0x8d, 0x06, 0x20,              // sta $2006
+            0xa9, 0x00,                    // lda #$00
+            0x8d, 0x06, 0x20,              // sta $2006
+            0xbd, 0x00, 0x92,              // lda $9200,x
+            0x8d, 0x07, 0x20,              // sta $2007
+            0xe8,                          // inx
+            0xe0, 0x20,                    // cpx #$20
+            0xd0, 0xf5,                    // bne $8064
+            0xa9, 0x02,                    // lda #$02
+            0x8d, 0x14, 0x40,              // sta $4014
+            0xa5, 0xf0,                    // lda $f0
+            0x8d, 0x00, 0x20,              // sta $2000
+            0xa5, 0xf1,                    // lda $f1
+            0x8d, 0x01, 0x20,              // sta $2001
+            0xa9, 0x00,                    // lda #$00
+            0x8d, 0x05, 0x20,              // sta $2005
+            0x8d, 0x05, 0x20,              // sta $2005
+            0xad, 0x02, 0x20,              // lda $2002
+            0x85, 0x00,                    // sta $00
+            0x4c, 0x86, 0x80,              // jmp $8086
+        ]),
+        (0x9000, &data),
+    ]);
+    for addr in 0x0200..0x0700 {
+        nes.poke(addr, if addr < 0x0300 { 0xff } else { 0x00 });
+    }
+    for &(addr, tile) in nametable {
+        nes.poke(0x0300 + (addr & 0x3ff), tile);
+    }
+    for (i, sprite) in oam.iter().enumerate() {
+        for (j, &value) in sprite.iter().enumerate() {
+            nes.poke(0x0200 + (i * 4 + j) as u16, value);
+        }
+    }
+    nes.poke(0x00f0, ctrl);
+    nes.poke(0x00f1, mask);
+    nes.run();
+    nes
+}
+
+const SOLID: [u8; 16] = [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0, 0, 0, 0, 0, 0, 0, 0];
+// only the leftmost column
+const LEFT: [u8; 16] = [0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0, 0, 0, 0, 0, 0, 0, 0];
+
+// the line sprite 0 hit happens on, by checking $2002 at the start of each line
+fn sprite_zero_hit(nes: &mut Nes<NoInput>) -> Option<usize> {
+    (0..239).find(|&line| {
+        nes.run_to_scanline(line + 1);
+        (nes.peek(0x0000) & 0x40) != 0
+    })
+}
+
+// sprite 0 at (x, 23) over solid background tiles at columns 0, 2 and 31
+// of row 3
+fn hit(mask: u8, tile: u8, attr: u8, x: u8) -> Option<usize> {
+    let nametable = [(0x60, 1), (0x62, 1), (0x7f, 1)];
+    let mut nes = scene(0x10, mask, &[(0x1010, SOLID), (0x0010, SOLID), (0x0020, LEFT)], &nametable, &[[23, tile, attr, x]]);
+    sprite_zero_hit(&mut nes)
+}
+
+#[test]
+fn sprite_zero_hit_on_overlap() {
+    assert_eq!(hit(0x18, 1, 0, 16), Some(24));
+    assert_eq!(hit(0x18, 1, 0, 12), Some(24));
+    // not without the background
+    assert_eq!(hit(0x18, 1, 0, 40), None);
+    assert_eq!(hit(0x10, 1, 0, 16), None);
+
+    // cleared on the pre-render line
+    let mut nes = scene(0x10, 0x18, &[(0x1010, SOLID), (0x0010, SOLID)], &[(0x62, 1)], &[[23, 1, 0, 16]]);
+    nes.run();
+    nes.run_to_scanline(20);
+    assert_eq!(nes.peek(0x0000) & 0x40, 0);
+    assert_eq!(sprite_zero_hit(&mut nes), Some(24));
+}
+
+#[test]
+fn sprite_zero_hit_transparent_pixels() {
+    // the opaque column is at x=9, the background starts at x=16
+    assert_eq!(hit(0x18, 2, 0, 9), None);
+    // until it's flipped to x=16
+    assert_eq!(hit(0x18, 2, 0x40, 9), Some(24));
+}
+
+#[test]
+fn sprite_zero_hit_x255() {
+    assert_eq!(hit(0x18, 1, 0, 255), None);
+    assert_eq!(hit(0x18, 1, 0, 254), Some(24));
+}
+
+#[test]
+fn sprite_zero_hit_left_column() {
+    // only overlaps at x=4-7
+    assert_eq!(hit(0x1e, 1, 0, 4), Some(24));
+    assert_eq!(hit(0x18, 1, 0, 4), None);
+    assert_eq!(hit(0x1a, 1, 0, 4), None);
+    assert_eq!(hit(0x1c, 1, 0, 4), None);
+}
+
+#[test]
+fn pattern_tables() {
+    let tiles = [(0x0010, SOLID), (0x1010, LEFT)];
+    let oam = [[23, 1, 0, 40]];
+
+    // background from $1000, sprites from $0000
+    let mut nes = scene(0x10, 0x1e, &tiles, &[(0x62, 1)], &oam);
+    nes.run();
+    assert_eq!(pixel(&mut nes, 16, 24), WHITE);
+    assert_eq!(pixel(&mut nes, 17, 24), BLACK);
+    assert_eq!(pixel(&mut nes, 41, 24), RED);
+
+    // and the other way around
+    let mut nes = scene(0x08, 0x1e, &tiles, &[(0x62, 1)], &oam);
+    nes.run();
+    assert_eq!(pixel(&mut nes, 17, 24), WHITE);
+    assert_eq!(pixel(&mut nes, 40, 24), RED);
+    assert_eq!(pixel(&mut nes, 41, 24), BLACK);
+}
+
+#[test]
+fn tall_sprites() {
+    // tile 3 is the top half at $1020 and the bottom half at $1030,
+    // the sprite table bit is ignored
+    let tiles = [(0x1020, SOLID), (0x1030, LEFT)];
+    for ctrl in [0x20, 0x28] {
+        let mut nes = scene(ctrl, 0x1e, &tiles, &[], &[[23, 3, 0, 40]]);
+        nes.run();
+        assert_eq!(pixel(&mut nes, 41, 24), RED);
+        assert_eq!(pixel(&mut nes, 41, 31), RED);
+        assert_eq!(pixel(&mut nes, 40, 32), RED);
+        assert_eq!(pixel(&mut nes, 41, 32), BLACK);
+        assert_eq!(pixel(&mut nes, 40, 39), RED);
+        assert_eq!(pixel(&mut nes, 40, 40), BLACK);
+    }
+
+    // flipping vertically swaps the halves
+    let mut nes = scene(0x20, 0x1e, &tiles, &[], &[[23, 3, 0x80, 40]]);
+    nes.run();
+    assert_eq!(pixel(&mut nes, 40, 24), RED);
+    assert_eq!(pixel(&mut nes, 41, 24), BLACK);
+    assert_eq!(pixel(&mut nes, 41, 32), RED);
+    assert_eq!(pixel(&mut nes, 41, 39), RED);
+}
+
+// sprite 0 over the first column, and the background in the first column
+// of rows 3 and 5
+fn left_column(mask: u8) -> Nes<NoInput> {
+    let mut nes = scene(0x10, mask, &[(0x1010, SOLID), (0x0010, SOLID)], &[(0x60, 1), (0xa0, 1)], &[[23, 1, 0, 0]]);
+    nes.run();
+    nes
+}
+
+#[test]
+fn left_column_clipping() {
+    let mut nes = left_column(0x1e);
+    assert_eq!(pixel(&mut nes, 2, 24), RED);
+    assert_eq!(pixel(&mut nes, 2, 40), WHITE);
+
+    let mut nes = left_column(0x1a);
+    assert_eq!(pixel(&mut nes, 2, 24), WHITE);
+    assert_eq!(pixel(&mut nes, 2, 40), WHITE);
+
+    let mut nes = left_column(0x1c);
+    assert_eq!(pixel(&mut nes, 2, 24), RED);
+    assert_eq!(pixel(&mut nes, 2, 40), BLACK);
+
+    let mut nes = left_column(0x18);
+    assert_eq!(pixel(&mut nes, 2, 24), BLACK);
+    assert_eq!(pixel(&mut nes, 7, 40), BLACK);
+    assert_eq!(pixel(&mut nes, 8, 40), BLACK);
+}
+
+#[test]
+fn greyscale_and_emphasis() {
+    let mut nes = left_column(0x1f);
+    assert_eq!(pixel(&mut nes, 2, 24), 0xffadadad);
+    assert_eq!(pixel(&mut nes, 2, 40), WHITE);
+
+    // red emphasis darkens green and blue, but not black
+    let mut nes = left_column(0x3e);
+    assert_eq!(pixel(&mut nes, 2, 40), 0xffffbdbe);
+    assert_eq!(pixel(&mut nes, 20, 40), BLACK);
+}
+
+#[test]
+fn indexed_output() {
+    // red emphasis on white, and the black backdrop
+    let mut nes = left_column(0x3e);
+    assert_eq!(nes.indexed_framebuffer()[(40 << 8) | 2], 0x070);
+    assert_eq!(nes.indexed_framebuffer()[(40 << 8) | 20], 0x04f);
+
+    // the colours are converted from the indices
+    let indexed = nes.indexed_framebuffer().to_vec();
+    let mut colours = vec![0; indexed.len()];
+    Palette::default().convert(&indexed, &mut colours);
+    let fb: Vec<u8> = colours.iter().flat_map(|c| c.to_ne_bytes()).collect();
+    assert_eq!(nes.framebuffer(), &fb[..]);
+}
+
+// counts nmis in $10, with a routine at $8100 that turns them on
+fn nmi_counter() -> Nes<NoInput> {
+    let mut nes = common::synthetic(&[
+        (0x8000, &[
+            0x4c, 0x00, 0x80,             // jmp $8000
+        ]),
+        (0x8100, &[
+            0xa9, 0x80, 0x8d, 0x00, 0x20, // lda #$80, sta $2000
+            0x4c, 0x05, 0x81,             // jmp $8105
+        ]),
+        (0x8200, &[
+            0xe6, 0x10,                   // inc $10
+            0x40,                         // rti
+        ]),
+        (0xfffa, &[0x00, 0x82]),
+    ]);
+    nes.poke(0x0010, 0);
+    nes.run();
+    nes
+}
+
+#[test]
+fn vblank_flag() {
+    let mut nes = nmi_counter();
+    nes.run_to_scanline(240);
+    assert_eq!(nes.peek(0x2002) & 0x80, 0);
+    nes.run_to_scanline(242);
+    assert_eq!(nes.peek(0x2002) & 0x80, 0x80);
+    nes.run_to_scanline(20);
+    assert_eq!(nes.peek(0x2002) & 0x80, 0);
+    assert_eq!(nes.peek(0x0010), 0);
+}
+
+#[test]
+fn nmi_at_vblank() {
+    let mut nes = nmi_counter();
+    nes.run_to_scanline(100);
+    nes.cpu_mut().pc = 0x8100;
+    nes.run_to_scanline(240);
+    assert_eq!(nes.peek(0x0010), 0);
+    nes.run_to_scanline(242);
+    assert_eq!(nes.peek(0x0010), 1);
+    nes.run();
+    nes.run_to_scanline(242);
+    assert_eq!(nes.peek(0x0010), 2);
+}
+
+#[test]
+fn nmi_enabled_during_vblank() {
+    let mut nes = nmi_counter();
+    nes.run_to_scanline(250);
+    nes.cpu_mut().pc = 0x8100;
+    nes.run_to_scanline(251);
+    assert_eq!(nes.peek(0x0010), 1);
+}
+
+#[test]
+fn oam_registers() {
+    let mut nes = common::synthetic(&[
+        (0x8000, &[
+            0xa9, 0x01, 0x8d, 0x03, 0x20, // lda #$01, sta $2003
+            0xa9, 0xff,                   // lda #$ff
+            0x8d, 0x04, 0x20,             // sta $2004
+            0x8d, 0x04, 0x20,             // sta $2004
+            0xa9, 0x01, 0x8d, 0x03, 0x20, // lda #$01, sta $2003
+            0xad, 0x04, 0x20, 0x85, 0x10, // lda $2004, sta $10
+            0xa9, 0x02, 0x8d, 0x03, 0x20, // lda #$02, sta $2003
+            0xad, 0x04, 0x20, 0x85, 0x11, // lda $2004, sta $11
+            0x4c, 0x21, 0x80,             // jmp $8021
+        ]),
+    ]);
+    nes.run();
+    assert_eq!(nes.peek(0x0010), 0xff);
+    // the attribute byte's unused bits read back as 0
+    assert_eq!(nes.peek(0x0011), 0xe3);
+}
+
+#[test]
+fn oamaddr_reset_while_rendering() {
+    let mut nes = common::synthetic(&[
+        (0x8000, &[
+            0xa9, 0x05, 0x8d, 0x03, 0x20, // lda #$05, sta $2003
+            0x4c, 0x05, 0x80,             // jmp $8005
+        ]),
+        (0x8100, &[
+            0xa9, 0x18, 0x8d, 0x01, 0x20, // lda #$18, sta $2001
+            0x4c, 0x05, 0x81,             // jmp $8105
+        ]),
+    ]);
+    nes.run();
+    nes.run_to_scanline(242);
+    assert_eq!(nes.peek(0x2003), 0x05);
+
+    nes.cpu_mut().pc = 0x8100;
+    nes.run();
+    nes.run_to_scanline(242);
+    assert_eq!(nes.peek(0x2003), 0x00);
+}
+
+#[test]
+fn oam_decay() {
+    let mut nes = common::synthetic(&[
+        (0x8000, &[
+            0xa9, 0x00, 0x8d, 0x03, 0x20, // lda #$00, sta $2003
+            0xa9, 0x42, 0x8d, 0x04, 0x20, // lda #$42, sta $2004
+            0x4c, 0x0a, 0x80,             // jmp $800a
+        ]),
+        (0x8100, &[
+            0xa9, 0x00, 0x8d, 0x03, 0x20, // lda #$00, sta $2003
+            0xad, 0x04, 0x20, 0x85, 0x10, // lda $2004, sta $10
+            0x4c, 0x0a, 0x81,             // jmp $810a
+        ]),
+    ]);
+    let read = |nes: &mut Nes<NoInput>| {
+        nes.cpu_mut().pc = 0x8100;
+        for _ in 0..4 {
+            nes.step();
+        }
+        nes.peek(0x0010)
+    };
+    for _ in 0..4 {
+        nes.step();
+    }
+    assert_eq!(read(&mut nes), 0x42);
+    // still there after being refreshed by the read
+    assert_eq!(read(&mut nes), 0x42);
+
+    // but not after a frame without rendering
+    nes.run();
+    assert_eq!(read(&mut nes), 0x00);
+}
+
+// lda, then sta $4014
+fn dma_cycles(lda: &[u8]) -> usize {
+    let mut code = lda.to_vec();
+    code.extend_from_slice(&[0x8d, 0x14, 0x40, 0x4c, 0x00, 0x90]);
+    let mut nes = common::synthetic(&[(0x8000, &code), (0x9000, &[0x4c, 0x00, 0x90])]);
+    nes.step();
+    let start = nes.cpu().cycles();
+    nes.step();
+    nes.cpu().cycles() - start
+}
+
+#[test]
+fn oam_dma_cycles() {
+    // the copy takes 513 cycles, plus one to line up with an even
+    // cycle, on top of the 4 for the sta
+    let mut cycles = [dma_cycles(&[0xa9, 0x02]), dma_cycles(&[0xa5, 0x02])];
+    cycles.sort();
+    assert_eq!(cycles, [4 + 513, 4 + 514]);
+}
+
+#[test]
+fn open_bus() {
+    let mut nes = common::synthetic(&[
+        (0x8000, &[
+            0xa9, 0xa5, 0x8d, 0x03, 0x20, // lda #$a5, sta $2003
+            0xad, 0x00, 0x20, 0x85, 0x10, // lda $2000, sta $10
+            0xad, 0x02, 0x20, 0x85, 0x11, // lda $2002, sta $11
+            0xad, 0x05, 0x20, 0x85, 0x12, // lda $2005, sta $12
+            0x4c, 0x14, 0x80,             // jmp $8014
+        ]),
+        (0x8100, &[
+            0xad, 0x00, 0x20, 0x85, 0x13, // lda $2000, sta $13
+            0x4c, 0x05, 0x81,             // jmp $8105
+        ]),
+    ]);
+    nes.run();
+    assert_eq!(nes.peek(0x0010), 0xa5);
+    // $2002 only drives the top 3 bits
+    assert_eq!(nes.peek(0x0011), 0x05);
+    assert_eq!(nes.peek(0x0012), 0x05);
+
+    let latch = |nes: &mut Nes<NoInput>, frames| {
+        for _ in 0..frames {
+            nes.run();
+        }
+        nes.cpu_mut().pc = 0x8100;
+        nes.step();
+        nes.step();
+        nes.peek(0x0013)
+    };
+    assert_eq!(latch(&mut nes, 10), 0x05);
+    // and fades after a while
+    assert_eq!(latch(&mut nes, 40), 0x00);
+}
+
+#[test]
+fn ppudata_reads() {
+    let mut nes = common::synthetic(&[
+        (0x8000, &[
+            0xa9, 0x2f, 0x8d, 0x06, 0x20,  // lda #$2f, sta $2006
+            0xa9, 0x01, 0x8d, 0x06, 0x20,  // lda #$01, sta $2006
+            0xa9, 0x77, 0x8d, 0x07, 0x20,  // lda #$77, sta $2007
+            0xa9, 0x3f, 0x8d, 0x06, 0x20,  // lda #$3f, sta $2006
+            0xa9, 0x01, 0x8d, 0x06, 0x20,  // lda #$01, sta $2006
+            0xa9, 0x30, 0x8d, 0x07, 0x20,  // lda #$30, sta $2007
+            0xa9, 0x3f, 0x8d, 0x06, 0x20,  // lda #$3f, sta $2006
+            0xa9, 0x10, 0x8d, 0x06, 0x20,  // lda #$10, sta $2006
+            0xa9, 0x16, 0x8d, 0x07, 0x20,  // lda #$16, sta $2007
+            0xa9, 0x30, 0x8d, 0x06, 0x20,  // lda #$30, sta $2006
+            0xa9, 0x01, 0x8d, 0x06, 0x20,  // lda #$01, sta $2006
+            0xa9, 0x12, 0x8d, 0x07, 0x20,  // lda #$12, sta $2007
+            0xa9, 0x3f, 0x8d, 0x06, 0x20,  // lda #$3f, sta $2006
+            0xa9, 0x01, 0x8d, 0x06, 0x20,  // lda #$01, sta $2006
+            0xad, 0x07, 0x20, 0x85, 0x10,  // lda $2007, sta $10
+            0xa9, 0x00, 0x8d, 0x06, 0x20,  // lda #$00, sta $2006
+            0xa9, 0x00, 0x8d, 0x06, 0x20,  // lda #$00, sta $2006
+            0xad, 0x07, 0x20, 0x85, 0x11,  // lda $2007, sta $11
+            0xa9, 0x3f, 0x8d, 0x06, 0x20,  // lda #$3f, sta $2006
+            0xa9, 0x00, 0x8d, 0x06, 0x20,  // lda #$00, sta $2006
+            0xad, 0x07, 0x20, 0x85, 0x12,  // lda $2007, sta $12
+            0xa9, 0x20, 0x8d, 0x06, 0x20,  // lda #$20, sta $2006
+            0xa9, 0x01, 0x8d, 0x06, 0x20,  // lda #$01, sta $2006
+            0xad, 0x07, 0x20, 0x85, 0x13,  // lda $2007, sta $13
+            0xad, 0x07, 0x20, 0x85, 0x14,  // lda $2007, sta $14
+            0x4c, 0x7d, 0x80,              // jmp $807d
+        ]),
+    ]);
+    nes.run();
+    // palette reads don't go through the buffer
+    assert_eq!(nes.peek(0x0010), 0x30);
+    // but fill it from the nametable underneath
+    assert_eq!(nes.peek(0x0011), 0x77);
+    // $3f10 is $3f00
+    assert_eq!(nes.peek(0x0012), 0x16);
+    // $3000 is $2000
+    assert_eq!(nes.peek(0x0013), 0x00);
+    assert_eq!(nes.peek(0x0014), 0x12);
+}
+
+#[test]
+fn debug_views() {
+    let mut nes = scene(0x00, 0x1e, &[(0x0010, SOLID), (0x0020, LEFT)], &[(0x0062, 1)], &[[49, 2, 0x40, 30]]);
+    nes.run();
+    let ppu = nes.ppu();
+    let palette = Palette::default();
+    let colour = |index: usize| 0xff000000 | palette.colours()[index];
+
+    // the tile at column 2, row 3, with the scroll outline along the top
+    let nametables = ppu.nametable_view(&palette);
+    assert_eq!((nametables.width, nametables.height), (512, 480));
+    assert_eq!(nametables.get(20, 28), colour(0x30));
+    assert_eq!(nametables.get(100, 100), colour(0x0f));
+    assert_eq!(nametables.get(100, 0), 0xff000000 | !palette.colours()[0x0f] & 0xffffff);
+    assert_eq!(nametables.get(300, 0), colour(0x0f));
+
+    // tile 1 in the first pattern table, with a background and a sprite palette
+    let patterns = ppu.pattern_table_view(0, 0, &palette);
+    assert_eq!((patterns.width, patterns.height), (128, 128));
+    assert_eq!(patterns.get(8, 0), colour(0x30));
+    assert_eq!(patterns.get(0, 0), colour(0x0f));
+    assert_eq!(ppu.pattern_table_view(0, 4, &palette).get(8, 0), colour(0x16));
+
+    // sprite 0 is flipped, so its column is on the right
+    let oam = ppu.oam_view(&palette);
+    assert_eq!((oam.width, oam.height), (64, 128));
+    assert_eq!(oam.get(7, 0), colour(0x16));
+    assert_eq!(oam.get(0, 0), colour(0x0f));
+    let sprite = ppu.oam_entries()[0];
+    assert_eq!((sprite.x, sprite.y, sprite.tile, sprite.palette), (30, 49, 2, 0));
+    assert!(sprite.flip_h && !sprite.flip_v && !sprite.behind);
+
+    let swatches = ppu.palette_view(&palette);
+    assert_eq!((swatches.width, swatches.height), (128, 16));
+    assert_eq!(swatches.get(12, 4), colour(0x30));
+    assert_eq!(swatches.get(12, 12), colour(0x16));
+
+    // a valid png header with the image's size
+    let png = swatches.to_png();
+    assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+    assert_eq!(&png[12..16], b"IHDR");
+    assert_eq!(png[16..24], [0, 0, 0, 128, 0, 0, 0, 16]);
+    assert_eq!(&png[png.len() - 8..png.len() - 4], b"IEND");
+}
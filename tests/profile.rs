@@ -0,0 +1,46 @@
+use nes::profile::{Kind, Profiler, Routine};
+
+mod common;
+
+#[test]
+fn subroutines() {
+    let mut nes = common::synthetic(&[
+        (0x8000, &[
+            0x20, 0x10, 0x80, // jsr $8010
+            0x4c, 0x00, 0x80, // jmp $8000
+        ]),
+        (0x8010, &[
+            0xea,             // nop
+            0x20, 0x20, 0x80, // jsr $8020
+            0x60,             // rts
+        ]),
+        (0x8020, &[
+            0x60,             // rts
+        ]),
+    ]);
+    nes.set_profiler(Some(Profiler::new()));
+    // three trips round the loop
+    for _ in 0..3 * 6 {
+        nes.step();
+    }
+
+    let profiler = nes.profiler().unwrap();
+    let stats = |addr| {
+        profiler.stats().into_iter()
+            .find(|(r, _)| *r == Routine { kind: Kind::Subroutine, addr })
+            .unwrap().1
+    };
+    let outer = stats(0x8010);
+    let inner = stats(0x8020);
+    assert_eq!(outer.calls, 3);
+    assert_eq!(inner.calls, 3);
+    // nop + jsr + rts, then rts
+    assert_eq!(outer.exclusive, 3 * (2 + 6 + 6));
+    assert_eq!(inner.exclusive, 3 * 6);
+    assert_eq!(outer.inclusive, outer.exclusive + inner.inclusive);
+
+    let mut folded = Vec::new();
+    profiler.write_folded(&mut folded).unwrap();
+    let folded = String::from_utf8(folded).unwrap();
+    assert!(folded.contains("root;$8010;$8020 18\n"), "{}", folded);
+}
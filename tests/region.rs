@@ -0,0 +1,82 @@
+mod common;
+
+use common::NoInput;
+use nes::{debug::StopReason, region::Region, Nes};
+
+// enables nmi and waits, the handler just returns
+fn rom(header: &[(usize, u8)]) -> Nes<NoInput> {
+    let mut game = vec![0; 0x10 + 0x4000];
+    game[..8].copy_from_slice(b"NES\x1a\x01\x00\x00\x00");
+    for &(i, value) in header {
+        game[i] = value;
+    }
+    game[0x10..0x10 + 8].copy_from_slice(&[
+        0xa9, 0x80, 0x8d, 0x00, 0x20, // lda #$80, sta $2000
+        0x4c, 0x05, 0x80,             // jmp $8005
+    ]);
+    game[0x10 + 0x0100] = 0x40;       // rti
+    game[0x10 + 0x3ffa..0x10 + 0x3ffe].copy_from_slice(&[0x00, 0x81, 0x00, 0x80]);
+    let mut nes = Nes::load_from_memory(&game).unwrap();
+    nes.connect(0, NoInput);
+    nes
+}
+
+#[test]
+fn region_from_header() {
+    assert_eq!(rom(&[]).region(), Region::Ntsc);
+    // ines 1.0 tv system
+    assert_eq!(rom(&[(9, 0x01)]).region(), Region::Pal);
+    // nes 2.0 timing
+    assert_eq!(rom(&[(7, 0x08), (12, 0x01)]).region(), Region::Pal);
+    assert_eq!(rom(&[(7, 0x08), (12, 0x02)]).region(), Region::Ntsc);
+    assert_eq!(rom(&[(7, 0x08), (12, 0x03)]).region(), Region::Dendy);
+
+    let mut nes = rom(&[(9, 0x01)]);
+    nes.set_region(Some(Region::Dendy));
+    assert_eq!(nes.region(), Region::Dendy);
+    nes.set_region(None);
+    assert_eq!(nes.region(), Region::Pal);
+}
+
+// cpu cycles in 10 frames with rendering off
+fn frame_cycles(region: Region) -> f64 {
+    let mut nes = rom(&[]);
+    nes.set_region(Some(region));
+    nes.run();
+    let start = nes.cpu().cycles();
+    for _ in 0..10 {
+        nes.run();
+    }
+    (nes.cpu().cycles() - start) as f64 / 10.0
+}
+
+#[test]
+fn frame_length() {
+    // within an instruction or so of the real length
+    let close = |actual: f64, expected: f64| (actual - expected).abs() < 1.0;
+    assert!(close(frame_cycles(Region::Ntsc), 341.0 * 262.0 / 3.0));
+    assert!(close(frame_cycles(Region::Pal), 341.0 * 312.0 * 5.0 / 16.0));
+    assert!(close(frame_cycles(Region::Dendy), 341.0 * 312.0 / 3.0));
+}
+
+fn nmi_scanline(region: Region) -> usize {
+    let mut nes = rom(&[]);
+    nes.set_region(Some(region));
+    nes.run();
+    assert_eq!(nes.run_to_nmi(), StopReason::Nmi);
+    nes.ppu().scanline()
+}
+
+#[test]
+fn vblank_line() {
+    assert_eq!(nmi_scanline(Region::Ntsc), 241);
+    assert_eq!(nmi_scanline(Region::Pal), 241);
+    assert_eq!(nmi_scanline(Region::Dendy), 291);
+}
+
+#[test]
+fn fps() {
+    assert!((Region::Ntsc.fps() - 60.0988).abs() < 0.001);
+    assert!((Region::Pal.fps() - 50.0070).abs() < 0.001);
+    assert!((Region::Dendy.fps() - 50.0070).abs() < 0.001);
+}
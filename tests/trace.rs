@@ -0,0 +1,92 @@
+use nes::{trace::{TraceFormat, Tracer}, Nes};
+
+mod common;
+
+use common::{Log, NoInput};
+
+fn program() -> Nes<NoInput> {
+    let mut nes = common::synthetic(&[
+        (0x8000, &[
+            0xa2, 0x02,       // ldx #$02
+            0xa0, 0x01,       // ldy #$01
+            0xe8,             // inx
+            0xa5, 0x10,       // lda $10
+            0xbd, 0x00, 0x03, // lda $0300,x
+            0xb1, 0x20,       // lda ($20),y
+            0xa7, 0x10,       // lax $10
+            0x6c, 0xff, 0x02, // jmp ($02ff)
+        ]),
+        (0x9000, &[
+            0x04, 0x10,       // nop $10
+            0x4c, 0x00, 0x80, // jmp $8000
+        ]),
+    ]);
+    nes.poke(0x0010, 0x55);
+    nes.poke(0x0020, 0x00);
+    nes.poke(0x0021, 0x04);
+    nes.poke(0x0303, 0x66);
+    nes.poke(0x0401, 0x77);
+    // the pointer's high byte comes from $0200, not $0300
+    nes.poke(0x02ff, 0x00);
+    nes.poke(0x0200, 0x90);
+    nes.poke(0x0300, 0x80);
+    nes
+}
+
+fn trace(nes: &mut Nes<NoInput>, tracer: Tracer, log: &Log, steps: usize) -> Vec<String> {
+    nes.set_tracer(Some(tracer));
+    for _ in 0..steps {
+        nes.step();
+    }
+    log.take().lines().map(str::to_string).collect()
+}
+
+#[test]
+fn nestest_format() {
+    let mut nes = program();
+    let log = Log::default();
+    let lines = trace(&mut nes, Tracer::new(Box::new(log.clone())), &log, 10);
+    assert_eq!(lines, [
+        "8000  A2 02     LDX #$02                        A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7",
+        "8002  A0 01     LDY #$01                        A:00 X:02 Y:00 P:24 SP:FD PPU:  0, 27 CYC:9",
+        "8004  E8        INX                             A:00 X:02 Y:01 P:24 SP:FD PPU:  0, 33 CYC:11",
+        "8005  A5 10     LDA $10 = 55                    A:00 X:03 Y:01 P:24 SP:FD PPU:  0, 39 CYC:13",
+        "8007  BD 00 03  LDA $0300,X @ 0303 = 66         A:55 X:03 Y:01 P:24 SP:FD PPU:  0, 48 CYC:16",
+        "800A  B1 20     LDA ($20),Y = 0400 @ 0401 = 77  A:66 X:03 Y:01 P:24 SP:FD PPU:  0, 60 CYC:20",
+        "800C  A7 10    *LAX $10 = 55                    A:77 X:03 Y:01 P:24 SP:FD PPU:  0, 75 CYC:25",
+        "800E  6C FF 02  JMP ($02FF) = 9000              A:55 X:55 Y:01 P:24 SP:FD PPU:  0, 84 CYC:28",
+        "9000  04 10    *NOP $10 = 55                    A:55 X:55 Y:01 P:24 SP:FD PPU:  0, 99 CYC:33",
+        "9002  4C 00 80  JMP $8000                       A:55 X:55 Y:01 P:24 SP:FD PPU:  0,108 CYC:36",
+    ]);
+}
+
+#[test]
+fn custom_format() {
+    let mut nes = program();
+    let log = Log::default();
+    let format = TraceFormat::Custom("{pc} [{bytes}] {disasm}; {a} {x} {y} {p} {flags} {sp} {scanline}:{dot} {cycle} {pc".to_string());
+    let tracer = Tracer::new(Box::new(log.clone())).format(format);
+    let lines = trace(&mut nes, tracer, &log, 4);
+    assert_eq!(lines, [
+        "8000 [A2 02   ] LDX #$02; 00 00 00 24 nv--dIzc FD 0:21 7 {pc",
+        "8002 [A0 01   ] LDY #$01; 00 02 00 24 nv--dIzc FD 0:27 9 {pc",
+        "8004 [E8      ] INX; 00 02 01 24 nv--dIzc FD 0:33 11 {pc",
+        "8005 [A5 10   ] LDA $10 = 55; 00 03 01 24 nv--dIzc FD 0:39 13 {pc",
+    ]);
+
+    // unknown placeholders are copied through
+    let mut nes = program();
+    let tracer = Tracer::new(Box::new(log.clone())).format(TraceFormat::Custom("{pc} {nope} {}{a}".to_string()));
+    assert_eq!(trace(&mut nes, tracer, &log, 1), ["8000 {nope} {}00"]);
+}
+
+#[test]
+fn range() {
+    let mut nes = program();
+    let log = Log::default();
+    let tracer = Tracer::new(Box::new(log.clone()))
+        .format(TraceFormat::Custom("{pc}".to_string()))
+        .range(0x8004..=0x8007);
+    // once around the loop and into the next
+    assert_eq!(trace(&mut nes, tracer, &log, 13), ["8004", "8005", "8007", "8004"]);
+}
@@ -0,0 +1,43 @@
+use nes::video::{Aspect, Overscan, Screen};
+
+const CROP: Overscan = Overscan { top: 8, bottom: 8, left: 8, right: 8 };
+
+fn close(a: f64, b: f64) -> bool {
+    (a - b).abs() < 1e-9
+}
+
+#[test]
+fn aspect_ratios() {
+    let screen = Screen::default();
+    assert!(close(screen.ratio().unwrap(), 4.0 / 3.0));
+    assert!(close(screen.aspect(Aspect::PixelAspect).ratio().unwrap(), 256.0 * 8.0 / 7.0 / 240.0));
+    assert!(close(screen.aspect(Aspect::Square).ratio().unwrap(), 256.0 / 240.0));
+    assert_eq!(screen.aspect(Aspect::Stretch).ratio(), None);
+
+    // cropping keeps the pixels the same shape
+    let cropped = screen.overscan(CROP);
+    assert_eq!((CROP.width(), CROP.height()), (240, 224));
+    assert!(close(cropped.ratio().unwrap(), 240.0 * 1.25 / 224.0));
+    assert!(close(cropped.aspect(Aspect::Square).ratio().unwrap(), 240.0 / 224.0));
+}
+
+#[test]
+fn viewport() {
+    // 4:3 fills a 4:3 window, and is pillarboxed in a wider one
+    assert_eq!(Screen::default().viewport(960, 720), (0, 0, 960, 720));
+    assert_eq!(Screen::default().viewport(1280, 720), (160, 0, 960, 720));
+    // and letterboxed in a taller one
+    assert_eq!(Screen::default().viewport(960, 1000), (0, 140, 960, 720));
+    assert_eq!(Screen::default().aspect(Aspect::Stretch).viewport(1280, 720), (0, 0, 1280, 720));
+
+    // whole multiples of the height
+    let screen = Screen::default().aspect(Aspect::Square).integer_scale(true);
+    assert_eq!(screen.viewport(1280, 800), (256, 40, 768, 720));
+    let screen = screen.overscan(CROP);
+    assert_eq!(screen.viewport(1280, 800), (280, 64, 720, 672));
+    // never smaller than 1x
+    assert_eq!(screen.viewport(100, 100), (0, 0, 100, 100));
+
+    let screen = Screen::default().aspect(Aspect::Stretch).integer_scale(true);
+    assert_eq!(screen.viewport(1000, 800), (116, 40, 768, 720));
+}